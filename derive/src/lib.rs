@@ -0,0 +1,435 @@
+//! Derive macros for the binarization traits of `bis`.
+//!
+//! Fields are read and written in declaration order. The container attribute
+//! `#[bis(error = "Type", options = "Type")]` sets the error type (`std::io::Error` by default, or
+//! its stand-in without `std`) and, when options are given, derives the custom traits taking them
+//! instead. Fields accept:
+//!
+//! * `asciiz` - a null terminated string.
+//! * `le`, `be` - an integer or float in the given byte order.
+//! * `magic = "i32"` - a `magic_enum!` type stored as its base type.
+//! * `compressed(element_size = 12, threshold = 1024, checksum = "signed")` - a `Vec` stored as
+//!   an element count followed by its elements, LZSS compressed once they take up at least
+//!   `threshold` bytes. Every element takes up `element_size` bytes (its size in memory unless
+//!   given), writing elements of any other size fails.
+//! * `since = 5`, `until = 7`, `condition = "expr"` - only present when the version is at least
+//!   `since` and below `until`, or when the expression holds. Conditions can refer to `options`
+//!   and, by reference, to the fields before them. Absent fields are read as their default,
+//!   `Option` fields as `None`.
+//! * `version` - an integer holding the version the rest of the input is read at.
+//!
+//! Versioned fields of structs with options are read at the version of the options, any reader
+//! will do. Without options they are read at the version of the reader, which has to implement
+//! `VersionedReader` the way `ContextReader` does. Nested structs are read through the same
+//! reader, so a version read from a header reaches them too:
+//!
+//! ```
+//! # use bis::{ContextReader, DebinarizationContext, Debinarizable};
+//! #[derive(Clone, Debug, PartialEq, bis::Debinarizable)]
+//! struct Header {
+//!     #[bis(since = 2)]
+//!     flags: u32,
+//! }
+//!
+//! #[derive(Clone, Debug, PartialEq, bis::Debinarizable)]
+//! struct File {
+//!     #[bis(version)]
+//!     version: u32,
+//!     header:  Header,
+//! }
+//!
+//! let data = [2, 0, 0, 0, 1, 0, 0, 0];
+//! let mut reader = ContextReader::new(&data[..], DebinarizationContext::new());
+//! assert_eq!(File::debinarize(&mut reader).unwrap().header, Header { flags: 1 });
+//! ```
+//!
+//! Writing has no reader to take the version from, versioned fields are written at the version of
+//! the options and cannot be written without them:
+//!
+//! ```compile_fail
+//! #[derive(Clone, bis::Binarizable)]
+//! struct Header {
+//!     #[bis(since = 2)]
+//!     flags: u32,
+//! }
+//! ```
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{quote, ToTokens};
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Expr, Fields, GenericArgument, Ident, LitInt, LitStr, PathArguments, Type};
+
+#[proc_macro_derive(Debinarizable, attributes(bis))]
+pub fn derive_debinarizable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input, debinarizable).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+#[proc_macro_derive(Binarizable, attributes(bis))]
+pub fn derive_binarizable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input, binarizable).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+#[derive(Default)]
+struct Container {
+    error:   Option<Type>,
+    options: Option<Type>,
+}
+
+enum Encoding {
+    Plain,
+    Asciiz,
+    Endian { big: bool },
+    Magic(Type),
+    Compressed { element: Box<Type>, element_size: Option<Expr>, threshold: Option<Expr>, checksum: Option<Ident> },
+}
+
+struct Field {
+    ident:     Ident,
+    ty:        Type,
+    encoding:  Encoding,
+    since:     Option<LitInt>,
+    until:     Option<LitInt>,
+    condition: Option<Expr>,
+    optional:  Option<Type>,
+    version:   bool,
+}
+
+fn expand(input: &DeriveInput, derive: fn(&DeriveInput, &Container, &[Field]) -> syn::Result<TokenStream2>) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(syn::Error::new_spanned(&input.ident, "bis can only be derived for structs with named fields"))
+        },
+        _ => return Err(syn::Error::new_spanned(&input.ident, "bis can only be derived for structs with named fields"))
+    };
+
+    let container = parse_container(&input.attrs)?;
+    let fields = fields.iter().map(parse_field).collect::<syn::Result<Vec<_>>>()?;
+    derive(input, &container, &fields)
+}
+
+fn parse_container(attrs: &[Attribute]) -> syn::Result<Container> {
+    let mut container = Container::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("bis")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("error") {
+                container.error = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+            } else if meta.path.is_ident("options") {
+                container.options = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+            } else {
+                return Err(meta.error("expected `error` or `options`"))
+            }
+            Ok(())
+        })?;
+    }
+    Ok(container)
+}
+
+fn parse_field(field: &syn::Field) -> syn::Result<Field> {
+    let ident = field.ident.clone().expect("named fields have identifiers");
+    let mut encoding = Encoding::Plain;
+    let mut since = None;
+    let mut until = None;
+    let mut condition = None;
+    let mut version = false;
+
+    for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("bis")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("asciiz") {
+                encoding = Encoding::Asciiz;
+            } else if meta.path.is_ident("le") {
+                encoding = Encoding::Endian { big: false };
+            } else if meta.path.is_ident("be") {
+                encoding = Encoding::Endian { big: true };
+            } else if meta.path.is_ident("magic") {
+                encoding = Encoding::Magic(meta.value()?.parse::<LitStr>()?.parse()?);
+            } else if meta.path.is_ident("since") {
+                since = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("until") {
+                until = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("version") {
+                version = true;
+            } else if meta.path.is_ident("condition") {
+                condition = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+            } else if meta.path.is_ident("compressed") {
+                let element = inner_type(&field.ty, "Vec")
+                    .ok_or_else(|| meta.error("compressed fields have to be a Vec"))?;
+                let (mut element_size, mut threshold, mut checksum) = (None, None, None);
+                if meta.input.peek(syn::token::Paren) {
+                    meta.parse_nested_meta(|inner| {
+                        if inner.path.is_ident("element_size") {
+                            element_size = Some(inner.value()?.parse()?);
+                        } else if inner.path.is_ident("threshold") {
+                            threshold = Some(inner.value()?.parse()?);
+                        } else if inner.path.is_ident("checksum") {
+                            let value = inner.value()?.parse::<LitStr>()?;
+                            checksum = match value.value().as_str() {
+                                "signed" => Some(Ident::new("Signed", value.span())),
+                                "unsigned" => Some(Ident::new("Unsigned", value.span())),
+                                _ => return Err(syn::Error::new_spanned(value, "expected \"signed\" or \"unsigned\""))
+                            };
+                        } else {
+                            return Err(inner.error("expected `element_size`, `threshold` or `checksum`"))
+                        }
+                        Ok(())
+                    })?;
+                }
+                encoding = Encoding::Compressed { element: Box::new(element), element_size, threshold, checksum };
+            } else {
+                return Err(meta.error("unknown bis field attribute"))
+            }
+            Ok(())
+        })?;
+    }
+
+    let conditional = since.is_some() || until.is_some() || condition.is_some();
+    if version && conditional {
+        return Err(syn::Error::new_spanned(&ident, "the version field cannot be conditional"))
+    }
+    let optional = inner_type(&field.ty, "Option").filter(|_| conditional);
+    Ok(Field { ident, ty: field.ty.clone(), encoding, since, until, condition, optional, version })
+}
+
+/// The type argument of `Outer<T>`.
+fn inner_type(ty: &Type, outer: &str) -> Option<Type> {
+    let Type::Path(path) = ty else { return None };
+    let segment = path.path.segments.last().filter(|segment| segment.ident == outer)?;
+    let PathArguments::AngleBracketed(arguments) = &segment.arguments else { return None };
+    match arguments.args.first() {
+        Some(GenericArgument::Type(inner)) if arguments.args.len() == 1 => Some(inner.clone()),
+        _ => None
+    }
+}
+
+/// Whether the field is present at the given version, with the preceding fields in scope by
+/// reference. Writing binds them by reference already, reading binds them by value.
+fn presence(field: &Field, previous: &[Field], by_value: bool, version: &TokenStream2) -> Option<TokenStream2> {
+    let mut checks = vec![];
+    match (&field.since, &field.until) {
+        (Some(since), Some(until)) => checks.push(quote!((#since..#until).contains(&#version))),
+        (Some(since), None) => checks.push(quote!(#version >= #since)),
+        (None, Some(until)) => checks.push(quote!(#version < #until)),
+        (None, None) => {}
+    }
+    if let Some(condition) = &field.condition {
+        let names = previous.iter().map(|field| &field.ident).filter(|_| by_value);
+        checks.push(quote!({
+            #( #[allow(unused_variables)] let #names = &#names; )*
+            #condition
+        }));
+    }
+    match checks.is_empty() {
+        true => None,
+        false => Some(quote!(#( (#checks) )&&*))
+    }
+}
+
+fn error_type(container: &Container) -> TokenStream2 {
+    match &container.error {
+        Some(error) => error.to_token_stream(),
+        None => quote!(::bis::core::io::stream::Error)
+    }
+}
+
+fn debinarizable(input: &DeriveInput, container: &Container, fields: &[Field]) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let error = error_type(container);
+    let (_, type_generics, where_clause) = input.generics.split_for_impl();
+    let params = &input.generics.params;
+
+    //Without options versions are kept by the reader, so that nested structs are read at the same
+    //version. With options they start out at the version of the options, like they are written.
+    let versioned = fields.iter().any(|field| field.since.is_some() || field.until.is_some() || field.version);
+    let reader_bounds = match versioned && container.options.is_none() {
+        true => quote!(::bis::core::io::stream::Read + ::bis::VersionedReader),
+        false => quote!(::bis::core::io::stream::Read)
+    };
+    let version = match &container.options {
+        None => quote!(::bis::VersionedReader::version(&*reader)),
+        Some(_) => quote!(__version)
+    };
+    let start = match (&container.options, fields.iter().any(|field| field.version)) {
+        (None, _) => quote!(),
+        (Some(_), true) => quote!(let mut __version = ::bis::VersionedOptions::version(&options);),
+        (Some(_), false) if versioned => quote!(let __version = ::bis::VersionedOptions::version(&options);),
+        (Some(_), false) => quote!()
+    };
+
+    let reads = fields.iter().enumerate().map(|(index, field)| {
+        let ident = &field.ident;
+        let ty = field.optional.as_ref().unwrap_or(&field.ty);
+        let read = read_field(field, ty);
+        let read = match (presence(field, &fields[..index], true, &version), field.optional.is_some()) {
+            (None, _) => quote!(let #ident = #read;),
+            (Some(present), true) => quote!(let #ident = if #present { Some(#read) } else { None };),
+            (Some(present), false) => quote!(let #ident = if #present { #read } else { ::core::default::Default::default() };),
+        };
+        match (field.version, &container.options) {
+            (true, None) => quote! {
+                #read
+                ::bis::VersionedReader::set_version(&mut *reader, #ident as u32);
+            },
+            (true, Some(_)) => quote! {
+                #read
+                __version = #ident as u32;
+            },
+            (false, _) => read
+        }
+    });
+    let idents = fields.iter().map(|field| &field.ident);
+    let body = quote! {
+        #start
+        #( #reads )*
+        Ok(Self { #( #idents ),* })
+    };
+
+    Ok(match &container.options {
+        None => quote! {
+            impl<__R: #reader_bounds + ?Sized, #params> ::bis::Debinarizable<__R> for #name #type_generics #where_clause {
+                type Error = #error;
+
+                fn debinarize(reader: &mut __R) -> Result<Self, Self::Error> {
+                    #body
+                }
+            }
+        },
+        Some(options) => quote! {
+            impl<__R: #reader_bounds + ?Sized, #params> ::bis::CustomDebinarizable<__R, #options> for #name #type_generics #where_clause {
+                type Error = #error;
+
+                #[allow(unused_variables)]
+                fn debinarize_with_options(reader: &mut __R, options: #options) -> Result<Self, Self::Error> {
+                    #body
+                }
+            }
+        }
+    })
+}
+
+fn read_field(field: &Field, ty: &Type) -> TokenStream2 {
+    match &field.encoding {
+        Encoding::Plain => quote!(<#ty as ::bis::Debinarizable<__R>>::debinarize(reader)?),
+        Encoding::Asciiz => quote!(::bis::read_asciiz(reader)?),
+        Encoding::Endian { big } => {
+            let from_bytes = Ident::new(if *big { "from_be_bytes" } else { "from_le_bytes" }, Span::call_site());
+            quote!({
+                let mut bytes = [0u8; ::core::mem::size_of::<#ty>()];
+                ::bis::core::io::stream::Read::read_exact(reader, &mut bytes)?;
+                <#ty>::#from_bytes(bytes)
+            })
+        },
+        Encoding::Magic(base) => quote!({
+            let value = <#base as ::bis::Debinarizable<__R>>::debinarize(reader)?;
+            <#ty as ::core::convert::TryFrom<#base>>::try_from(value)
+                .map_err(|_| ::bis::invalid_magic(stringify!(#ty), value))?
+        }),
+        Encoding::Compressed { element, element_size, threshold, checksum } => {
+            let element_size = element_size.as_ref().map_or_else(|| quote!(::core::mem::size_of::<#element>()), ToTokens::to_token_stream);
+            let threshold = threshold.as_ref().map_or_else(|| quote!(::bis::COMPRESSED_BLOCK_THRESHOLD), ToTokens::to_token_stream);
+            let checksum = checksum_tokens(checksum);
+            quote!({
+                let count = <u32 as ::bis::Debinarizable<__R>>::debinarize(reader)? as usize;
+                let data = ::bis::read_compressed_block(reader, count, #element_size, #threshold, #checksum)?;
+                let mut items = ::bis::__private::Vec::with_capacity(count.min(data.len()));
+                let mut cursor = ::bis::core::io::stream::Cursor::new(data);
+                for _ in 0..count {
+                    items.push(<#element as ::bis::Debinarizable<::bis::core::io::stream::Cursor<::bis::__private::Vec<u8>>>>::debinarize(&mut cursor)?);
+                }
+                items
+            })
+        }
+    }
+}
+
+fn binarizable(input: &DeriveInput, container: &Container, fields: &[Field]) -> syn::Result<TokenStream2> {
+    if container.options.is_none() {
+        if let Some(field) = fields.iter().find(|field| field.since.is_some() || field.until.is_some()) {
+            return Err(syn::Error::new_spanned(&field.ident, "versioned fields need #[bis(options = \"...\")] on the struct to be written"))
+        }
+    }
+
+    let name = &input.ident;
+    let error = error_type(container);
+    let (_, type_generics, where_clause) = input.generics.split_for_impl();
+    let params = &input.generics.params;
+
+    let idents = fields.iter().map(|field| &field.ident);
+    let writes = fields.iter().enumerate().map(|(index, field)| {
+        let ident = &field.ident;
+        let ty = field.optional.as_ref().unwrap_or(&field.ty);
+        let write = write_field(field, ty, quote!(#ident));
+        let write = match field.optional.is_some() {
+            true => quote! {
+                match #ident {
+                    Some(#ident) => { #write }
+                    None => return Err(::bis::missing_field(stringify!(#ident)).into())
+                }
+            },
+            false => write
+        };
+        match presence(field, &fields[..index], false, &quote!(::bis::VersionedOptions::version(&options))) {
+            None => write,
+            Some(present) => quote!(if #present { #write })
+        }
+    });
+    let body = quote! {
+        let Self { #( #idents ),* } = self;
+        #( #writes )*
+        Ok(())
+    };
+
+    Ok(match &container.options {
+        None => quote! {
+            impl<__W: ::bis::core::io::stream::Write + ?Sized, #params> ::bis::Binarizable<__W> for #name #type_generics #where_clause {
+                type Error = #error;
+
+                fn binarize(&self, writer: &mut __W) -> Result<(), Self::Error> {
+                    #body
+                }
+            }
+        },
+        Some(options) => quote! {
+            impl<__W: ::bis::core::io::stream::Write + ?Sized, #params> ::bis::CustomBinarizable<__W, #options> for #name #type_generics #where_clause {
+                type Error = #error;
+
+                #[allow(unused_variables)]
+                fn binarize_with_options(&self, writer: &mut __W, options: #options) -> Result<(), Self::Error> {
+                    #body
+                }
+            }
+        }
+    })
+}
+
+/// Writes the field bound by reference to `value`.
+fn write_field(field: &Field, ty: &Type, value: TokenStream2) -> TokenStream2 {
+    match &field.encoding {
+        Encoding::Plain => quote!(<#ty as ::bis::Binarizable<__W>>::binarize(#value, writer)?;),
+        Encoding::Asciiz => quote!(::bis::write_asciiz(writer, #value)?;),
+        Encoding::Endian { big } => {
+            let to_bytes = Ident::new(if *big { "to_be_bytes" } else { "to_le_bytes" }, Span::call_site());
+            quote!(::bis::core::io::stream::Write::write_all(writer, &#value.#to_bytes())?;)
+        },
+        Encoding::Magic(base) => quote!(<#base as ::bis::Binarizable<__W>>::binarize(&<#base as ::core::convert::From<#ty>>::from(*#value), writer)?;),
+        Encoding::Compressed { element, element_size, threshold, checksum } => {
+            let element_size = element_size.as_ref().map_or_else(|| quote!(::core::mem::size_of::<#element>()), ToTokens::to_token_stream);
+            let threshold = threshold.as_ref().map_or_else(|| quote!(::bis::COMPRESSED_BLOCK_THRESHOLD), ToTokens::to_token_stream);
+            let checksum = checksum_tokens(checksum);
+            quote!({
+                let mut data = ::bis::__private::Vec::new();
+                for item in #value.iter() {
+                    <#element as ::bis::Binarizable<::bis::__private::Vec<u8>>>::binarize(item, &mut data)?;
+                }
+                ::bis::write_compressed_block(writer, #value.len(), #element_size, &data, #threshold, #checksum)?;
+            })
+        }
+    }
+}
+
+fn checksum_tokens(checksum: &Option<Ident>) -> TokenStream2 {
+    match checksum {
+        Some(variant) => quote!(Some(::bis::LzssChecksum::#variant)),
+        None => quote!(None)
+    }
+}
@@ -0,0 +1,236 @@
+use std::io::{self, Cursor};
+use bis::*;
+
+#[derive(Debug)]
+pub enum KindError {
+    Unknown(i32)
+}
+
+magic_enum! {
+    i32, Kind, KindError, Unknown {
+        Texture = 1,
+        Model = 0x4d4c4450
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Options {
+    version: u32
+}
+
+impl DebinarizationOptions for Options {}
+
+impl BinarizationOptions for Options {}
+
+impl VersionedOptions for Options {
+    fn version(&self) -> u32 { self.version }
+}
+
+#[derive(Debug, Clone, PartialEq, Debinarizable, Binarizable)]
+struct Point {
+    x: u16,
+    #[bis(be)]
+    y: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Debinarizable, Binarizable)]
+struct Encoded {
+    #[bis(asciiz)]
+    name:   String,
+    #[bis(le)]
+    little: u32,
+    #[bis(be)]
+    big:    u32,
+    #[bis(be)]
+    scale:  f32,
+    #[bis(magic = "i32")]
+    kind:   Kind,
+    flags:  u8,
+    #[bis(condition = "*flags & 1 != 0")]
+    parent: Option<i16>,
+    #[bis(condition = "*flags & 2 != 0")]
+    weight: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Debinarizable, Binarizable)]
+struct Arrays {
+    #[bis(compressed(element_size = 6))]
+    points: Vec<Point>,
+    #[bis(compressed(threshold = 4, checksum = "signed"))]
+    ids:    Vec<u32>,
+    #[bis(compressed(checksum = "unsigned"))]
+    small:  Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Binarizable)]
+struct MisSized {
+    #[bis(compressed(element_size = 4))]
+    points: Vec<Point>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Debinarizable, Binarizable)]
+#[bis(options = "Options")]
+struct Versioned {
+    always:  u16,
+    #[bis(since = 2)]
+    added:   Option<u32>,
+    #[bis(until = 3)]
+    removed: u8,
+    #[bis(since = 2, until = 4, condition = "options.version != 3")]
+    ranged:  Option<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Debinarizable)]
+#[bis(options = "Options")]
+struct OptionsFile {
+    #[bis(version)]
+    version: u8,
+    #[bis(since = 3)]
+    extra:   Option<u16>,
+}
+
+#[derive(Debug, Clone, PartialEq, Debinarizable)]
+struct Body {
+    #[bis(since = 3)]
+    extra: Option<u16>,
+    last:  u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Debinarizable)]
+struct File {
+    #[bis(version)]
+    version: u8,
+    body:    Body,
+}
+
+fn write<T: Binarizable<Vec<u8>, Error = io::Error>>(value: &T) -> Vec<u8> {
+    let mut data = vec![];
+    value.binarize(&mut data).unwrap();
+    data
+}
+
+fn encoded(flags: u8) -> Encoded {
+    Encoded {
+        name: "data\\a.paa".to_string(),
+        little: 0x01020304,
+        big: 0x01020304,
+        scale: 1.5,
+        kind: Kind::Model,
+        flags,
+        parent: (flags & 1 != 0).then_some(-3),
+        weight: if flags & 2 != 0 { 700 } else { 0 },
+    }
+}
+
+#[test]
+fn field_encodings() {
+    let data = write(&encoded(3));
+    let mut expected = b"data\\a.paa\0".to_vec();
+    expected.extend([4, 3, 2, 1, 1, 2, 3, 4]);
+    expected.extend(1.5f32.to_be_bytes());
+    expected.extend(0x4d4c4450i32.to_le_bytes());
+    expected.push(3);
+    expected.extend((-3i16).to_le_bytes());
+    expected.extend(700u16.to_le_bytes());
+    assert_eq!(data, expected);
+}
+
+#[test]
+fn conditions() {
+    for flags in 0..4 {
+        let value = encoded(flags);
+        let data = write(&value);
+        let read = Encoded::debinarize(&mut Cursor::new(&data)).unwrap();
+        assert_eq!(read, value);
+    }
+
+    let missing = Encoded { parent: None, ..encoded(1) };
+    assert!(missing.binarize(&mut vec![]).is_err());
+}
+
+#[test]
+fn unknown_magic() {
+    let mut data = write(&encoded(0));
+    let kind = data.len() - 5;
+    data[kind..kind + 4].copy_from_slice(&7i32.to_le_bytes());
+    let error = Encoded::debinarize(&mut Cursor::new(&data)).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn compressed_arrays() {
+    let arrays = Arrays {
+        points: (0..400).map(|i| Point { x: i % 7, y: 5 }).collect(),
+        ids: vec![1, 2, 3],
+        small: vec![9; 10],
+    };
+    let data = write(&arrays);
+    assert!(data.len() < 400 * 6);
+    assert_eq!(Arrays::debinarize(&mut Cursor::new(&data)).unwrap(), arrays);
+
+    let mut stored = 10u32.to_le_bytes().to_vec();
+    stored.extend([9; 10]);
+    assert!(data.ends_with(&stored), "arrays below the threshold are stored as is");
+}
+
+#[test]
+fn element_sizes_are_checked_on_write() {
+    assert_eq!(write(&MisSized { points: vec![] }), 0u32.to_le_bytes());
+
+    let mut data = vec![];
+    let error = MisSized { points: vec![Point { x: 1, y: 2 }] }.binarize(&mut data).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    assert!(data.is_empty());
+}
+
+#[test]
+fn versions() {
+    for version in 1..6 {
+        let options = Options { version };
+        let value = Versioned {
+            always: 7,
+            added: (version >= 2).then_some(0xdead),
+            removed: if version < 3 { 9 } else { 0 },
+            ranged: (version == 2).then_some(4),
+        };
+        let mut data = vec![];
+        value.binarize_with_options(&mut data, options).unwrap();
+        let expected_length = 2 + if version >= 2 { 4 } else { 0 } + if version < 3 { 1 } else { 0 } + if version == 2 { 1 } else { 0 };
+        assert_eq!(data.len(), expected_length, "version {version}");
+        let mut reader = ContextReader::new(Cursor::new(&data), DebinarizationContext::new().with_version(version));
+        assert_eq!(Versioned::debinarize_with_options(&mut reader, options).unwrap(), value);
+    }
+}
+
+#[test]
+fn options_versions_win_over_the_reader() {
+    let value = Versioned { always: 7, added: Some(0xdead), removed: 9, ranged: Some(4) };
+    let options = Options { version: 2 };
+    let mut data = vec![];
+    value.binarize_with_options(&mut data, options).unwrap();
+
+    let mut reader = ContextReader::new(Cursor::new(&data), DebinarizationContext::new().with_version(5));
+    assert_eq!(Versioned::debinarize_with_options(&mut reader, options).unwrap(), value);
+    assert_eq!(reader.version(), 5);
+    assert_eq!(Versioned::debinarize_with_options(&mut Cursor::new(&data), options).unwrap(), value);
+}
+
+#[test]
+fn version_fields_with_options() {
+    let options = Options { version: 1 };
+    let file = OptionsFile::debinarize_with_options(&mut Cursor::new([3, 0xff, 0]), options).unwrap();
+    assert_eq!(file, OptionsFile { version: 3, extra: Some(0xff) });
+    let file = OptionsFile::debinarize_with_options(&mut Cursor::new([2, 0xff, 0]), options).unwrap();
+    assert_eq!(file, OptionsFile { version: 2, extra: None });
+}
+
+#[test]
+fn version_field() {
+    let mut reader = ContextReader::new(Cursor::new([3, 0xff, 0, 1]), DebinarizationContext::new());
+    let file = File::debinarize(&mut reader).unwrap();
+    assert_eq!(file.body, Body { extra: Some(0xff), last: 1 });
+    assert_eq!(reader.version(), 3, "the version is kept for the rest of the input");
+
+    let mut reader = ContextReader::new(Cursor::new([2, 1]), DebinarizationContext::new().with_version(5));
+    assert_eq!(File::debinarize(&mut reader).unwrap().body, Body { extra: None, last: 1 });
+}
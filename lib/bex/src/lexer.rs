@@ -0,0 +1,90 @@
+use std::io;
+use crate::read::Analyser;
+
+/// Input held in memory, lexed into tokens.
+pub struct Lexer<T> {
+    contents: Vec<T>,
+    pos:      usize,
+}
+
+/// A token lexed differently depending on the scope it is in, the token may move to another scope.
+pub trait ScopedToken<T>: Sized {
+    type Scope;
+    type Error;
+
+    fn next_token(lexer: &mut Lexer<T>, scope: &mut Self::Scope) -> Result<Self, Self::Error>;
+}
+
+impl<T: Copy + PartialEq> Lexer<T> {
+    pub fn new(contents: Vec<T>) -> Self {
+        Self { contents, pos: 0 }
+    }
+
+    pub fn into_inner(self) -> Vec<T> { self.contents }
+
+    /// Lexes tokens from the default scope until the input runs out.
+    pub fn tokenize_until_end<K: ScopedToken<T>>(mut self) -> Result<Vec<K>, K::Error> where K::Scope: Default {
+        let mut scope = K::Scope::default();
+        let mut tokens = vec![];
+        while !self.is_end() {
+            tokens.push(K::next_token(&mut self, &mut scope)?);
+        }
+        Ok(tokens)
+    }
+}
+
+impl<T: Copy + PartialEq> Analyser<T> for Lexer<T> {
+    fn contents(&self) -> &[T] { &self.contents }
+
+    fn pos(&self) -> usize { self.pos }
+
+    fn set_pos(&mut self, pos: usize) -> io::Result<()> {
+        if pos > self.contents.len() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Cannot move past the end of the input."))
+        }
+        self.pos = pos;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Words split by spaces, counting them in the scope.
+    struct Word(Vec<u8>);
+
+    impl ScopedToken<u8> for Word {
+        type Scope = usize;
+        type Error = io::Error;
+
+        fn next_token(lexer: &mut Lexer<u8>, scope: &mut usize) -> io::Result<Self> {
+            *scope += 1;
+            let mut word = vec![lexer.get_not(b' ')?];
+            while !lexer.is_end() && *lexer.peek()? != b' ' {
+                word.push(lexer.get()?);
+            }
+            Ok(Word(word))
+        }
+    }
+
+    #[test]
+    fn tokenizes_until_the_end() {
+        let words = Lexer::new(b"ab c  def".to_vec()).tokenize_until_end::<Word>().unwrap();
+        let words: Vec<_> = words.into_iter().map(|word| word.0).collect();
+        assert_eq!(words, [b"ab".to_vec(), b"c".to_vec(), b"def".to_vec()]);
+    }
+
+    #[test]
+    fn moves_stay_within_the_input() {
+        let mut lexer = Lexer::new(b"a;b".to_vec());
+        assert!(lexer.step_back().is_err());
+        assert_eq!(lexer.get_until(b';').unwrap(), b"a");
+        assert!(lexer.take(&b'b').unwrap());
+        assert!(lexer.is_end());
+        assert!(lexer.get().is_err());
+        assert!(lexer.set_pos(4).is_err());
+        lexer.step_back().unwrap();
+        assert_eq!(lexer.get_not(b';').unwrap(), b'b');
+    }
+}
@@ -0,0 +1,9 @@
+//! A minimal in-tree stand-in for BexRs, holding the part of its lexing API `bis` uses: an
+//! analyser over input held in memory, tokens lexed in a scope and preprocessors.
+pub mod lexer;
+pub mod process;
+pub mod read;
+
+pub use lexer::*;
+pub use process::*;
+pub use read::*;
@@ -0,0 +1,8 @@
+use crate::lexer::Lexer;
+
+/// Turns input into the document it stands for, the way a preprocessor does.
+pub trait PreProcess<T> {
+    type E;
+
+    fn preprocess(&mut self, lexer: Lexer<T>) -> Result<Vec<T>, Self::E>;
+}
@@ -0,0 +1,73 @@
+use std::io;
+
+fn end_reached() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "The end of the input was reached.")
+}
+
+/// Walks over input held in memory one element at a time.
+pub trait Analyser<T: Copy + PartialEq> {
+    fn contents(&self) -> &[T];
+
+    fn pos(&self) -> usize;
+
+    /// Moves to a position, which may be the end of the input but not past it.
+    fn set_pos(&mut self, pos: usize) -> io::Result<()>;
+
+    fn is_end(&self) -> bool { self.pos() >= self.contents().len() }
+
+    fn peek(&self) -> io::Result<&T> {
+        self.contents().get(self.pos()).ok_or_else(end_reached)
+    }
+
+    fn get(&mut self) -> io::Result<T> {
+        let current = *self.peek()?;
+        self.set_pos(self.pos() + 1)?;
+        Ok(current)
+    }
+
+    /// Consumes the next element when it is the expected one.
+    fn take(&mut self, expected: &T) -> io::Result<bool> {
+        let found = self.peek()? == expected;
+        if found {
+            self.step_forward()?;
+        }
+        Ok(found)
+    }
+
+    fn step_forward(&mut self) -> io::Result<()> {
+        self.get().map(|_| ())
+    }
+
+    fn step_back(&mut self) -> io::Result<()> {
+        match self.pos() {
+            0 => Err(io::Error::new(io::ErrorKind::InvalidInput, "Cannot step back from the start of the input.")),
+            pos => self.set_pos(pos - 1)
+        }
+    }
+
+    /// The next element that is not `not`.
+    fn get_not(&mut self, not: T) -> io::Result<T> {
+        loop {
+            let current = self.get()?;
+            if current != not {
+                return Ok(current)
+            }
+        }
+    }
+
+    /// The elements up to `until`, which is consumed but not returned.
+    fn get_until(&mut self, until: T) -> io::Result<Vec<T>> {
+        let mut found = vec![];
+        loop {
+            match self.get()? {
+                current if current == until => return Ok(found),
+                current => found.push(current)
+            }
+        }
+    }
+
+    fn seek_until(&mut self, until: T) -> io::Result<()> {
+        while self.get()? != until {}
+        Ok(())
+    }
+}
@@ -1,7 +1,43 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Display;
+use crate::core::io::stream::{self as io, Read, Write};
+use crate::core::io::lzss::LzssChecksum;
+use crate::core::io::primitives::{ArrayCompression, BisReadExt, BisWriteExt};
+
+/// Arrays taking up at least this many bytes are stored compressed by default.
+pub const COMPRESSED_BLOCK_THRESHOLD: usize = 1024;
+
 pub trait DebinarizationOptions : Default {
 
 }
 
+pub trait BinarizationOptions : Default {
+
+}
+
+/// Options carrying the version of the format being written, used by versioned fields.
+pub trait VersionedOptions {
+    fn version(&self) -> u32;
+}
+
+/// Lets the helpers reading lists of items tell the input ending apart from input that is broken.
+pub trait DebinarizeError {
+    /// Whether the error was caused by the input ending before an item could be read.
+    fn is_eof(&self) -> bool;
+
+    /// Whether the item was read in full before it was rejected, so that reading can carry on with
+    /// the next one.
+    fn is_recoverable(&self) -> bool { false }
+}
+
+/// Readers knowing how far into their input they are. The helpers reading lists of items use it to
+/// tell input ending between two items apart from an item that was cut off.
+pub trait PositionedReader {
+    fn position(&self) -> u64;
+}
+
 pub enum DebinarizePredicateOption {
     Ok,
     Skip,
@@ -20,31 +56,107 @@ pub trait Debinarizable<R: ?Sized>: Sized + Clone {
         Ok(())
     }
 
-    fn debinarize_while(reader: &mut R, mut predicate: impl FnMut(&mut Self, &mut R) -> Result<DebinarizePredicateOption, Self::Error>) -> Result<Vec<Self>, Self::Error> {
+    /// Reads items until the predicate breaks or the input ends before the next item. Any other
+    /// error is returned, including the input ending part way through an item.
+    fn debinarize_while(reader: &mut R, mut predicate: impl FnMut(&mut Self, &mut R) -> Result<DebinarizePredicateOption, Self::Error>) -> Result<Vec<Self>, Self::Error>
+        where Self::Error: DebinarizeError, R: PositionedReader {
         let mut vec = Vec::new();
 
-        while let Ok(mut item) = Self::debinarize(reader) {
+        loop {
+            let start = reader.position();
+            let mut item = match Self::debinarize(reader) {
+                Ok(item) => item,
+                Err(error) if error.is_eof() && reader.position() == start => break,
+                Err(error) => return Err(error)
+            };
             match predicate(&mut item, reader)? {
                 DebinarizePredicateOption::Skip => { continue }
                 DebinarizePredicateOption::Break => { break }
-                _ => {vec.push(item.clone())}
+                _ => {vec.push(item)}
             }
         }
 
         Ok(vec)
     }
 
-    fn debinarize_until(reader: &mut R, mut predicate: impl FnMut(&Self) -> Result<bool, Self::Error>) -> Result<Vec<Self>, Self::Error> {
+    /// Reads items up to and including the first one the predicate accepts, or until the input
+    /// ends before the next item. Any other error is returned.
+    fn debinarize_until(reader: &mut R, mut predicate: impl FnMut(&Self) -> Result<bool, Self::Error>) -> Result<Vec<Self>, Self::Error>
+        where Self::Error: DebinarizeError, R: PositionedReader {
         let mut vec = Vec::new();
 
-        while let Ok(item) = Self::debinarize(reader) {
-            vec.push(item.clone());
+        loop {
+            let start = reader.position();
+            let item = match Self::debinarize(reader) {
+                Ok(item) => item,
+                Err(error) if error.is_eof() && reader.position() == start => break,
+                Err(error) => return Err(error)
+            };
+            let done = predicate(&item)?;
+            vec.push(item);
 
-            if predicate(&item)? { break; }
+            if done { break; }
         }
 
         Ok(vec)
     }
+
+    /// Like [`Debinarizable::debinarize_while`], but errors are collected alongside the items.
+    /// Reading carries on past recoverable errors and stops at the first one that is not.
+    fn debinarize_while_lenient(reader: &mut R, mut predicate: impl FnMut(&mut Self, &mut R) -> Result<DebinarizePredicateOption, Self::Error>) -> (Vec<Self>, Vec<Self::Error>)
+        where Self::Error: DebinarizeError, R: PositionedReader {
+        let mut vec = Vec::new();
+        let mut errors = Vec::new();
+
+        loop {
+            let start = reader.position();
+            let result = Self::debinarize(reader).and_then(|mut item| {
+                predicate(&mut item, reader).map(|option| (item, option))
+            });
+            match result {
+                Ok((_, DebinarizePredicateOption::Skip)) => { continue }
+                Ok((_, DebinarizePredicateOption::Break)) => { break }
+                Ok((item, _)) => vec.push(item),
+                Err(error) if error.is_eof() && reader.position() == start => break,
+                Err(error) => {
+                    let recoverable = error.is_recoverable();
+                    errors.push(error);
+                    if !recoverable { break }
+                }
+            }
+        }
+
+        (vec, errors)
+    }
+
+    /// Like [`Debinarizable::debinarize_until`], but errors are collected alongside the items.
+    /// Reading carries on past recoverable errors and stops at the first one that is not.
+    fn debinarize_until_lenient(reader: &mut R, mut predicate: impl FnMut(&Self) -> Result<bool, Self::Error>) -> (Vec<Self>, Vec<Self::Error>)
+        where Self::Error: DebinarizeError, R: PositionedReader {
+        let mut vec = Vec::new();
+        let mut errors = Vec::new();
+
+        loop {
+            let start = reader.position();
+            let result = Self::debinarize(reader).and_then(|item| {
+                predicate(&item).map(|done| (item, done))
+            });
+            match result {
+                Ok((item, done)) => {
+                    vec.push(item);
+                    if done { break }
+                },
+                Err(error) if error.is_eof() && reader.position() == start => break,
+                Err(error) => {
+                    let recoverable = error.is_recoverable();
+                    errors.push(error);
+                    if !recoverable { break }
+                }
+            }
+        }
+
+        (vec, errors)
+    }
 }
 
 
@@ -61,4 +173,258 @@ pub trait CustomDebinarizable<
     }
 
 
-}
\ No newline at end of file
+}
+
+
+pub trait Binarizable<W: ?Sized> {
+    type Error;
+
+    fn binarize(&self, writer: &mut W) -> Result<(), Self::Error>;
+
+    fn binarize_all(writer: &mut W, slice: &[Self]) -> Result<(), Self::Error> where Self: Sized {
+        for elem in slice {
+            elem.binarize(writer)?;
+        }
+        Ok(())
+    }
+}
+
+
+pub trait CustomBinarizable<
+    W: ?Sized,
+    O: BinarizationOptions
+> {
+    type Error;
+
+    fn binarize_with_options(&self, writer: &mut W, options: O) -> Result<(), Self::Error>;
+
+    fn binarize(&self, writer: &mut W) -> Result<(), Self::Error> {
+        self.binarize_with_options(writer, O::default())
+    }
+
+    fn binarize_all_with_options(writer: &mut W, slice: &[Self], options: O) -> Result<(), Self::Error> where Self: Sized, O: Clone {
+        for elem in slice {
+            elem.binarize_with_options(writer, options.clone())?;
+        }
+        Ok(())
+    }
+}
+
+macro_rules! binarizable_primitive {
+    ($($typ: ty),*) => {$(
+        impl<R: Read + ?Sized> Debinarizable<R> for $typ {
+            type Error = io::Error;
+
+            fn debinarize(reader: &mut R) -> Result<Self, Self::Error> {
+                let mut bytes = [0u8; core::mem::size_of::<$typ>()];
+                reader.read_exact(&mut bytes)?;
+                Ok(<$typ>::from_le_bytes(bytes))
+            }
+        }
+
+        impl<W: Write + ?Sized> Binarizable<W> for $typ {
+            type Error = io::Error;
+
+            fn binarize(&self, writer: &mut W) -> Result<(), Self::Error> {
+                writer.write_all(&self.to_le_bytes())
+            }
+        }
+    )*}
+}
+
+binarizable_primitive!(u8, i8, u16, i16, u32, i32, u64, i64, f32, f64);
+
+impl<T: PositionedReader + ?Sized> PositionedReader for &mut T {
+    fn position(&self) -> u64 { (**self).position() }
+}
+
+impl<T> PositionedReader for io::Cursor<T> {
+    fn position(&self) -> u64 { io::Cursor::position(self) }
+}
+
+impl DebinarizeError for io::Error {
+    fn is_eof(&self) -> bool { self.kind() == io::ErrorKind::UnexpectedEof }
+}
+
+/// Reads a null terminated string, invalid characters are replaced.
+pub fn read_asciiz<R: Read + ?Sized>(reader: &mut R) -> io::Result<String> {
+    reader.read_asciiz()
+}
+
+pub fn write_asciiz<W: Write + ?Sized>(writer: &mut W, value: &str) -> io::Result<()> {
+    writer.write_asciiz(value)
+}
+
+/// Reads the data of `count` elements of `element_size` bytes each, which is LZSS compressed when
+/// it takes up at least `threshold` bytes. The count is not trusted, nothing is allocated up front.
+pub fn read_compressed_block<R: Read + ?Sized>(
+    reader: &mut R,
+    count: usize,
+    element_size: usize,
+    threshold: usize,
+    checksum: Option<LzssChecksum>
+) -> io::Result<Vec<u8>> {
+    let length = count.checked_mul(element_size)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("{count} elements of {element_size} bytes overflow.")))?;
+
+    reader.read_compressed_data(length, ArrayCompression::Lzss { threshold, checksum })
+}
+
+/// Writes an element count followed by the data of the elements, compressed when it takes up at
+/// least `threshold` bytes. The data has to hold exactly `count` elements of `element_size` bytes,
+/// anything else could not be read back.
+pub fn write_compressed_block<W: Write + ?Sized>(
+    writer: &mut W,
+    count: usize,
+    element_size: usize,
+    data: &[u8],
+    threshold: usize,
+    checksum: Option<LzssChecksum>
+) -> io::Result<()> {
+    if count.checked_mul(element_size) != Some(data.len()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{count} elements of {element_size} bytes were written as {} bytes.", data.len())
+        ))
+    }
+    let count = u32::try_from(count)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("{count} elements do not fit a u32 count.")))?;
+    writer.write_all(&count.to_le_bytes())?;
+    writer.write_compressed_data(data, ArrayCompression::Lzss { threshold, checksum })
+}
+
+/// The error for a value that does not belong to the enum it is read as.
+pub fn invalid_magic(name: &str, value: impl Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{value} is not a valid {name}."))
+}
+
+/// The error for an optional field that has to be written but was never set.
+pub fn missing_field(name: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("{name} is required by the options it is written with."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SKIPPED: u16 = 0xEEEE;
+    const FATAL: u16 = 0xFFFF;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Item(u16);
+
+    #[derive(Debug)]
+    enum ItemError {
+        Io(io::Error),
+        Skipped,
+        Fatal,
+    }
+
+    impl DebinarizeError for ItemError {
+        fn is_eof(&self) -> bool { matches!(self, ItemError::Io(error) if error.is_eof()) }
+
+        fn is_recoverable(&self) -> bool { matches!(self, ItemError::Skipped) }
+    }
+
+    impl<R: Read> Debinarizable<R> for Item {
+        type Error = ItemError;
+
+        fn debinarize(reader: &mut R) -> Result<Self, Self::Error> {
+            match u16::debinarize(reader).map_err(ItemError::Io)? {
+                SKIPPED => Err(ItemError::Skipped),
+                FATAL => Err(ItemError::Fatal),
+                value => Ok(Item(value))
+            }
+        }
+    }
+
+    fn input(values: &[u16], trailing: &[u8]) -> io::Cursor<Vec<u8>> {
+        let mut data: Vec<u8> = values.iter().flat_map(|value| value.to_le_bytes()).collect();
+        data.extend_from_slice(trailing);
+        io::Cursor::new(data)
+    }
+
+    fn items(values: &[u16]) -> Vec<Item> { values.iter().copied().map(Item).collect() }
+
+    fn read_while(mut reader: io::Cursor<Vec<u8>>) -> Result<Vec<Item>, ItemError> {
+        Item::debinarize_while(&mut reader, |_, _| Ok(DebinarizePredicateOption::Ok))
+    }
+
+    fn read_until(mut reader: io::Cursor<Vec<u8>>) -> Result<Vec<Item>, ItemError> {
+        Item::debinarize_until(&mut reader, |item| Ok(item.0 == 0))
+    }
+
+    fn read_while_lenient(mut reader: io::Cursor<Vec<u8>>) -> (Vec<Item>, Vec<ItemError>) {
+        Item::debinarize_while_lenient(&mut reader, |_, _| Ok(DebinarizePredicateOption::Ok))
+    }
+
+    fn read_until_lenient(mut reader: io::Cursor<Vec<u8>>) -> (Vec<Item>, Vec<ItemError>) {
+        Item::debinarize_until_lenient(&mut reader, |item| Ok(item.0 == 0))
+    }
+
+    #[test]
+    fn input_ending_between_items_stops_cleanly() {
+        assert_eq!(read_while(input(&[1, 2, 3], &[])).unwrap(), items(&[1, 2, 3]));
+        assert_eq!(read_until(input(&[1, 2, 3], &[])).unwrap(), items(&[1, 2, 3]));
+        assert_eq!(read_while(input(&[], &[])).unwrap(), items(&[]));
+
+        let (read, errors) = read_while_lenient(input(&[1, 2], &[]));
+        assert_eq!(read, items(&[1, 2]));
+        assert!(errors.is_empty());
+        let (read, errors) = read_until_lenient(input(&[1, 2], &[]));
+        assert_eq!(read, items(&[1, 2]));
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn predicates_end_the_list() {
+        assert_eq!(read_until(input(&[1, 0, 3], &[])).unwrap(), items(&[1, 0]));
+
+        let mut reader = input(&[1, 2, 3, 4], &[]);
+        let read = Item::debinarize_while(&mut reader, |item, _| Ok(match item.0 {
+            2 => DebinarizePredicateOption::Skip,
+            4 => DebinarizePredicateOption::Break,
+            _ => DebinarizePredicateOption::Ok
+        })).unwrap();
+        assert_eq!(read, items(&[1, 3]));
+    }
+
+    #[test]
+    fn truncated_items_are_errors() {
+        assert!(matches!(read_while(input(&[1, 2], &[3])), Err(ItemError::Io(error)) if error.is_eof()));
+        assert!(matches!(read_until(input(&[1, 2], &[3])), Err(ItemError::Io(error)) if error.is_eof()));
+
+        let (read, errors) = read_while_lenient(input(&[1, 2], &[3]));
+        assert_eq!(read, items(&[1, 2]));
+        assert!(matches!(errors.as_slice(), [ItemError::Io(error)] if error.is_eof()));
+        let (read, errors) = read_until_lenient(input(&[1, 2], &[3]));
+        assert_eq!(read, items(&[1, 2]));
+        assert!(matches!(errors.as_slice(), [ItemError::Io(error)] if error.is_eof()));
+    }
+
+    #[test]
+    fn other_errors_stop_reading() {
+        assert!(matches!(read_while(input(&[1, FATAL, 2], &[])), Err(ItemError::Fatal)));
+        assert!(matches!(read_until(input(&[1, FATAL, 2], &[])), Err(ItemError::Fatal)));
+
+        let (read, errors) = read_while_lenient(input(&[1, FATAL, 2], &[]));
+        assert_eq!(read, items(&[1]));
+        assert!(matches!(errors.as_slice(), [ItemError::Fatal]));
+        let (read, errors) = read_until_lenient(input(&[1, FATAL, 2], &[]));
+        assert_eq!(read, items(&[1]));
+        assert!(matches!(errors.as_slice(), [ItemError::Fatal]));
+    }
+
+    #[test]
+    fn recoverable_errors_are_skipped_only_when_lenient() {
+        assert!(matches!(read_while(input(&[1, SKIPPED, 2], &[])), Err(ItemError::Skipped)));
+        assert!(matches!(read_until(input(&[1, SKIPPED, 2], &[])), Err(ItemError::Skipped)));
+
+        let (read, errors) = read_while_lenient(input(&[1, SKIPPED, 2, SKIPPED], &[]));
+        assert_eq!(read, items(&[1, 2]));
+        assert!(matches!(errors.as_slice(), [ItemError::Skipped, ItemError::Skipped]));
+        let (read, errors) = read_until_lenient(input(&[1, SKIPPED, 0, 2], &[]));
+        assert_eq!(read, items(&[1, 0]));
+        assert!(matches!(errors.as_slice(), [ItemError::Skipped]));
+    }
+}
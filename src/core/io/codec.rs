@@ -0,0 +1,357 @@
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+#[cfg(feature = "std")]
+use std::io::{Read, Write};
+#[cfg(feature = "std")]
+use flate2::Compression;
+#[cfg(feature = "std")]
+use flate2::read::{DeflateDecoder, ZlibDecoder};
+#[cfg(feature = "std")]
+use flate2::write::{DeflateEncoder, ZlibEncoder};
+use crate::core::io::stream as io;
+use thiserror::Error;
+use crate::core::io::lzo::{self, LzoError};
+use crate::core::io::lzss::{self, LzssChecksum, LzssEncoder, LzssError, LzssLevel};
+
+/// The format name banks register their entry codec under.
+pub const BANK_CODEC_FORMAT: &str = "pbo";
+/// The format name textures register the codec of their compressed mipmaps under.
+pub const TEXTURE_CODEC_FORMAT: &str = "paa";
+
+#[derive(Error, Debug)]
+pub enum CodecError {
+    #[error("Codec Error: No codec is registered for version {1} of {0}.")]
+    NotRegistered(String, u32),
+    #[error("Codec Error: The data unpacked to {actual} bytes instead of {expected}.")]
+    LengthMismatch { expected: usize, actual: usize },
+    #[error(transparent)]
+    Lzss(#[from] LzssError),
+    #[error(transparent)]
+    Lzo(#[from] LzoError),
+    #[error(transparent)]
+    IO(#[from] io::Error),
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum CodecKind {
+    Stored,
+    Lzss,
+    LzssChecksum(LzssChecksum),
+    Lzo,
+    #[cfg(feature = "std")]
+    Zlib,
+    #[cfg(feature = "std")]
+    Deflate
+}
+
+/// Packs and unpacks blocks of data. Unpacking is always bounded by the length the format says
+/// the data unpacks to.
+pub trait Codec: Debug + Send + Sync {
+    fn kind(&self) -> CodecKind;
+
+    fn encode(&self, input: &[u8]) -> Result<Vec<u8>, CodecError>;
+
+    /// Decodes data that is expected to unpack to exactly `length` bytes.
+    fn decode(&self, input: &[u8], length: usize) -> Result<Vec<u8>, CodecError>;
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub struct StoredCodec;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub struct LzssCodec {
+    level:    LzssLevel,
+    checksum: Option<LzssChecksum>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub struct LzoCodec;
+
+/// Zlib streams, or raw deflate streams without the zlib header and trailer.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct ZlibCodec {
+    raw:   bool,
+    level: u32,
+}
+
+/// Maps formats and their versions to the codec their compressed data is stored with. A lookup
+/// resolves to the codec registered for the highest version not above the one requested, so a
+/// codec only has to be registered again for the version that changed it.
+#[derive(Debug, Clone)]
+pub struct CodecRegistry {
+    formats: BTreeMap<String, BTreeMap<u32, Arc<dyn Codec>>>
+}
+
+impl CodecKind {
+    /// The codec for this kind with default settings.
+    pub fn codec(self) -> Arc<dyn Codec> {
+        match self {
+            CodecKind::Stored => Arc::new(StoredCodec),
+            CodecKind::Lzss => Arc::new(LzssCodec::new()),
+            CodecKind::LzssChecksum(checksum) => Arc::new(LzssCodec::new().with_checksum(checksum)),
+            CodecKind::Lzo => Arc::new(LzoCodec),
+            #[cfg(feature = "std")]
+            CodecKind::Zlib => Arc::new(ZlibCodec::zlib()),
+            #[cfg(feature = "std")]
+            CodecKind::Deflate => Arc::new(ZlibCodec::deflate()),
+        }
+    }
+}
+
+impl Codec for StoredCodec {
+    fn kind(&self) -> CodecKind { CodecKind::Stored }
+
+    fn encode(&self, input: &[u8]) -> Result<Vec<u8>, CodecError> { Ok(input.to_vec()) }
+
+    fn decode(&self, input: &[u8], length: usize) -> Result<Vec<u8>, CodecError> {
+        match input.len() == length {
+            true => Ok(input.to_vec()),
+            false => Err(CodecError::LengthMismatch { expected: length, actual: input.len() })
+        }
+    }
+}
+
+impl LzssCodec {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn with_level(mut self, level: LzssLevel) -> Self {
+        self.level = level;
+        self
+    }
+
+    pub fn with_checksum(mut self, checksum: LzssChecksum) -> Self {
+        self.checksum = Some(checksum);
+        self
+    }
+}
+
+impl Codec for LzssCodec {
+    fn kind(&self) -> CodecKind {
+        match self.checksum {
+            None => CodecKind::Lzss,
+            Some(checksum) => CodecKind::LzssChecksum(checksum)
+        }
+    }
+
+    fn encode(&self, input: &[u8]) -> Result<Vec<u8>, CodecError> {
+        let mut output = LzssEncoder::new(self.level).encode(input);
+        if let Some(checksum) = self.checksum {
+            output.extend_from_slice(&checksum.calculate(input).to_le_bytes());
+        }
+        Ok(output)
+    }
+
+    fn decode(&self, input: &[u8], length: usize) -> Result<Vec<u8>, CodecError> {
+        Ok(match self.checksum {
+            None => lzss::decode(input, length)?,
+            Some(checksum) => lzss::decode_with_checksum(input, length, checksum)?
+        })
+    }
+}
+
+impl Codec for LzoCodec {
+    fn kind(&self) -> CodecKind { CodecKind::Lzo }
+
+    fn encode(&self, input: &[u8]) -> Result<Vec<u8>, CodecError> { Ok(lzo::encode(input)) }
+
+    fn decode(&self, input: &[u8], length: usize) -> Result<Vec<u8>, CodecError> {
+        Ok(lzo::decode(input, length)?)
+    }
+}
+
+#[cfg(feature = "std")]
+impl ZlibCodec {
+    pub fn zlib() -> Self { Self { raw: false, level: Compression::default().level() } }
+
+    pub fn deflate() -> Self { Self { raw: true, ..Self::zlib() } }
+
+    /// Sets the compression level, from 0 (stored) to 9 (best).
+    pub fn with_level(mut self, level: u32) -> Self {
+        self.level = level.min(9);
+        self
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for ZlibCodec {
+    fn default() -> Self { Self::zlib() }
+}
+
+#[cfg(feature = "std")]
+impl Codec for ZlibCodec {
+    fn kind(&self) -> CodecKind {
+        match self.raw {
+            true => CodecKind::Deflate,
+            false => CodecKind::Zlib
+        }
+    }
+
+    fn encode(&self, input: &[u8]) -> Result<Vec<u8>, CodecError> {
+        let level = Compression::new(self.level);
+        Ok(match self.raw {
+            true => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), level);
+                encoder.write_all(input)?;
+                encoder.finish()?
+            },
+            false => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), level);
+                encoder.write_all(input)?;
+                encoder.finish()?
+            }
+        })
+    }
+
+    fn decode(&self, input: &[u8], length: usize) -> Result<Vec<u8>, CodecError> {
+        let decoder: Box<dyn Read> = match self.raw {
+            true => Box::new(DeflateDecoder::new(input)),
+            false => Box::new(ZlibDecoder::new(input))
+        };
+
+        //One byte more than expected is read to detect data that unpacks to more than it should.
+        let mut output = Vec::with_capacity(length.min(input.len().saturating_mul(4)));
+        decoder.take(length as u64 + 1).read_to_end(&mut output)?;
+        match output.len() == length {
+            true => Ok(output),
+            false => Err(CodecError::LengthMismatch { expected: length, actual: output.len() })
+        }
+    }
+}
+
+impl CodecRegistry {
+    /// A registry without any codecs.
+    pub fn new() -> Self {
+        Self { formats: BTreeMap::new() }
+    }
+
+    pub fn register(&mut self, format: &str, version: u32, codec: Arc<dyn Codec>) {
+        self.formats.entry(format.to_lowercase()).or_default().insert(version, codec);
+    }
+
+    pub fn with(mut self, format: &str, version: u32, codec: Arc<dyn Codec>) -> Self {
+        self.register(format, version, codec);
+        self
+    }
+
+    pub fn get(&self, format: &str, version: u32) -> Result<&Arc<dyn Codec>, CodecError> {
+        self.formats.get(&format.to_lowercase())
+            .and_then(|versions| versions.range(..=version).next_back())
+            .map(|(_, codec)| codec)
+            .ok_or_else(|| CodecError::NotRegistered(format.to_string(), version))
+    }
+
+    pub fn decode(&self, format: &str, version: u32, input: &[u8], length: usize) -> Result<Vec<u8>, CodecError> {
+        self.get(format, version)?.decode(input, length)
+    }
+
+    pub fn encode(&self, format: &str, version: u32, input: &[u8]) -> Result<Vec<u8>, CodecError> {
+        self.get(format, version)?.encode(input)
+    }
+}
+
+impl Default for CodecRegistry {
+    /// The codecs of the formats read by this crate.
+    fn default() -> Self {
+        Self::new().with(BANK_CODEC_FORMAT, 0, CodecKind::Lzss.codec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "std")]
+    const KINDS: [CodecKind; 7] = [
+        CodecKind::Stored,
+        CodecKind::Lzss,
+        CodecKind::LzssChecksum(LzssChecksum::Signed),
+        CodecKind::LzssChecksum(LzssChecksum::Unsigned),
+        CodecKind::Lzo,
+        CodecKind::Zlib,
+        CodecKind::Deflate,
+    ];
+    #[cfg(not(feature = "std"))]
+    const KINDS: [CodecKind; 5] = [
+        CodecKind::Stored,
+        CodecKind::Lzss,
+        CodecKind::LzssChecksum(LzssChecksum::Signed),
+        CodecKind::LzssChecksum(LzssChecksum::Unsigned),
+        CodecKind::Lzo,
+    ];
+
+    fn text(length: usize) -> Vec<u8> {
+        b"class a { value = 1; };\n".iter().copied().cycle().take(length).collect()
+    }
+
+    #[test]
+    fn every_kind_round_trips() {
+        let input = text(3000);
+        for kind in KINDS {
+            let codec = kind.codec();
+            assert_eq!(codec.kind(), kind);
+            let encoded = codec.encode(&input).unwrap();
+            assert_eq!(codec.decode(&encoded, input.len()).unwrap(), input, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn lookups_resolve_to_the_nearest_lower_version() {
+        let registry = CodecRegistry::new()
+            .with("PAA", 2, CodecKind::Lzss.codec())
+            .with("paa", 5, CodecKind::Lzo.codec());
+
+        assert!(matches!(registry.get("paa", 1), Err(CodecError::NotRegistered(format, 1)) if format == "paa"));
+        assert_eq!(registry.get("paa", 2).unwrap().kind(), CodecKind::Lzss);
+        assert_eq!(registry.get("Paa", 4).unwrap().kind(), CodecKind::Lzss);
+        assert_eq!(registry.get("paa", 5).unwrap().kind(), CodecKind::Lzo);
+        assert_eq!(registry.get("paa", u32::MAX).unwrap().kind(), CodecKind::Lzo);
+    }
+
+    #[test]
+    fn unknown_formats_are_not_registered() {
+        let registry = CodecRegistry::default();
+        assert_eq!(registry.get(BANK_CODEC_FORMAT, 0).unwrap().kind(), CodecKind::Lzss);
+        assert!(matches!(registry.decode("wrp", 3, &[], 0), Err(CodecError::NotRegistered(format, 3)) if format == "wrp"));
+        assert!(matches!(registry.encode("wrp", 3, &[]), Err(CodecError::NotRegistered(..))));
+    }
+
+    #[test]
+    fn stored_data_has_to_match_its_length() {
+        assert!(matches!(
+            StoredCodec.decode(b"abc", 4),
+            Err(CodecError::LengthMismatch { expected: 4, actual: 3 })
+        ));
+    }
+
+    #[test]
+    fn checksums_are_verified() {
+        let codec = LzssCodec::new().with_checksum(LzssChecksum::Unsigned);
+        let mut encoded = codec.encode(b"checksummed").unwrap();
+        let last = encoded.len() - 1;
+        encoded[last] ^= 1;
+        assert!(matches!(codec.decode(&encoded, 11), Err(CodecError::Lzss(LzssError::ChecksumMismatch { .. }))));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn zlib_data_has_to_match_its_length() {
+        let input = text(1000);
+        for codec in [ZlibCodec::zlib(), ZlibCodec::deflate().with_level(20)] {
+            let encoded = codec.encode(&input).unwrap();
+            assert!(encoded.len() < input.len());
+            assert!(matches!(
+                codec.decode(&encoded, input.len() - 1),
+                Err(CodecError::LengthMismatch { expected: 999, actual: 1000 })
+            ));
+            assert!(matches!(
+                codec.decode(&encoded, input.len() + 1),
+                Err(CodecError::LengthMismatch { expected: 1001, actual: 1000 })
+            ));
+        }
+        assert!(ZlibCodec::zlib().decode(&[0xFF; 16], 16).is_err());
+    }
+}
@@ -0,0 +1,221 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use crate::core::io::stream::{self as io, Read, Seek, SeekFrom};
+use alloc::sync::Arc;
+use crate::core::io::binarizable::{DebinarizationOptions, PositionedReader, VersionedOptions};
+use crate::core::io::codec::{Codec, CodecError, CodecRegistry};
+use crate::core::profile::GameProfile;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// A problem noticed while reading that did not stop it.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// How far into the input the reader was when the problem was noticed.
+    pub offset:   u64,
+    pub message:  String,
+}
+
+/// Receives the diagnostics reported while reading.
+pub trait DiagnosticSink {
+    fn report(&mut self, diagnostic: Diagnostic);
+}
+
+/// Forwards diagnostics to the `log` crate.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub struct LogDiagnostics;
+
+/// Readers knowing the version of the format they read, derived versioned fields are read at it.
+pub trait VersionedReader {
+    fn version(&self) -> u32;
+
+    /// Changes the version the rest of the input is read at.
+    fn set_version(&mut self, version: u32);
+}
+
+/// State shared by everything read from one input. Readers update it as they learn more about
+/// the data, such as the version of a format that is only known once its header was read.
+#[derive(Clone)]
+pub struct DebinarizationContext<S: DiagnosticSink = Vec<Diagnostic>> {
+    version:     u32,
+    profile:     GameProfile,
+    codecs:      Arc<CodecRegistry>,
+    diagnostics: S,
+}
+
+/// Wraps a reader together with the context it is read in, so that nested debinarization can
+/// reach the context through the reader it is given.
+#[derive(Clone, Debug)]
+pub struct ContextReader<R: Read, S: DiagnosticSink = Vec<Diagnostic>> {
+    reader:   R,
+    position: u64,
+    context:  DebinarizationContext<S>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} at offset {}: {}", self.severity, self.offset, self.message)
+    }
+}
+
+impl DiagnosticSink for Vec<Diagnostic> {
+    fn report(&mut self, diagnostic: Diagnostic) { self.push(diagnostic) }
+}
+
+impl DiagnosticSink for LogDiagnostics {
+    fn report(&mut self, diagnostic: Diagnostic) {
+        match diagnostic.severity {
+            Severity::Info => log::info!("{diagnostic}"),
+            Severity::Warning => log::warn!("{diagnostic}"),
+            Severity::Error => log::error!("{diagnostic}"),
+        }
+    }
+}
+
+impl<S: DiagnosticSink + Default> Default for DebinarizationContext<S> {
+    fn default() -> Self {
+        Self::with_sink(S::default())
+    }
+}
+
+impl DebinarizationContext {
+    /// A context collecting its diagnostics.
+    pub fn new() -> Self { Self::default() }
+}
+
+impl<S: DiagnosticSink> DebinarizationContext<S> {
+    pub fn with_sink(diagnostics: S) -> Self {
+        Self { version: 0, profile: GameProfile::default(), codecs: Arc::new(CodecRegistry::default()), diagnostics }
+    }
+
+    pub fn with_version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn with_profile(mut self, profile: GameProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    pub fn with_codecs(mut self, codecs: Arc<CodecRegistry>) -> Self {
+        self.codecs = codecs;
+        self
+    }
+
+    pub fn version(&self) -> u32 { self.version }
+
+    pub fn set_version(&mut self, version: u32) { self.version = version }
+
+    pub fn profile(&self) -> &GameProfile { &self.profile }
+
+    pub fn set_profile(&mut self, profile: GameProfile) { self.profile = profile }
+
+    pub fn codecs(&self) -> &Arc<CodecRegistry> { &self.codecs }
+
+    pub fn set_codecs(&mut self, codecs: Arc<CodecRegistry>) { self.codecs = codecs }
+
+    /// The codec the format stores its compressed data with at the current version.
+    pub fn codec(&self, format: &str) -> Result<&Arc<dyn Codec>, CodecError> {
+        self.codecs.get(format, self.version)
+    }
+
+    pub fn diagnostics(&self) -> &S { &self.diagnostics }
+
+    pub fn diagnostics_mut(&mut self) -> &mut S { &mut self.diagnostics }
+
+    pub fn into_diagnostics(self) -> S { self.diagnostics }
+
+    pub fn report(&mut self, severity: Severity, offset: u64, message: impl Into<String>) {
+        self.diagnostics.report(Diagnostic { severity, offset, message: message.into() });
+    }
+}
+
+impl<S: DiagnosticSink> VersionedOptions for DebinarizationContext<S> {
+    fn version(&self) -> u32 { self.version }
+}
+
+impl<S: DiagnosticSink + Default> DebinarizationOptions for DebinarizationContext<S> {
+
+}
+
+impl<S: DiagnosticSink> fmt::Debug for DebinarizationContext<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DebinarizationContext")
+            .field("version", &self.version)
+            .field("profile", &self.profile)
+            .field("codecs", &self.codecs)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<R: Read, S: DiagnosticSink> ContextReader<R, S> {
+    pub fn new(reader: R, context: DebinarizationContext<S>) -> Self {
+        Self { reader, position: 0, context }
+    }
+
+    pub fn context(&self) -> &DebinarizationContext<S> { &self.context }
+
+    pub fn context_mut(&mut self) -> &mut DebinarizationContext<S> { &mut self.context }
+
+    /// How many bytes were read so far, or the offset last seeked to.
+    pub fn position(&self) -> u64 { self.position }
+
+    /// Runs `read` with the context set to the given version, restoring the previous version
+    /// afterwards. Useful for nested data that carries its own version.
+    pub fn with_version<T>(&mut self, version: u32, read: impl FnOnce(&mut Self) -> T) -> T {
+        let previous = core::mem::replace(&mut self.context.version, version);
+        let result = read(self);
+        self.context.version = previous;
+        result
+    }
+
+    /// Reports a diagnostic at the current position.
+    pub fn report(&mut self, severity: Severity, message: impl Into<String>) {
+        self.context.report(severity, self.position, message);
+    }
+
+    pub fn warn(&mut self, message: impl Into<String>) {
+        self.report(Severity::Warning, message);
+    }
+
+    pub fn into_parts(self) -> (R, DebinarizationContext<S>) { (self.reader, self.context) }
+}
+
+impl<R: Read, S: DiagnosticSink> VersionedReader for ContextReader<R, S> {
+    fn version(&self) -> u32 { self.context.version }
+
+    fn set_version(&mut self, version: u32) { self.context.version = version }
+}
+
+impl<R: Read, S: DiagnosticSink> PositionedReader for ContextReader<R, S> {
+    fn position(&self) -> u64 { self.position }
+}
+
+impl<T: VersionedReader + ?Sized> VersionedReader for &mut T {
+    fn version(&self) -> u32 { (**self).version() }
+
+    fn set_version(&mut self, version: u32) { (**self).set_version(version) }
+}
+
+impl<R: Read, S: DiagnosticSink> Read for ContextReader<R, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Read + Seek, S: DiagnosticSink> Seek for ContextReader<R, S> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = self.reader.seek(pos)?;
+        Ok(self.position)
+    }
+}
@@ -0,0 +1,457 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::core::io::stream::{self as io, Read};
+use thiserror::Error;
+
+const M2_MAX_LENGTH:   usize = 8;
+const M2_MAX_DISTANCE: usize = 0x0800;
+const M3_MAX_DISTANCE: usize = 0x4000;
+const M4_MAX_DISTANCE: usize = 0xbfff;
+const MIN_MATCH:       usize = 4;
+const HASH_BITS:       u32   = 14;
+
+/// Literal runs up to this length can be stored in the first byte of a stream.
+const MAX_FIRST_LITERALS: usize = 255 - 17;
+
+/// A match copying three bytes from a distance of 16kB ends the stream.
+const END_OF_STREAM: [u8; 3] = [0x11, 0x00, 0x00];
+
+/// The most a single input byte can expand to, every zero byte of a length extends a run by 255.
+const MAX_EXPANSION: usize = 256;
+
+#[derive(Error, Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum LzoError {
+    #[error("LZO Error: The input ended at offset {0} before the end of stream marker.")]
+    TruncatedInput(usize),
+    #[error("LZO Error: The match at offset {offset} reaches {distance} bytes back but only {available} bytes were decoded.")]
+    InvalidBackReference { offset: usize, distance: usize, available: usize },
+    #[error("LZO Error: The instruction at offset {offset} overflows the {capacity} byte output.")]
+    OutputOverflow { offset: usize, capacity: usize },
+    #[error("LZO Error: The stream decoded to {actual} bytes instead of {expected}.")]
+    LengthMismatch { expected: usize, actual: usize },
+}
+
+/// Where the decoder reads the compressed stream from.
+trait Source {
+    fn position(&self) -> usize;
+
+    fn byte(&mut self) -> Result<u8, LzoError>;
+
+    /// Fills the output with the next bytes of the stream.
+    fn read_into(&mut self, output: &mut [u8]) -> Result<(), LzoError>;
+
+    fn le16(&mut self) -> Result<usize, LzoError> {
+        Ok(self.byte()? as usize | (self.byte()? as usize) << 8)
+    }
+
+    /// Reads the length stored in an instruction, a zero field is followed by a run of zero bytes
+    /// each adding 255 and a final non-zero byte.
+    fn length(&mut self, field: usize, max_field: usize) -> Result<usize, LzoError> {
+        if field != 0 {
+            return Ok(field)
+        }
+
+        let mut length = max_field;
+        loop {
+            match self.byte()? {
+                0 => length = length.saturating_add(255),
+                byte => return Ok(length.saturating_add(byte as usize))
+            }
+        }
+    }
+}
+
+/// Where the decoder writes the unpacked data to.
+trait Buffer {
+    /// The most bytes the stream may unpack to.
+    fn capacity(&self) -> usize;
+
+    /// The first `length` bytes of the buffer, `length` never exceeds the capacity.
+    fn prefix(&mut self, length: usize) -> &mut [u8];
+}
+
+struct Input<'a> {
+    data:     &'a [u8],
+    position: usize,
+}
+
+/// Reads the stream from a reader one byte at a time, so that nothing past its end is consumed.
+struct StreamInput<R: Read> {
+    reader:   R,
+    position: usize,
+    error:    Option<io::Error>,
+}
+
+/// A buffer growing with the data actually decoded, used when the length is not trusted.
+struct GrowingBuffer {
+    data:     Vec<u8>,
+    capacity: usize,
+}
+
+struct Output<'a, B: Buffer + ?Sized> {
+    data:    &'a mut B,
+    written: usize,
+}
+
+/// Compresses the input into an LZO1X stream, terminated by an end of stream marker.
+pub fn encode(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len() + input.len() / 16 + 64);
+    let mut table = vec![0usize; 1 << HASH_BITS];
+    let mut state_index = None;
+    let mut literal_start = 0;
+    let mut position = 0;
+
+    while position + MIN_MATCH <= input.len() {
+        let key = hash(&input[position..]);
+        let candidate = core::mem::replace(&mut table[key], position + 1);
+        if candidate != 0 {
+            let start = candidate - 1;
+            let distance = position - start;
+            if distance <= M4_MAX_DISTANCE && input[start..start + MIN_MATCH] == input[position..position + MIN_MATCH] {
+                let length = MIN_MATCH + input[position + MIN_MATCH..].iter()
+                    .zip(&input[start + MIN_MATCH..])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+
+                write_literals(&mut output, &input[literal_start..position], state_index);
+                state_index = Some(write_match(&mut output, distance, length));
+                position += length;
+                literal_start = position;
+                continue
+            }
+        }
+        position += 1;
+    }
+
+    write_literals(&mut output, &input[literal_start..], state_index);
+    output.extend_from_slice(&END_OF_STREAM);
+    output
+}
+
+/// Decodes a stream that is expected to unpack to exactly `length` bytes, failing early when the
+/// input could not possibly expand to that length instead of allocating an output buffer for it.
+pub fn decode(input: &[u8], length: usize) -> Result<Vec<u8>, LzoError> {
+    if length > input.len().saturating_mul(MAX_EXPANSION) {
+        return Err(LzoError::TruncatedInput(input.len()))
+    }
+
+    let mut decompressed_buffer = vec![0; length];
+    let actual = decode_into(input, &mut decompressed_buffer)?;
+    match actual == length {
+        true => Ok(decompressed_buffer),
+        false => Err(LzoError::LengthMismatch { expected: length, actual })
+    }
+}
+
+/// Decodes into the given buffer until the end of stream marker, returning the number of bytes
+/// written. The stream may not unpack to more than the buffer holds.
+pub fn decode_into(input: &[u8], output: &mut [u8]) -> Result<usize, LzoError> {
+    let mut output = Output { data: output, written: 0 };
+    run(&mut Input { data: input, position: 0 }, &mut output)?;
+    Ok(output.written)
+}
+
+/// Decodes a stream read from the reader that is expected to unpack to exactly `length` bytes,
+/// consuming it up to and including its end of stream marker. The output grows with the data
+/// actually decoded, so an untrusted length does not allocate anything up front.
+pub fn decode_from<R: Read>(reader: R, length: usize) -> io::Result<Vec<u8>> {
+    let mut input = StreamInput { reader, position: 0, error: None };
+    let mut buffer = GrowingBuffer { data: Vec::new(), capacity: length };
+    let mut output = Output { data: &mut buffer, written: 0 };
+    if let Err(error) = run(&mut input, &mut output) {
+        return Err(input.error.take().unwrap_or_else(|| error.into()))
+    }
+    match output.written == length {
+        true => Ok(buffer.data),
+        false => Err(LzoError::LengthMismatch { expected: length, actual: output.written }.into())
+    }
+}
+
+fn run<B: Buffer + ?Sized>(input: &mut impl Source, output: &mut Output<B>) -> Result<(), LzoError> {
+    let mut state = 0;
+    let mut pending = None;
+
+    let first = input.byte()?;
+    match first > 17 {
+        true => {
+            let count = first as usize - 17;
+            output.copy_literals(input, count, 0)?;
+            state = count.min(4);
+        },
+        false => pending = Some(first)
+    }
+
+    loop {
+        let offset = input.position() - pending.is_some() as usize;
+        let instruction = match pending.take() {
+            Some(instruction) => instruction,
+            None => input.byte()?
+        } as usize;
+        let (length, distance, next_state) = match instruction {
+            0..=15 => match state {
+                0 => {
+                    let count = input.length(instruction, 15)?.saturating_add(3);
+                    output.copy_literals(input, count, offset)?;
+                    state = 4;
+                    continue
+                },
+                4 => (3, ((input.byte()? as usize) << 2) + ((instruction >> 2) & 3) + M2_MAX_DISTANCE + 1, instruction & 3),
+                _ => (2, ((input.byte()? as usize) << 2) + ((instruction >> 2) & 3) + 1, instruction & 3)
+            },
+            16..=31 => {
+                let length = input.length(instruction & 7, 7)?.saturating_add(2);
+                let trailer = input.le16()?;
+                let distance = ((instruction & 8) << 11) + (trailer >> 2);
+                if distance == 0 {
+                    return Ok(())
+                }
+                (length, distance + M3_MAX_DISTANCE, trailer & 3)
+            },
+            32..=63 => {
+                let length = input.length(instruction & 31, 31)?.saturating_add(2);
+                let trailer = input.le16()?;
+                (length, (trailer >> 2) + 1, trailer & 3)
+            },
+            64..=127 => (3 + ((instruction >> 5) & 1), ((input.byte()? as usize) << 3) + ((instruction >> 2) & 7) + 1, instruction & 3),
+            _ => (5 + ((instruction >> 5) & 3), ((input.byte()? as usize) << 3) + ((instruction >> 2) & 7) + 1, instruction & 3),
+        };
+
+        output.copy_match(distance, length, offset)?;
+        output.copy_literals(input, next_state, offset)?;
+        state = next_state;
+    }
+}
+
+impl Source for Input<'_> {
+    fn position(&self) -> usize { self.position }
+
+    fn byte(&mut self) -> Result<u8, LzoError> {
+        let byte = *self.data.get(self.position).ok_or(LzoError::TruncatedInput(self.position))?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn read_into(&mut self, output: &mut [u8]) -> Result<(), LzoError> {
+        let literals = self.data.get(self.position..self.position.saturating_add(output.len()))
+            .ok_or(LzoError::TruncatedInput(self.data.len()))?;
+
+        output.copy_from_slice(literals);
+        self.position += output.len();
+        Ok(())
+    }
+}
+
+impl<R: Read> Source for StreamInput<R> {
+    fn position(&self) -> usize { self.position }
+
+    fn byte(&mut self) -> Result<u8, LzoError> {
+        let mut byte = [0u8; 1];
+        self.read_into(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    fn read_into(&mut self, output: &mut [u8]) -> Result<(), LzoError> {
+        if let Err(error) = self.reader.read_exact(output) {
+            self.error = Some(error);
+            return Err(LzoError::TruncatedInput(self.position))
+        }
+        self.position += output.len();
+        Ok(())
+    }
+}
+
+impl Buffer for [u8] {
+    fn capacity(&self) -> usize { self.len() }
+
+    fn prefix(&mut self, length: usize) -> &mut [u8] { &mut self[..length] }
+}
+
+impl Buffer for GrowingBuffer {
+    fn capacity(&self) -> usize { self.capacity }
+
+    fn prefix(&mut self, length: usize) -> &mut [u8] {
+        if self.data.len() < length {
+            self.data.resize(length, 0);
+        }
+        &mut self.data[..length]
+    }
+}
+
+impl<B: Buffer + ?Sized> Output<'_, B> {
+    fn copy_literals(&mut self, input: &mut impl Source, count: usize, offset: usize) -> Result<(), LzoError> {
+        if count > self.data.capacity() - self.written {
+            return Err(LzoError::OutputOverflow { offset, capacity: self.data.capacity() })
+        }
+
+        input.read_into(&mut self.data.prefix(self.written + count)[self.written..])?;
+        self.written += count;
+        Ok(())
+    }
+
+    fn copy_match(&mut self, distance: usize, length: usize, offset: usize) -> Result<(), LzoError> {
+        if distance > self.written {
+            return Err(LzoError::InvalidBackReference { offset, distance, available: self.written })
+        }
+        if length > self.data.capacity() - self.written {
+            return Err(LzoError::OutputOverflow { offset, capacity: self.data.capacity() })
+        }
+
+        //Matches may overlap the bytes they produce, so they are copied one at a time.
+        let data = self.data.prefix(self.written + length);
+        for _ in 0..length {
+            data[self.written] = data[self.written - distance];
+            self.written += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Writes a run of literals. Short runs following a match are stored in the two spare bits of
+/// that match, whose position is given by `state_index`.
+fn write_literals(output: &mut Vec<u8>, literals: &[u8], state_index: Option<usize>) {
+    let count = literals.len();
+    match state_index {
+        _ if count == 0 => return,
+        None if count <= MAX_FIRST_LITERALS => output.push(17 + count as u8),
+        Some(index) if count <= 3 => output[index] |= count as u8,
+        _ if count <= 18 => output.push((count - 3) as u8),
+        _ => {
+            output.push(0);
+            write_length(output, count - 18);
+        }
+    }
+    output.extend_from_slice(literals);
+}
+
+/// Writes a match, returning the position of the byte that holds the number of literals that
+/// follow it.
+fn write_match(output: &mut Vec<u8>, distance: usize, length: usize) -> usize {
+    if length <= M2_MAX_LENGTH && distance <= M2_MAX_DISTANCE {
+        let distance = distance - 1;
+        let instruction = match length {
+            3..=4 => 0x40 | ((length - 3) << 5),
+            _ => 0x80 | ((length - 5) << 5)
+        } | ((distance & 7) << 2);
+        output.push(instruction as u8);
+        output.push((distance >> 3) as u8);
+        return output.len() - 2
+    }
+
+    let (instruction, max_field, distance) = match distance <= M3_MAX_DISTANCE {
+        true => (0x20, 31, distance - 1),
+        false => {
+            let distance = distance - M3_MAX_DISTANCE;
+            (0x10 | ((distance >> 11) & 8), 7, distance & 0x3fff)
+        }
+    };
+    match length - 2 <= max_field {
+        true => output.push((instruction | (length - 2)) as u8),
+        false => {
+            output.push(instruction as u8);
+            write_length(output, length - 2 - max_field);
+        }
+    }
+    output.push(((distance & 0x3f) << 2) as u8);
+    output.push((distance >> 6) as u8);
+    output.len() - 2
+}
+
+fn write_length(output: &mut Vec<u8>, mut remaining: usize) {
+    while remaining > 255 {
+        output.push(0);
+        remaining -= 255;
+    }
+    output.push(remaining as u8);
+}
+
+#[inline]
+fn hash(data: &[u8]) -> usize {
+    let key = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+    (key.wrapping_mul(0x9E3779B1) >> (32 - HASH_BITS)) as usize
+}
+
+impl From<LzoError> for io::Error {
+    fn from(value: LzoError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noise(seed: u64, length: usize) -> Vec<u8> {
+        let mut state = seed;
+        (0..length).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 24) as u8
+        }).collect()
+    }
+
+    /// Inputs of the given length that exercise literal runs, every match kind and long lengths.
+    fn shapes(length: usize) -> Vec<Vec<u8>> {
+        let text = b"class CfgPatches { units[] = {}; weapons[] = {}; };\n";
+        let mut far = noise(length as u64, length - length / 2);
+        far.extend_from_within(..length / 2);
+        vec![
+            vec![0; length],
+            noise(length as u64 + 1, length),
+            text.iter().copied().cycle().take(length).collect(),
+            (0..length).map(|i| (i / 300) as u8).collect(),
+            far,
+        ]
+    }
+
+    #[test]
+    fn round_trips() {
+        let lengths = (0..=64).chain([255, 256, 1000, 4096, M2_MAX_DISTANCE * 2 + 3, M3_MAX_DISTANCE * 2 + 5, M4_MAX_DISTANCE * 2 + 7]);
+        for length in lengths {
+            for input in shapes(length) {
+                let encoded = encode(&input);
+                assert_eq!(decode(&encoded, input.len()).unwrap(), input, "decode failed on {} bytes", length);
+
+                let mut output = vec![0; input.len() + 16];
+                assert_eq!(decode_into(&encoded, &mut output).unwrap(), input.len());
+                assert_eq!(output[..input.len()], input);
+
+                let mut stream = io::Cursor::new([encoded.as_slice(), b"tail"].concat());
+                assert_eq!(decode_from(&mut stream, input.len()).unwrap(), input);
+                assert_eq!(stream.position() as usize, encoded.len(), "decode_from read past the stream");
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_short_outputs() {
+        let input = noise(3, 1000);
+        let encoded = encode(&input);
+        assert!(decode(&encoded, input.len() - 1).is_err());
+        assert!(decode_into(&encoded, &mut vec![0; input.len() - 1]).is_err());
+        assert!(decode_from(encoded.as_slice(), input.len() - 1).is_err());
+        assert!(decode_from(&encoded[..encoded.len() - 1], input.len()).is_err());
+    }
+
+    /// Random and corrupted streams may fail to decode but never panic.
+    #[test]
+    fn garbage_does_not_panic() {
+        let valid: Vec<Vec<u8>> = shapes(2000).iter().map(|input| encode(input)).collect();
+        for seed in 0..2000u64 {
+            let mut input = noise(seed, (seed as usize * 7) % 600);
+            if seed % 2 == 0 {
+                input = valid[seed as usize % valid.len()].clone();
+                for (index, byte) in noise(seed * 31, 4).into_iter().enumerate() {
+                    let at = (seed as usize * 13 + index * 101) % input.len();
+                    input[at] ^= byte;
+                }
+            }
+
+            for length in [0, 1, 17, 2000, 70_000] {
+                let _ = decode(&input, length);
+                let _ = decode_into(&input, &mut vec![0; length]);
+                let _ = decode_from(input.as_slice(), length);
+            }
+        }
+    }
+}
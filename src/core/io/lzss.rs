@@ -1,301 +1,762 @@
-use std::io::{Error, ErrorKind};
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::core::io::stream::{self as io, Read, Write};
+use thiserror::Error;
 
 const N:         usize = 4096;
 const F:         usize = 18;
-const NIL:       usize = N;
 const FILL:      u8    = 0x20;
 const THRESHOLD: usize = 2;
 
 const N_F: usize = N - F;
+const MIN_MATCH:    usize = THRESHOLD + 1;
+/// Distances are stored in twelve bits, a distance of zero is avoided as not every decoder wraps it
+/// around to a full window.
+const MAX_DISTANCE: usize = N - 1;
+const HASH_BITS:    u32   = 13;
+const HASH_SIZE:    usize = 1 << HASH_BITS;
+/// How much unencoded input the writer collects before compressing it.
+const WRITER_BLOCK: usize = 1 << 16;
+
+/// The most a single input byte can expand to, eight back-references of `F` bytes each take up
+/// seventeen input bytes.
+const MAX_EXPANSION: usize = 9;
+
+#[derive(Error, Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum LzssError {
+    #[error("LZSS Error: The input ended after {read} bytes with {missing} bytes left to decode.")]
+    TruncatedInput { read: usize, missing: usize },
+    #[error("LZSS Error: The back-reference at offset {offset} reaches {distance} bytes back, before the start of the window.")]
+    InvalidBackReference { offset: usize, distance: usize },
+    #[error("LZSS Error: The run of {length} bytes at offset {offset} overflows the {capacity} byte output.")]
+    OutputOverflow { offset: usize, length: usize, capacity: usize },
+    #[error("LZSS Error: The stored checksum {expected:#010x} does not match the calculated checksum {actual:#010x}.")]
+    ChecksumMismatch { expected: u32, actual: u32 },
+}
+
+/// The additive checksum binarized formats store after their compressed blocks. Older formats sum
+/// the bytes as signed chars, newer ones as unsigned.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum LzssChecksum {
+    Signed,
+    Unsigned
+}
+
+/// Trades compression speed for ratio, every level produces data the engine can read.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum LzssLevel {
+    /// Follows only a few candidates per position and never defers a match.
+    Fastest,
+    #[default]
+    Default,
+    /// Searches every candidate in the window.
+    Best
+}
+
+/// A hash chain matcher with optional lazy matching. Its tables are kept between calls so a single
+/// encoder can compress any number of inputs without reallocating.
+#[derive(Debug, Clone)]
+pub struct LzssEncoder {
+    level:    LzssLevel,
+    head:     Vec<usize>,
+    previous: Vec<usize>,
+    position: usize,
+    inserted: usize,
+    mask:     u8,
+    code:     [u8; 17],
+    code_idx: usize,
+}
+
+/// The ring buffer of already decoded bytes that back-references copy from.
+struct Window {
+    buffer:   [u8; N],
+    position: usize,
+}
+
+/// Decompresses a stream of known unpacked length. Input is read a byte at a time so that the
+/// wrapped reader is left right after the compressed data, unbuffered readers should be wrapped
+/// in a `BufReader`.
+pub struct LzssReader<R: Read> {
+    reader:   R,
+    checksum: Option<(LzssChecksum, u32)>,
+    window:   Window,
+    flags:    u32,
+    consumed: usize,
+    decoded:  usize,
+    length:   usize,
+    run_from: usize,
+    run_left: usize,
+}
 
-struct Context {
-    match_position:    usize,
-    match_length:      usize,
-    previous_children: [usize; N + 1],
-    next_children:     [usize; N + 257],
-    parents:           [usize; N + 1],
-    buffer:            [u8;    N + F - 1]
+/// Compresses everything written to it. The compressed stream is only complete once `finish`
+/// has been called. Compressed bytes are passed on with the next write or flush, so when the
+/// wrapped writer fails the input of that call is not taken and can be written again.
+pub struct LzssWriter<W: Write> {
+    writer:   W,
+    checksum: Option<(LzssChecksum, u32)>,
+    encoder:  LzssEncoder,
+    buffer:   Vec<u8>,
+    base:     usize,
+    output:   Vec<u8>,
 }
 
+/// Compresses the input at the default level, use an `LzssEncoder` directly to pick the level or
+/// to reuse its tables.
 pub fn encode(input: &[u8]) -> Vec<u8> {
-    let mut compressed_buffer: Vec<u8> = vec![];
-    let mut context = Context::new();
-
-    let mut len: usize = 0;
-    let mut mask: u8 = 1;
-    let mut input_idx: usize = 0;
-    let mut code_idx: usize = 1;
-    let mut s: usize = 0;
-
-    let mut r = N_F;
-    let stop_pos = input.len();
-
-    let mut code: [u8; 17] = [0; 17];
-    for i in s..r { context.buffer[i] = FILL }
-    while len < F && input_idx < stop_pos {
-        context.buffer[r + len] = input[input_idx];
-        input_idx += 1; len += 1;
-    }
-    for i in 1..=F { context.insert_node(r - i) }
-    context.insert_node(r);
-    loop {
-        if context.match_length > len {
-            context.match_length = len;
-        }
+    LzssEncoder::default().encode(input)
+}
+
+/// Decodes exactly `length` bytes, failing early when the input could not possibly expand to
+/// that length instead of allocating an output buffer for it.
+pub fn decode(input: &[u8], length: usize) -> Result<Vec<u8>, LzssError> {
+    if length > input.len().saturating_mul(MAX_EXPANSION) {
+        return Err(LzssError::TruncatedInput { read: input.len(), missing: length })
+    }
+
+    let mut decompressed_buffer = vec![FILL; length];
+    decode_into(input, &mut decompressed_buffer)?;
+    Ok(decompressed_buffer)
+}
 
-        if context.match_length <= THRESHOLD {
-            context.match_length = 1;
-            code[0] |= mask;
-            code[code_idx] = context.buffer[r];
-            code_idx += 1;
-        } else {
-            let encoded_position = (r - context.match_position) & (N - 1);
-            code[code_idx] = encoded_position as u8;
-            code_idx += 1;
-            code[code_idx] = (
-                ((encoded_position >> 3) & 0xf0) |
-                    (context.match_length - (THRESHOLD + 1))
-            ) as u8;
-            code_idx += 1;
+/// Decodes into the given buffer until it is full, returning the number of input bytes that were
+/// consumed.
+pub fn decode_into(input: &[u8], output: &mut [u8]) -> Result<usize, LzssError> {
+    let length = output.len();
+    let mut window = Window::new();
+    let mut source_idx: usize = 0;
+    let mut decompressed_idx: usize = 0;
+    let mut flags: u32 = 0;
+
+    while decompressed_idx < length {
+        let truncated = LzssError::TruncatedInput { read: source_idx, missing: length - decompressed_idx };
+        flags >>= 1;
+        if flags & 0x100 == 0 {
+            flags = *input.get(source_idx).ok_or(truncated)? as u32 | 0xFF00;
+            source_idx += 1;
         }
 
-        mask = mask << 1;
-        if mask == 0 {
-            compressed_buffer.extend_from_slice(&code[0..code_idx]);
-            code[0] = 0;
-            code_idx = 1;
-            mask = 1;
+        if flags & 1 != 0 {
+            let c = *input.get(source_idx).ok_or(truncated)?;
+            source_idx += 1;
+
+            output[decompressed_idx] = c;
+            decompressed_idx += 1;
+            window.push(c);
+            continue
         }
 
-        let last_match_length = context.match_length;
-        for _i in 0..std::cmp::min(last_match_length, stop_pos - input_idx) {
-            context.delete_node(s);
-            let c = input[input_idx];
-            input_idx += 1;
-            context.buffer[s] = c;
-            if s < F - 1 { context.buffer[s + N] = c }
-            s = (s + 1) & (N - 1);
-            r = (r + 1) & (N - 1);
-            context.insert_node(r);
+        let (Some(&low), Some(&high)) = (input.get(source_idx), input.get(source_idx + 1)) else {
+            return Err(truncated)
+        };
+        let (mut position, run) = window.back_reference(low, high, source_idx, decompressed_idx, length)?;
+        source_idx += 2;
+
+        for _ in 0..run {
+            let c = window.buffer[position];
+            output[decompressed_idx] = c;
+            decompressed_idx += 1;
+            window.push(c);
+            position = (position + 1) & (N - 1);
         }
-        for _i in 0..last_match_length {
-            context.delete_node(s);
-            s = (s + 1) & (N - 1);
-            r = (r + 1) & (N - 1);
-            len -= 1;
-            if len != 0 { context.insert_node(r) }
+    }
+
+    Ok(source_idx)
+}
+
+/// Compresses the input and appends the checksum of the uncompressed data.
+pub fn encode_with_checksum(input: &[u8], checksum: LzssChecksum) -> Vec<u8> {
+    let mut compressed_buffer = encode(input);
+    compressed_buffer.extend_from_slice(&checksum.calculate(input).to_le_bytes());
+    compressed_buffer
+}
+
+/// Decodes exactly `length` bytes followed by a checksum trailer, which has to match the decoded
+/// data.
+pub fn decode_with_checksum(input: &[u8], length: usize, checksum: LzssChecksum) -> Result<Vec<u8>, LzssError> {
+    if length > input.len().saturating_mul(MAX_EXPANSION) {
+        return Err(LzssError::TruncatedInput { read: input.len(), missing: length })
+    }
+
+    let mut decompressed_buffer = vec![FILL; length];
+    decode_into_with_checksum(input, &mut decompressed_buffer, checksum)?;
+    Ok(decompressed_buffer)
+}
+
+/// Decodes into the given buffer and verifies the checksum trailer after it, returning the number
+/// of input bytes consumed including the trailer.
+pub fn decode_into_with_checksum(input: &[u8], output: &mut [u8], checksum: LzssChecksum) -> Result<usize, LzssError> {
+    let consumed = decode_into(input, output)?;
+    let trailer = input.get(consumed..consumed + 4)
+        .ok_or(LzssError::TruncatedInput { read: input.len(), missing: 0 })?;
+    checksum.verify(output, u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]))?;
+    Ok(consumed + 4)
+}
+
+impl LzssChecksum {
+    pub fn calculate(&self, data: &[u8]) -> u32 {
+        self.update(0, data)
+    }
+
+    /// Continues a checksum with more data, so that streams can be summed incrementally.
+    pub fn update(&self, checksum: u32, data: &[u8]) -> u32 {
+        match self {
+            LzssChecksum::Signed => data.iter().fold(checksum, |sum, &c| sum.wrapping_add(c as i8 as u32)),
+            LzssChecksum::Unsigned => data.iter().fold(checksum, |sum, &c| sum.wrapping_add(c as u32)),
         }
+    }
 
-        if len == 0 {
-            break;
+    pub fn verify(&self, data: &[u8], expected: u32) -> Result<(), LzssError> {
+        let actual = self.calculate(data);
+        match actual == expected {
+            true => Ok(()),
+            false => Err(LzssError::ChecksumMismatch { expected, actual })
         }
     }
+}
 
-    if code_idx > 1 {
-        compressed_buffer.extend_from_slice(&code[0..code_idx]);
+impl LzssLevel {
+    fn max_chain(&self) -> usize {
+        match self {
+            LzssLevel::Fastest => 4,
+            LzssLevel::Default => 128,
+            LzssLevel::Best => N,
+        }
     }
 
-    return compressed_buffer
+    fn lazy(&self) -> bool { *self != LzssLevel::Fastest }
 }
 
-pub fn decode(input: &[u8], length: usize) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    const SOURCE_INDEX_OUT_OF_BOUNDS: &str = "Source index is out of bounds for input buffer.";
-    const OVERFLOW: &str = "LZSS Overflow Encountered.";
+impl Default for LzssEncoder {
+    fn default() -> Self { Self::new(LzssLevel::default()) }
+}
 
-    let mut decompressed_buffer: Vec<u8> = vec![FILL; length]; //Fill with most common
-    let mut bytes_left = length;
-    let mut decompressed_idx: usize = 0;
-    let mut source_idx: usize = 0;
-    let mut r = N_F;
-    let mut text_buf = [FILL; N_F];
-    let mut flags: u32 = 0;
+impl LzssEncoder {
+    pub fn new(level: LzssLevel) -> Self {
+        LzssEncoder {
+            level,
+            head: vec![0; HASH_SIZE],
+            previous: vec![0; N],
+            position: 0,
+            inserted: 0,
+            mask: 1,
+            code: [0; 17],
+            code_idx: 1,
+        }
+    }
 
-    while bytes_left > 0 && source_idx <= input.len() {
-        flags >>= 1;
-        let mut c: u8;
-
-        if flags & 256 == 0 {
-            if input.len() < source_idx {
-                return Err(Box::new(Error::new(
-                    ErrorKind::InvalidData,
-                    SOURCE_INDEX_OUT_OF_BOUNDS
-                )));
-            }
+    pub fn level(&self) -> LzssLevel { self.level }
 
-            c = input[source_idx];
-            source_idx += 1;
-            flags = c as u32 | 0xFF00
-        }
+    pub fn encode(&mut self, input: &[u8]) -> Vec<u8> {
+        let mut compressed_buffer = Vec::with_capacity(input.len() / 2);
+        self.encode_into(input, &mut compressed_buffer);
+        compressed_buffer
+    }
 
-        if flags & 1 != 0 {
-            if input.len() < source_idx {
-                return Err(Box::new(Error::new(
-                    ErrorKind::InvalidData,
-                    SOURCE_INDEX_OUT_OF_BOUNDS
-                )));
+    /// Appends the compressed input to the given buffer.
+    pub fn encode_into(&mut self, input: &[u8], output: &mut Vec<u8>) {
+        self.reset();
+        self.compress(input, 0, true, output);
+    }
+
+    fn reset(&mut self) {
+        self.head.fill(0);
+        self.previous.fill(0);
+        self.position = 0;
+        self.inserted = 0;
+        self.mask = 1;
+        self.code = [0; 17];
+        self.code_idx = 1;
+    }
+
+    /// Encodes the data following the current position. `data` starts at the absolute position
+    /// `base` and has to keep a window of history before the current position. Unless `finish` is
+    /// set, enough look-ahead is held back for the output not to depend on how the input was split
+    /// up.
+    fn compress(&mut self, data: &[u8], base: usize, finish: bool, output: &mut Vec<u8>) {
+        let end = base + data.len();
+        let limit = match finish {
+            true => end,
+            false => end.saturating_sub(F + 1)
+        };
+
+        while self.position < limit {
+            let position = self.position;
+            let (length, distance) = self.find_match(data, base, position, end);
+            if length < MIN_MATCH {
+                self.literal(data[position - base], output);
+                self.position += 1;
+                continue
             }
 
-            c = input[source_idx];
-            source_idx += 1;
+            //Prefer a literal when the next position starts a longer match.
+            if self.level.lazy() && length < F && position + 1 < end {
+                let (next_length, _) = self.find_match(data, base, position + 1, end);
+                if next_length > length {
+                    self.literal(data[position - base], output);
+                    self.position += 1;
+                    continue
+                }
+            }
 
-            decompressed_buffer[decompressed_idx] = c;
-            decompressed_idx += 1;
+            self.reference(distance, length, output);
+            self.position += length;
+        }
 
-            text_buf[r] = c;
-            r += 1;
-            r &= N - 1;
-            continue
+        if finish {
+            self.flush(output);
         }
-        //Else here because rust doesnt detect control flow. Ide bug.
-        else {
+    }
 
+    /// The longest match for the given position as its length and distance, positions before it
+    /// are added to the hash chains first.
+    fn find_match(&mut self, data: &[u8], base: usize, position: usize, end: usize) -> (usize, usize) {
+        self.insert_until(data, base, position, end);
+        let max_length = core::cmp::min(F, end - position);
+        if max_length < MIN_MATCH {
+            return (0, 0)
+        }
 
-            if input.len() < source_idx + 2 {
-                return Err(Box::new(Error::new(
-                    ErrorKind::InvalidData,
-                    SOURCE_INDEX_OUT_OF_BOUNDS
-                )));
+        let current = &data[position - base..position - base + max_length];
+        let mut best = (0, 0);
+        let mut candidate = self.head[hash(current)];
+        let mut chain = self.level.max_chain();
+        while candidate != 0 && chain > 0 {
+            let start = candidate - 1;
+            let distance = position - start;
+            if distance > MAX_DISTANCE {
+                break
             }
-            let mut i: usize = input[source_idx] as usize;
-            source_idx += 1;
-            let mut j: usize = input[source_idx] as usize;
-            source_idx += 1;
 
-            i |= (j & 0xf0) << 4;
-            j &= 0x0f;
-            j += THRESHOLD;
-
-            let mut ii: usize = r - i;
-            let jj: usize = j + ii;
-            if (j + 1) > bytes_left {
-                return Err(Box::new(Error::new(
-                    ErrorKind::InvalidData,
-                    OVERFLOW
-                )));
+            let length = current.iter()
+                .zip(&data[start - base..])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if length > best.0 {
+                best = (length, distance);
+                if length == max_length { break }
             }
 
-            while ii <= jj {
-                c = text_buf[ii & (N - 1)];
-
-                decompressed_buffer[decompressed_idx] = c;
-                decompressed_idx += 1;
-                bytes_left -= 1;
+            candidate = self.previous[start & (N - 1)];
+            chain -= 1;
+        }
 
-                text_buf[r] = c;
-                r += 1;
-                r &= N - 1;
+        best
+    }
 
-                ii += 1;
+    fn insert_until(&mut self, data: &[u8], base: usize, position: usize, end: usize) {
+        while self.inserted < position {
+            let inserted = self.inserted;
+            if inserted + MIN_MATCH <= end {
+                let key = hash(&data[inserted - base..]);
+                self.previous[inserted & (N - 1)] = self.head[key];
+                self.head[key] = inserted + 1;
             }
+            self.inserted += 1;
         }
+    }
+
+    fn literal(&mut self, c: u8, output: &mut Vec<u8>) {
+        self.code[0] |= self.mask;
+        self.code[self.code_idx] = c;
+        self.code_idx += 1;
+        self.advance(output);
+    }
 
+    fn reference(&mut self, distance: usize, length: usize, output: &mut Vec<u8>) {
+        self.code[self.code_idx] = distance as u8;
+        self.code[self.code_idx + 1] = (((distance >> 4) & 0xf0) | (length - MIN_MATCH)) as u8;
+        self.code_idx += 2;
+        self.advance(output);
     }
 
-    return Ok(decompressed_buffer);
+    fn advance(&mut self, output: &mut Vec<u8>) {
+        self.mask <<= 1;
+        if self.mask == 0 {
+            self.flush(output);
+        }
+    }
+
+    fn flush(&mut self, output: &mut Vec<u8>) {
+        if self.code_idx > 1 {
+            output.extend_from_slice(&self.code[0..self.code_idx]);
+        }
+        self.code[0] = 0;
+        self.code_idx = 1;
+        self.mask = 1;
+    }
+}
+
+#[inline]
+fn hash(data: &[u8]) -> usize {
+    let key = data[0] as u32 | (data[1] as u32) << 8 | (data[2] as u32) << 16;
+    (key.wrapping_mul(0x9E3779B1) >> (32 - HASH_BITS)) as usize
+}
+
+impl Window {
+    fn new() -> Self { Window { buffer: [FILL; N], position: N_F } }
+
+    #[inline]
+    fn push(&mut self, c: u8) {
+        self.buffer[self.position] = c;
+        self.position = (self.position + 1) & (N - 1);
+    }
+
+    /// Validates the back-reference stored in the given pair of bytes, returning where in the
+    /// window it starts and how many bytes it covers.
+    fn back_reference(&self, low: u8, high: u8, offset: usize, decoded: usize, capacity: usize) -> Result<(usize, usize), LzssError> {
+        //A distance of zero points at the slot about to be overwritten, which is a full window back.
+        let distance = match low as usize | ((high as usize & 0xf0) << 4) {
+            0 => N,
+            distance => distance
+        };
+        let run = (high as usize & 0x0f) + THRESHOLD + 1;
+        if distance > decoded + N_F {
+            return Err(LzssError::InvalidBackReference { offset, distance })
+        }
+        if run > capacity - decoded {
+            return Err(LzssError::OutputOverflow { offset, length: run, capacity })
+        }
+
+        Ok(((self.position + N - distance) & (N - 1), run))
+    }
 }
 
-impl Context {
+impl<R: Read> LzssReader<R> {
+    pub fn new(reader: R, length: usize) -> Self {
+        LzssReader {
+            reader,
+            checksum: None,
+            window: Window::new(),
+            flags: 0,
+            consumed: 0,
+            decoded: 0,
+            length,
+            run_from: 0,
+            run_left: 0,
+        }
+    }
+
+    /// Expects a checksum trailer after the compressed data, it is verified once the last byte has
+    /// been decoded.
+    pub fn with_checksum(mut self, checksum: LzssChecksum) -> Self {
+        self.checksum = Some((checksum, 0));
+        self
+    }
 
+    /// How many bytes are left to be decoded.
+    pub fn remaining(&self) -> usize { self.length - self.decoded }
 
-    fn insert_node(&mut self, node: usize) {
-        let mut cmp = 1;
-        self.match_length = 0;
-        let mut p = N + 1 + self.buffer[node] as usize;
+    /// How many compressed bytes were read so far.
+    pub fn consumed(&self) -> usize { self.consumed }
+
+    pub fn into_inner(self) -> R { self.reader }
+
+    fn next_input(&mut self) -> io::Result<u8> {
+        let mut byte = [0u8; 1];
         loop {
-            if cmp >= 0 {
-                if self.next_children[p] == NIL {
-                    self.next_children[p] = node;
-                    self.parents[node] = p;
-                    return
-                }
+            match self.reader.read(&mut byte) {
+                Ok(0) => return Err(LzssError::TruncatedInput { read: self.consumed, missing: self.remaining() }.into()),
+                Ok(_) => {
+                    self.consumed += 1;
+                    return Ok(byte[0])
+                },
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e)
+            }
+        }
+    }
+}
 
-                p = self.next_children[p]
-            } else {
-                if self.previous_children[p] == NIL {
-                    self.previous_children[p] = node;
-                    self.parents[node] = p;
-                    return;
-                }
+impl<R: Read> Read for LzssReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut written = 0;
+        while written < buf.len() && self.decoded < self.length {
+            if self.run_left > 0 {
+                let c = self.window.buffer[self.run_from];
+                self.run_from = (self.run_from + 1) & (N - 1);
+                self.run_left -= 1;
+                self.window.push(c);
+                self.decoded += 1;
+                buf[written] = c;
+                written += 1;
+                continue
+            }
 
-                p = self.previous_children[p];
+            self.flags >>= 1;
+            if self.flags & 0x100 == 0 {
+                self.flags = self.next_input()? as u32 | 0xFF00;
             }
 
-            let i = (1..F)
-                .find(|&i| {
-                    cmp = self.buffer[node + i] as isize - self.buffer[p + i] as isize;
-                    cmp != 0
-                })
-                .unwrap_or(F);
-
-            if i > self.match_length {
-                self.match_position = p;
-                self.match_length = i;
-                if self.match_length >= F { break }
+            if self.flags & 1 != 0 {
+                let c = self.next_input()?;
+                self.window.push(c);
+                self.decoded += 1;
+                buf[written] = c;
+                written += 1;
+                continue
             }
+
+            let offset = self.consumed;
+            let low = self.next_input()?;
+            let high = self.next_input()?;
+            (self.run_from, self.run_left) = self.window.back_reference(low, high, offset, self.decoded, self.length)?;
         }
 
-        self.parents[node] = self.parents[p];
-        self.previous_children[node] = self.previous_children[p];
-        self.next_children[node] = self.next_children[p];
-        self.parents[self.previous_children[p]] = node;
-        self.parents[self.next_children[p]] = node;
+        //The checksum is dropped once verified, so the trailer is only read once.
+        if let Some((checksum, sum)) = self.checksum {
+            let sum = checksum.update(sum, &buf[..written]);
+            self.checksum = Some((checksum, sum));
+            if self.decoded == self.length {
+                let mut trailer = [0u8; 4];
+                for byte in trailer.iter_mut() {
+                    *byte = self.next_input()?;
+                }
 
-        if self.next_children[self.parents[p]] == p {
-            self.next_children[self.parents[p]] = node
-        } else {
-            self.previous_children[self.parents[p]] = node
+                let expected = u32::from_le_bytes(trailer);
+                if expected != sum {
+                    return Err(LzssError::ChecksumMismatch { expected, actual: sum }.into())
+                }
+                self.checksum = None;
+            }
         }
 
-        self.parents[p] = NIL;
+        Ok(written)
     }
+}
 
-    fn delete_node(&mut self, node: usize) {
-        if self.parents[node] == NIL {
-            return;
+impl<W: Write> LzssWriter<W> {
+    pub fn new(writer: W) -> Self {
+        LzssWriter {
+            writer,
+            checksum: None,
+            encoder: LzssEncoder::default(),
+            buffer: vec![],
+            base: 0,
+            output: vec![],
         }
+    }
 
-        let q = if self.next_children[node] == NIL {
-            self.previous_children[node]
-        } else if self.previous_children[node] == NIL {
-            self.next_children[node]
-        } else {
-            let mut q = self.previous_children[node];
-            if self.next_children[q] !=  NIL {
-                while self.next_children[q] != NIL {
-                    q = self.next_children[q]
-                }
+    /// Appends a checksum of everything written once the stream is finished.
+    pub fn with_checksum(mut self, checksum: LzssChecksum) -> Self {
+        self.checksum = Some((checksum, 0));
+        self
+    }
+
+    /// Replaces the encoder, for example to pick a different level or to reuse its tables.
+    pub fn with_encoder(mut self, mut encoder: LzssEncoder) -> Self {
+        encoder.reset();
+        self.encoder = encoder;
+        self
+    }
+
+    /// Encodes the remaining buffered input and returns the wrapped writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.encoder.compress(&self.buffer, self.base, true, &mut self.output);
+        if let Some((_, sum)) = self.checksum {
+            self.output.extend_from_slice(&sum.to_le_bytes());
+        }
+        self.write_output()?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
 
-                self.next_children[self.parents[q]] = self.previous_children[q];
-                self.parents[self.previous_children[q]] = self.parents[q];
-                self.previous_children[q] = self.previous_children[node];
-                self.parents[self.previous_children[node]] = q;
+    /// Hands the compressed bytes produced so far to the wrapped writer, keeping whatever it did
+    /// not accept for the next attempt.
+    fn write_output(&mut self) -> io::Result<()> {
+        while !self.output.is_empty() {
+            match self.writer.write(&self.output) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(written) => { self.output.drain(..written); },
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {},
+                Err(error) => return Err(error)
             }
+        }
+        Ok(())
+    }
+}
 
-            self.next_children[q] = self.next_children[node];
-            self.parents[self.next_children[node]] = q;
+impl<W: Write> Write for LzssWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        //Output of earlier input goes first, this input is only taken once it went through.
+        self.write_output()?;
+        self.buffer.extend_from_slice(buf);
+        if self.base + self.buffer.len() - self.encoder.position >= WRITER_BLOCK {
+            self.encoder.compress(&self.buffer, self.base, false, &mut self.output);
+
+            //Only a window of history has to be kept for future matches.
+            let base = self.encoder.position.saturating_sub(N).max(self.base);
+            self.buffer.drain(..base - self.base);
+            self.base = base;
+        }
+        if let Some((checksum, sum)) = self.checksum {
+            self.checksum = Some((checksum, checksum.update(sum, buf)));
+        }
+        Ok(buf.len())
+    }
 
-            q
-        };
+    /// Flushes the compressed bytes produced so far, input is encoded in blocks so recent input is
+    /// held back until more input or `finish` decides how it is encoded.
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_output()?;
+        self.writer.flush()
+    }
+}
+
+impl From<LzssError> for io::Error {
+    fn from(value: LzssError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEVELS: [LzssLevel; 3] = [LzssLevel::Fastest, LzssLevel::Default, LzssLevel::Best];
+
+    fn noise(seed: u64, length: usize) -> Vec<u8> {
+        let mut state = seed;
+        (0..length).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 24) as u8
+        }).collect()
+    }
+
+    fn assert_round_trip(encoder: &mut LzssEncoder, input: &[u8]) -> Vec<u8> {
+        let encoded = encoder.encode(input);
+        assert_eq!(decode(&encoded, input.len()).unwrap(), input, "{:?} failed on {} bytes", encoder.level(), input.len());
+        encoded
+    }
+
+    /// The high nibble of a distance goes in the top of the second byte of a reference, so
+    /// references reaching further back than 255 bytes used to point at the wrong data.
+    #[test]
+    fn far_references() {
+        for distance in [256, 257, 1000, 2048, MAX_DISTANCE - F] {
+            let mut input = noise(distance as u64, distance);
+            input.extend_from_within(..F * 4);
+            for level in LEVELS {
+                let encoded = assert_round_trip(&mut LzssEncoder::new(level), &input);
+                assert!(encoded.len() < input.len() + input.len() / 8 - F * 2);
+            }
+        }
+    }
+
+    /// A match running into the end of the input is cut short, the encoder used to keep reading
+    /// past the input and emit references covering data that was never there.
+    #[test]
+    fn matches_ending_with_input() {
+        let pattern = b"abcdefgh".repeat(8);
+        for length in 0..pattern.len() {
+            for level in LEVELS {
+                assert_round_trip(&mut LzssEncoder::new(level), &pattern[..length]);
+            }
+        }
+    }
 
-        self.parents[q] = self.parents[node];
+    /// Accepts a few bytes at a time and, while flaky, fails every other call.
+    struct FlakyWriter {
+        written: Vec<u8>,
+        calls:   usize,
+        flaky:   bool,
+    }
 
-        if self.next_children[self.parents[node]] == node {
-            self.next_children[self.parents[node]] = q
-        } else {
-            self.previous_children[self.parents[node]] = q;
+    impl Write for FlakyWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.calls += 1;
+            if self.flaky && self.calls.is_multiple_of(2) {
+                return Err(io::Error::other("congested"))
+            }
+            let length = buf.len().min(7);
+            self.written.extend_from_slice(&buf[..length]);
+            Ok(length)
         }
 
-        self.parents[node] = NIL;
+        fn flush(&mut self) -> io::Result<()> { Ok(()) }
     }
 
-    fn new() -> Self {
-        Context {
-            match_position: 0,
-            match_length: 0,
-            previous_children: [NIL; N + 1],
-            next_children: [NIL; N + 257],
-            parents: [NIL; N + 1],
-            buffer: [FILL; N + F - 1],
+    #[test]
+    fn writer_retries_after_errors() {
+        let input = noise(7, WRITER_BLOCK * 3);
+        let mut writer = LzssWriter::new(FlakyWriter { written: vec![], calls: 0, flaky: true })
+            .with_checksum(LzssChecksum::Unsigned);
+        for chunk in input.chunks(5000) {
+            let mut remaining = chunk;
+            while !remaining.is_empty() {
+                if let Ok(written) = writer.write(remaining) {
+                    remaining = &remaining[written..];
+                }
+            }
         }
+        while writer.flush().is_err() {}
+        writer.writer.flaky = false;
+        let compressed = writer.finish().unwrap().written;
+
+        assert_eq!(decode_with_checksum(&compressed, input.len(), LzssChecksum::Unsigned).unwrap(), input);
     }
-}
\ No newline at end of file
+
+    /// Reusing an encoder, and slots of the window being reused once the input is longer than the
+    /// window, must not leave stale candidates behind.
+    #[test]
+    fn reused_encoder_and_window() {
+        for level in LEVELS {
+            let mut encoder = LzssEncoder::new(level);
+            for seed in 1..4 {
+                let mut input = noise(seed, N * 3);
+                input.extend_from_within(N / 2..N / 2 + N);
+                input.extend(b"repeated ".repeat(200));
+                assert_round_trip(&mut encoder, &input);
+                assert_round_trip(&mut encoder, &input[..N + F]);
+            }
+        }
+    }
+
+    fn lzss_error(error: &io::Error) -> Option<&LzssError> {
+        error.get_ref().and_then(|error| error.downcast_ref::<LzssError>())
+    }
+
+    #[test]
+    fn checksum_mismatches_are_reported() {
+        let input = b"checksummed checksummed".to_vec();
+        let mut compressed = encode_with_checksum(&input, LzssChecksum::Unsigned);
+        let last = compressed.len() - 1;
+        compressed[last] ^= 1;
+
+        let error = decode_with_checksum(&compressed, input.len(), LzssChecksum::Unsigned).unwrap_err();
+        assert!(matches!(error, LzssError::ChecksumMismatch { .. }));
+
+        let mut reader = LzssReader::new(compressed.as_slice(), input.len()).with_checksum(LzssChecksum::Unsigned);
+        let error = reader.read_to_end(&mut vec![]).unwrap_err();
+        assert!(matches!(lzss_error(&error), Some(LzssError::ChecksumMismatch { .. })));
+    }
+
+    #[test]
+    fn signed_and_unsigned_checksums_differ() {
+        let input = [0x80u8, 0xFF, 0x01];
+        let compressed = encode_with_checksum(&input, LzssChecksum::Signed);
+        assert_eq!(decode_with_checksum(&compressed, input.len(), LzssChecksum::Signed).unwrap(), input);
+        assert!(decode_with_checksum(&compressed, input.len(), LzssChecksum::Unsigned).is_err());
+    }
+
+    /// A missing trailer used to be reported only once, reading again then ended the stream as if
+    /// the checksum had been verified.
+    #[test]
+    fn missing_trailers_keep_failing() {
+        let input = noise(3, 300);
+        let compressed = encode(&input);
+
+        let mut reader = LzssReader::new(compressed.as_slice(), input.len()).with_checksum(LzssChecksum::Unsigned);
+        let mut output = vec![0; input.len()];
+        let error = reader.read(&mut output).unwrap_err();
+        assert!(matches!(lzss_error(&error), Some(LzssError::TruncatedInput { .. })));
+        assert_eq!(reader.remaining(), 0);
+        assert!(reader.read(&mut output).is_err());
+
+        let mut reader = LzssReader::new(compressed.as_slice(), input.len());
+        let mut decoded = vec![];
+        reader.read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, input);
+    }
+}
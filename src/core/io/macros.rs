@@ -1,7 +1,25 @@
+use alloc::string::String;
+use thiserror::Error;
+
+/// Returned when parsing a string that names none of the variants of a magic enum.
+#[derive(Error, Debug, Clone, Eq, PartialEq, Hash)]
+#[error("Magic Enum Error: {value} is not a valid {name}.")]
+pub struct ParseMagicError {
+    pub name:  &'static str,
+    pub value: String,
+}
+
 /// This macro generates a new type enum that is statically convertible to and from a provided base
 /// type. It also generates a TryFrom<T> implementation where T is the provided base type, this can
 /// be useful for checked conversion from the base type to the enum.
 ///
+/// Giving the name of a catch-all variant instead of an error type generates an open enum, values
+/// without a variant of their own are kept in the catch-all variant so they can be written back
+/// unchanged, and the conversion from the base type can no longer fail.
+///
+/// Both kinds of enum convert back into the base type, parse from variant names or numbers, list
+/// their known variants in `ALL` and are read and written as their base type in little endian.
+///
 /// # Usage
 ///
 /// magic_enum! {
@@ -10,6 +28,13 @@
 ///         ...
 ///     }
 /// }
+///
+/// magic_enum! {
+///     base_type, enum_name, unknown_variant {
+///         variant = value,
+///         ...
+///     }
+/// }
 #[macro_export]
 macro_rules! magic_enum {
     ($typ: ty, $name: ident, $error: ty, $error_variant: ident {
@@ -21,7 +46,7 @@ macro_rules! magic_enum {
             $($variant = $value),*
         }
 
-        impl std::convert::TryFrom<$typ> for $name {
+        impl ::core::convert::TryFrom<$typ> for $name {
             type Error = $error;
 
             fn try_from(value: $typ) -> Result<Self, Self::Error> {
@@ -32,14 +57,117 @@ macro_rules! magic_enum {
             }
         }
 
-        impl std::fmt::Display for $name {
-            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        impl From<$name> for $typ {
+            fn from(value: $name) -> Self { value as $typ }
+        }
+
+        impl ::core::fmt::Display for $name {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
                 match self {
                     $( $name::$variant => write!(f, stringify!($variant)), )*
                 }
             }
         }
-    }
-}
 
+        impl ::core::str::FromStr for $name {
+            type Err = $crate::ParseMagicError;
 
+            fn from_str(value: &str) -> Result<Self, Self::Err> {
+                let error = || $crate::ParseMagicError { name: stringify!($name), value: $crate::__private::String::from(value) };
+                match value {
+                    $( stringify!($variant) => Ok($name::$variant), )*
+                    _ => value.parse::<$typ>().ok()
+                        .and_then(|value| <$name as ::core::convert::TryFrom<$typ>>::try_from(value).ok())
+                        .ok_or_else(error)
+                }
+            }
+        }
+
+        impl<R: $crate::core::io::stream::Read + ?Sized> $crate::Debinarizable<R> for $name {
+            type Error = $crate::core::io::stream::Error;
+
+            fn debinarize(reader: &mut R) -> Result<Self, Self::Error> {
+                let value = <$typ as $crate::Debinarizable<R>>::debinarize(reader)?;
+                <$name as ::core::convert::TryFrom<$typ>>::try_from(value)
+                    .map_err(|_| $crate::invalid_magic(stringify!($name), value))
+            }
+        }
+
+        $crate::magic_enum!(@common $typ, $name { $($variant),* });
+    };
+    ($typ: ty, $name: ident, $unknown: ident {
+        $($variant: ident = $value:expr),* $(,)?
+    }) => {
+        #[derive(Debug, Clone, Hash, Copy, PartialEq, Eq)]
+        pub enum $name {
+            $($variant,)*
+            $unknown($typ)
+        }
+
+        impl From<$typ> for $name {
+            fn from(value: $typ) -> Self {
+                match value {
+                    $($value => $name::$variant,)*
+                    _ => $name::$unknown(value),
+                }
+            }
+        }
+
+        impl From<$name> for $typ {
+            fn from(value: $name) -> Self {
+                match value {
+                    $( $name::$variant => $value, )*
+                    $name::$unknown(value) => value,
+                }
+            }
+        }
+
+        impl ::core::fmt::Display for $name {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                match self {
+                    $( $name::$variant => write!(f, stringify!($variant)), )*
+                    $name::$unknown(value) => write!(f, "{}({})", stringify!($unknown), value),
+                }
+            }
+        }
+
+        impl ::core::str::FromStr for $name {
+            type Err = $crate::ParseMagicError;
+
+            fn from_str(value: &str) -> Result<Self, Self::Err> {
+                let error = || $crate::ParseMagicError { name: stringify!($name), value: $crate::__private::String::from(value) };
+                let number = value.strip_prefix(concat!(stringify!($unknown), "("))
+                    .and_then(|value| value.strip_suffix(')'))
+                    .unwrap_or(value);
+                match value {
+                    $( stringify!($variant) => Ok($name::$variant), )*
+                    _ => number.parse::<$typ>().map($name::from).map_err(|_| error())
+                }
+            }
+        }
+
+        impl<R: $crate::core::io::stream::Read + ?Sized> $crate::Debinarizable<R> for $name {
+            type Error = $crate::core::io::stream::Error;
+
+            fn debinarize(reader: &mut R) -> Result<Self, Self::Error> {
+                Ok($name::from(<$typ as $crate::Debinarizable<R>>::debinarize(reader)?))
+            }
+        }
+
+        $crate::magic_enum!(@common $typ, $name { $($variant),* });
+    };
+    (@common $typ: ty, $name: ident { $($variant: ident),* }) => {
+        impl $name {
+            /// Every known variant, in declaration order.
+            pub const ALL: &'static [$name] = &[$($name::$variant),*];
+        }
+
+        impl<W: $crate::core::io::stream::Write + ?Sized> $crate::Binarizable<W> for $name {
+            type Error = $crate::core::io::stream::Error;
+
+            fn binarize(&self, writer: &mut W) -> Result<(), Self::Error> {
+                <$typ as $crate::Binarizable<W>>::binarize(&<$typ>::from(*self), writer)
+            }
+        }
+    };
+}
@@ -1,4 +1,12 @@
+pub mod stream;
 pub mod binarizable; pub use binarizable::*;
 pub mod lzss;        pub use lzss::*;
-pub mod read;        pub use read::*;
+pub mod lzo;         pub use lzo::LzoError;
+pub mod codec;       pub use codec::*;
+pub mod context;     pub use context::*;
+pub mod primitives;  pub use primitives::*;
+#[cfg(feature = "std")]
+pub mod read;
+#[cfg(feature = "std")]
+pub use read::*;
 pub mod macros;      pub use macros::*;
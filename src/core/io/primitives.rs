@@ -0,0 +1,272 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use byteorder::{ByteOrder, LittleEndian};
+use crate::core::io::stream::{self as io, Cursor, Read, ReadBytesExt, Write, WriteBytesExt};
+use crate::core::io::lzo;
+use crate::core::io::lzss::{LzssChecksum, LzssReader, LzssWriter};
+
+/// The most bytes a compressed integer takes up.
+const MAX_COMPRESSED_INT_LENGTH: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Vector3P {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+/// A 3x4 transform, three orientation axes followed by a position.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Matrix4P {
+    pub orientation: [Vector3P; 3],
+    pub position:    Vector3P,
+}
+
+/// A colour packed into a single 32 bit integer as ARGB.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub struct PackedColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+/// How the data of a compressed array is stored.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum ArrayCompression {
+    #[default]
+    Stored,
+    /// LZSS compressed when the data takes up at least `threshold` bytes.
+    Lzss { threshold: usize, checksum: Option<LzssChecksum> },
+    /// LZO compressed when the data takes up at least `threshold` bytes. Flagged arrays are
+    /// preceded by a byte saying whether they are compressed instead.
+    Lzo { threshold: usize, flagged: bool },
+}
+
+impl PackedColor {
+    pub fn from_argb(argb: u32) -> Self {
+        let [b, g, r, a] = argb.to_le_bytes();
+        Self { r, g, b, a }
+    }
+
+    pub fn argb(&self) -> u32 { u32::from_le_bytes([self.b, self.g, self.r, self.a]) }
+}
+
+/// Reads the primitives shared by the engine formats.
+pub trait BisReadExt: Read {
+    /// Reads a little endian 32 bit integer, the integer most of the engine formats are made of.
+    fn read_i32_le(&mut self) -> io::Result<i32> {
+        self.read_i32::<LittleEndian>()
+    }
+
+    /// Reads a null terminated string, invalid characters are replaced.
+    fn read_asciiz(&mut self) -> io::Result<String> {
+        Ok(String::from_utf8_lossy(&self.read_asciiz_bytes(usize::MAX)?).into_owned())
+    }
+
+    /// Reads the bytes of a null terminated string without the terminator, failing when no
+    /// terminator is found within `max_length` bytes.
+    fn read_asciiz_bytes(&mut self, max_length: usize) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        loop {
+            match self.read_u8()? {
+                0 => return Ok(bytes),
+                _ if bytes.len() == max_length => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("The string is longer than {max_length} bytes.")))
+                },
+                byte => bytes.push(byte)
+            }
+        }
+    }
+
+    /// Reads an integer stored seven bits at a time, low bits first, where the high bit of every
+    /// byte says whether another one follows.
+    fn read_compressed_int(&mut self) -> io::Result<u32> {
+        let mut value = 0u32;
+        for index in 0..MAX_COMPRESSED_INT_LENGTH {
+            let byte = self.read_u8()?;
+            let bits = (byte & 0x7F) as u32;
+            if index == MAX_COMPRESSED_INT_LENGTH - 1 && bits > u32::MAX >> 28 {
+                break
+            }
+            value |= bits << (7 * index);
+            if byte & 0x80 == 0 {
+                return Ok(value)
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::InvalidData, "The compressed integer overflows 32 bits."))
+    }
+
+    fn read_vector3p<B: ByteOrder>(&mut self) -> io::Result<Vector3P> {
+        Ok(Vector3P { x: self.read_f32::<B>()?, y: self.read_f32::<B>()?, z: self.read_f32::<B>()? })
+    }
+
+    fn read_matrix4p<B: ByteOrder>(&mut self) -> io::Result<Matrix4P> {
+        Ok(Matrix4P {
+            orientation: [self.read_vector3p::<B>()?, self.read_vector3p::<B>()?, self.read_vector3p::<B>()?],
+            position: self.read_vector3p::<B>()?,
+        })
+    }
+
+    /// Reads a string prefixed by its length as a compressed integer.
+    fn read_rstring(&mut self) -> io::Result<String> {
+        let length = self.read_compressed_int()? as usize;
+        let mut bytes = Vec::new();
+        (&mut *self).take(length as u64).read_to_end(&mut bytes)?;
+        match bytes.len() == length {
+            true => Ok(String::from_utf8_lossy(&bytes).into_owned()),
+            false => Err(io::ErrorKind::UnexpectedEof.into())
+        }
+    }
+
+    fn read_packed_color<B: ByteOrder>(&mut self) -> io::Result<PackedColor> {
+        Ok(PackedColor::from_argb(self.read_u32::<B>()?))
+    }
+
+    /// Reads data that unpacks to exactly `length` bytes. The length is not trusted, nothing is
+    /// allocated for it up front.
+    fn read_compressed_data(&mut self, length: usize, compression: ArrayCompression) -> io::Result<Vec<u8>> {
+        let compressed = match compression {
+            ArrayCompression::Stored => false,
+            ArrayCompression::Lzss { threshold, .. } => length >= threshold,
+            ArrayCompression::Lzo { flagged: true, .. } => self.read_u8()? != 0,
+            ArrayCompression::Lzo { threshold, .. } => length >= threshold,
+        };
+
+        let mut data = Vec::new();
+        match compression {
+            ArrayCompression::Lzss { checksum, .. } if compressed => {
+                let mut reader = LzssReader::new(&mut *self, length);
+                if let Some(checksum) = checksum {
+                    reader = reader.with_checksum(checksum);
+                }
+                reader.read_to_end(&mut data)?;
+            },
+            ArrayCompression::Lzo { .. } if compressed => data = lzo::decode_from(&mut *self, length)?,
+            _ => { (&mut *self).take(length as u64).read_to_end(&mut data)?; }
+        }
+
+        match data.len() == length {
+            true => Ok(data),
+            false => Err(io::ErrorKind::UnexpectedEof.into())
+        }
+    }
+
+    /// Reads an element count followed by the data of that many elements of `element_size` bytes,
+    /// which are then read from the unpacked data one at a time.
+    fn read_compressed_array<B: ByteOrder, T>(
+        &mut self,
+        element_size: usize,
+        compression: ArrayCompression,
+        mut read_element: impl FnMut(&mut Cursor<Vec<u8>>) -> io::Result<T>
+    ) -> io::Result<Vec<T>> {
+        let count = self.read_u32::<B>()? as usize;
+        let length = count.checked_mul(element_size)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("{count} elements of {element_size} bytes overflow.")))?;
+
+        let data = self.read_compressed_data(length, compression)?;
+        let mut items = Vec::with_capacity(count.min(data.len()));
+        let mut cursor = Cursor::new(data);
+        for _ in 0..count {
+            items.push(read_element(&mut cursor)?);
+        }
+        Ok(items)
+    }
+}
+
+/// Writes the primitives shared by the engine formats.
+pub trait BisWriteExt: Write {
+    fn write_i32_le(&mut self, value: i32) -> io::Result<()> {
+        self.write_i32::<LittleEndian>(value)
+    }
+
+    fn write_asciiz(&mut self, value: &str) -> io::Result<()> {
+        if value.as_bytes().contains(&0) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("\"{value}\" contains a null character.")))
+        }
+        self.write_all(value.as_bytes())?;
+        self.write_u8(0)
+    }
+
+    fn write_compressed_int(&mut self, mut value: u32) -> io::Result<()> {
+        while value >= 0x80 {
+            self.write_u8((value & 0x7F) as u8 | 0x80)?;
+            value >>= 7;
+        }
+        self.write_u8(value as u8)
+    }
+
+    fn write_vector3p<B: ByteOrder>(&mut self, value: &Vector3P) -> io::Result<()> {
+        self.write_f32::<B>(value.x)?;
+        self.write_f32::<B>(value.y)?;
+        self.write_f32::<B>(value.z)
+    }
+
+    fn write_matrix4p<B: ByteOrder>(&mut self, value: &Matrix4P) -> io::Result<()> {
+        for axis in &value.orientation {
+            self.write_vector3p::<B>(axis)?;
+        }
+        self.write_vector3p::<B>(&value.position)
+    }
+
+    fn write_rstring(&mut self, value: &str) -> io::Result<()> {
+        let length = u32::try_from(value.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "The string is too long to be stored."))?;
+        self.write_compressed_int(length)?;
+        self.write_all(value.as_bytes())
+    }
+
+    fn write_packed_color<B: ByteOrder>(&mut self, value: &PackedColor) -> io::Result<()> {
+        self.write_u32::<B>(value.argb())
+    }
+
+    /// Writes the data, compressed when the compression asks for it.
+    fn write_compressed_data(&mut self, data: &[u8], compression: ArrayCompression) -> io::Result<()> {
+        match compression {
+            ArrayCompression::Lzss { threshold, checksum } if data.len() >= threshold => {
+                let mut writer = LzssWriter::new(&mut *self);
+                if let Some(checksum) = checksum {
+                    writer = writer.with_checksum(checksum);
+                }
+                writer.write_all(data)?;
+                writer.finish()?;
+                Ok(())
+            },
+            ArrayCompression::Lzo { threshold, flagged } => {
+                let compressed = data.len() >= threshold;
+                if flagged {
+                    self.write_u8(compressed as u8)?;
+                }
+                match compressed {
+                    true => self.write_all(&lzo::encode(data)),
+                    false => self.write_all(data)
+                }
+            },
+            _ => self.write_all(data)
+        }
+    }
+
+    /// Writes an element count followed by the data of the elements, each written through
+    /// `write_element` first.
+    fn write_compressed_array<B: ByteOrder, T>(
+        &mut self,
+        items: &[T],
+        compression: ArrayCompression,
+        mut write_element: impl FnMut(&mut Vec<u8>, &T) -> io::Result<()>
+    ) -> io::Result<()> {
+        let count = u32::try_from(items.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("{} elements do not fit a u32 count.", items.len())))?;
+
+        let mut data = Vec::new();
+        for item in items {
+            write_element(&mut data, item)?;
+        }
+        self.write_u32::<B>(count)?;
+        self.write_compressed_data(&data, compression)
+    }
+}
+
+impl<R: Read + ?Sized> BisReadExt for R {}
+
+impl<W: Write + ?Sized> BisWriteExt for W {}
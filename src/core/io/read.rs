@@ -1,59 +1,131 @@
+use std::cell::Cell;
+use std::fmt;
 use std::io;
-use std::io::{Read, Seek, SeekFrom};
-const BUFF_SIZE: usize = 512;
-
-pub enum PredicateOption {
-    Skip,
-    Continue,
-    Exit,
-    Err(io::Error)
+use std::io::{BufRead, Read, Seek, SeekFrom};
+const DEFAULT_CAPACITY: usize = 8 * 1024;
+
+/// A position in the input. Lines and columns start at one, columns count bytes.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Location {
+    pub offset: u64,
+    pub line:   usize,
+    pub column: usize,
 }
 
-pub struct BufferedReader<R: Read + Seek> {
-    reader:                 R,
-    buffer_start:           usize, //the index in our reader where buffer starts
-    buffer_index:           usize, // will never be longer than BUFF_SIZE
-    buffer:                 [u8; BUFF_SIZE],
-    actual_buffer_length:   usize //the amount of bytes loaded into the buffer on last read
+/// Buffers a reader so that bytes can be looked at before they are consumed and given back after.
+///
+/// Up to `capacity` bytes are read at a time, and as many bytes behind the current position are
+/// kept so they can be ungotten. Marked positions are kept until the mark is reset or released.
+/// When the reader is seekable, seeking back past the kept bytes reads the input again from the
+/// closest position whose location is known, so line and column stay accurate.
+pub struct BufferedReader<R: Read> {
+    reader:      R,
+    buffer:      Vec<u8>,
+    base:        Location, //the location of the first byte in the buffer
+    index:       usize,
+    capacity:    usize,
+    marks:       Vec<u64>,
+    checkpoints: Vec<Location>,
+    located:     Cell<(usize, Location)>, //the last index whose location was asked for
 }
 
-impl<R: Read + Seek> BufferedReader<R> {
+impl Location {
+    pub const START: Location = Location { offset: 0, line: 1, column: 1 };
+
+    fn advance(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.offset += 1;
+            match byte {
+                b'\n' => {
+                    self.line += 1;
+                    self.column = 1;
+                },
+                _ => self.column += 1
+            }
+        }
+    }
+}
+
+impl Default for Location {
+    fn default() -> Self { Self::START }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
 
+impl<R: Read> BufferedReader<R> {
+    /// Wraps a reader positioned at the start of its input.
     pub fn new(reader: R) -> BufferedReader<R> {
-        let mut r = BufferedReader {
+        Self::with_capacity(DEFAULT_CAPACITY, reader)
+    }
+
+    pub fn with_capacity(capacity: usize, reader: R) -> BufferedReader<R> {
+        BufferedReader {
             reader,
-            buffer_start: 0,
-            buffer_index: 0,
-            buffer: [0; BUFF_SIZE],
-            actual_buffer_length: 0,
-        };
-        r.load_buffer().unwrap();
-        r
-    }
-
-    fn load_buffer(&mut self) -> Result<(), io::Error> {
-        self.reader.seek(SeekFrom::Start(self.buffer_start as u64))?;
-        self.actual_buffer_length = self.reader.read(&mut self.buffer)?;
-        self.buffer_index = 0;
-        Ok(())
+            buffer: Vec::new(),
+            base: Location::START,
+            index: 0,
+            capacity: capacity.max(1),
+            marks: vec![],
+            checkpoints: vec![],
+            located: Cell::new((0, Location::START)),
+        }
     }
 
+    pub fn capacity(&self) -> usize { self.capacity }
 
-    pub fn pos(&self) -> usize { self.buffer_start + self.buffer_index }
+    pub fn get_ref(&self) -> &R { &self.reader }
 
-    pub fn get(&mut self) -> Result<u8, io::Error> {
-        if self.buffer_index >= self.actual_buffer_length {
-            self.buffer_start += self.actual_buffer_length;
-            self.load_buffer()?;
-        }
+    /// The inner reader, reading from it directly skips the buffered bytes.
+    pub fn get_mut(&mut self) -> &mut R { &mut self.reader }
+
+    /// Unwraps the inner reader, any buffered bytes are lost.
+    pub fn into_inner(self) -> R { self.reader }
+
+    pub fn pos(&self) -> u64 { self.base.offset + self.index as u64 }
 
-        if self.buffer_index < self.actual_buffer_length {
-            let val = self.buffer[self.buffer_index];
-            self.buffer_index += 1;
-            return Ok(val)
+    /// The location of the next byte. Only the bytes between the current position and the one
+    /// last asked for are looked at.
+    pub fn location(&self) -> Location {
+        let (located, mut location) = self.located.get();
+        if self.index >= located {
+            location.advance(&self.buffer[located..self.index]);
+        } else {
+            let skipped = &self.buffer[self.index..located];
+            let lines = skipped.iter().filter(|&&byte| byte == b'\n').count();
+            location.offset -= skipped.len() as u64;
+            location.line -= lines;
+            location.column = match (lines, self.buffer[..self.index].iter().rposition(|&byte| byte == b'\n')) {
+                (0, _) => location.column - skipped.len(),
+                (_, Some(newline)) => self.index - newline,
+                (_, None) => self.base.column + self.index
+            };
         }
+        self.located.set((self.index, location));
+        location
+    }
 
-        Err(io::Error::other("Invalid position! {core::io::read::BufferedReader<R>::get(&mut self);}"))
+    /// The next `count` bytes without consuming them, fewer when the input ends before.
+    pub fn peek(&mut self, count: usize) -> Result<&[u8], io::Error> {
+        let available = self.fill(count)?.min(count);
+        Ok(&self.buffer[self.index..self.index + available])
+    }
+
+    pub fn peek_byte(&mut self) -> Result<Option<u8>, io::Error> {
+        Ok(self.peek(1)?.first().copied())
+    }
+
+    pub fn get(&mut self) -> Result<u8, io::Error> {
+        match self.fill(1)? {
+            0 => Err(io::ErrorKind::UnexpectedEof.into()),
+            _ => {
+                self.index += 1;
+                Ok(self.buffer[self.index - 1])
+            }
+        }
     }
 
     #[inline]
@@ -63,25 +135,251 @@ impl<R: Read + Seek> BufferedReader<R> {
         Ok(current)
     }
 
+    /// Gives back the last `count` bytes, which have to still be buffered.
+    pub fn unget(&mut self, count: usize) -> Result<(), io::Error> {
+        if count > self.index {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Cannot unget {count} bytes, only {} are kept.", self.index)))
+        }
+        self.index -= count;
+        Ok(())
+    }
 
+    /// Remembers the current position, everything read after it is kept until the mark is reset
+    /// or released. Marks nest, the last one made is the first one reset.
+    pub fn mark(&mut self) {
+        self.marks.push(self.pos());
+    }
 
-    pub fn unget(&mut self) -> Result<(), io::Error>{
-        return if self.buffer_index == 0 {
-            if self.buffer_start <= 0 {
-                Err(io::Error::other("At start of stream cannot unget. {core::io::read::BufferedReader<R>::unget(&mut self);}"))
-            } else {
-                self.buffer_start -= std::cmp::min(self.buffer_start, BUFF_SIZE);
-                self.load_buffer()?;
-                Ok(self.buffer_index = self.actual_buffer_length)
+    /// Returns to the last mark and removes it.
+    pub fn reset(&mut self) -> Result<(), io::Error> {
+        let offset = self.marks.pop()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "There is no mark to reset to."))?;
+        self.index = (offset - self.base.offset) as usize;
+        Ok(())
+    }
+
+    /// Removes the last mark without moving.
+    pub fn release(&mut self) -> Result<(), io::Error> {
+        match self.marks.pop() {
+            Some(_) => Ok(()),
+            None => Err(io::Error::new(io::ErrorKind::InvalidInput, "There is no mark to release."))
+        }
+    }
+
+    /// Reads until at least `wanted` bytes are buffered after the current position or the input
+    /// ends, returning how many are.
+    fn fill(&mut self, wanted: usize) -> Result<usize, io::Error> {
+        while self.buffer.len() - self.index < wanted {
+            self.compact();
+            let length = self.buffer.len();
+            let chunk = self.capacity.max(wanted - (length - self.index));
+            self.buffer.resize(length + chunk, 0);
+            let read = loop {
+                match self.reader.read(&mut self.buffer[length..]) {
+                    Ok(read) => break read,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => {
+                        self.buffer.truncate(length);
+                        return Err(e)
+                    }
+                }
+            };
+            self.buffer.truncate(length + read);
+            if read == 0 {
+                break
             }
-        } else if self.buffer_start > 0 {
-            Ok(self.buffer_index -= 1)
-        } else {
-            panic!("Buffer index is less than 0; this is impossible! You broke something :(")
+        }
+        Ok(self.buffer.len() - self.index)
+    }
+
+    /// Drops the bytes that no longer have to be kept, remembering the location they end at.
+    fn compact(&mut self) {
+        let mut discard = self.index.saturating_sub(self.capacity);
+        if let Some(&earliest) = self.marks.iter().min() {
+            discard = discard.min((earliest - self.base.offset) as usize);
+        }
+        if discard == 0 {
+            return
         }
 
+        self.base.advance(&self.buffer[..discard]);
+        self.buffer.drain(..discard);
+        self.index -= discard;
+        self.located.set(match self.located.get() {
+            (located, location) if located >= discard => (located - discard, location),
+            _ => (0, self.base)
+        });
+        if self.checkpoints.last().is_none_or(|last| last.offset < self.base.offset) {
+            self.checkpoints.push(self.base);
+        }
     }
+}
 
+impl<R: Read> Read for BufferedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        self.consume(count);
+        Ok(count)
+    }
 }
 
+impl<R: Read> BufRead for BufferedReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.fill(1)?;
+        Ok(&self.buffer[self.index..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.index = (self.index + amt).min(self.buffer.len());
+    }
+}
+
+impl<R: Read + Seek> Seek for BufferedReader<R> {
+    /// Seeks within the buffered bytes where possible. Seeking back past them drops every mark.
+    /// Seeking past the end of the input is an error, as the location there is unknown.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.pos().checked_add_signed(delta),
+            SeekFrom::End(delta) => {
+                let end = self.reader.seek(SeekFrom::End(0))?;
+                self.reader.seek(SeekFrom::Start(self.base.offset + self.buffer.len() as u64))?;
+                end.checked_add_signed(delta)
+            }
+        }.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Cannot seek to a negative or overflowing position."))?;
+
+        if target < self.base.offset {
+            let checkpoint = match self.checkpoints.partition_point(|checkpoint| checkpoint.offset <= target) {
+                0 => Location::START,
+                index => self.checkpoints[index - 1]
+            };
+            self.reader.seek(SeekFrom::Start(checkpoint.offset))?;
+            self.buffer.clear();
+            self.marks.clear();
+            self.index = 0;
+            self.base = checkpoint;
+            self.located.set((0, checkpoint));
+        }
+
+        while self.pos() < target {
+            let available = self.fill(1)?;
+            if available == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("Cannot seek past the end of the input at {}.", self.pos())))
+            }
+            self.consume(available.min((target - self.pos()) as usize));
+        }
+        self.index = (target - self.base.offset) as usize;
+        Ok(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const TEXT: &[u8] = b"class A {\n    x = 1;\n};\n\nclass B {};\n";
+
+    fn reader(capacity: usize) -> BufferedReader<Cursor<&'static [u8]>> {
+        BufferedReader::with_capacity(capacity, Cursor::new(TEXT))
+    }
+
+    /// The location of every offset, counted from the start.
+    fn expected(offset: usize) -> Location {
+        let mut location = Location::START;
+        location.advance(&TEXT[..offset]);
+        location
+    }
+
+    #[test]
+    fn locations_follow_reads_and_ungets() {
+        let mut reader = reader(4);
+        for (offset, &byte) in TEXT.iter().enumerate() {
+            assert_eq!(reader.location(), expected(offset));
+            assert_eq!(reader.get().unwrap(), byte);
+        }
+        assert_eq!(reader.location(), expected(TEXT.len()));
+        assert_eq!(reader.location(), Location { offset: TEXT.len() as u64, line: 6, column: 1 });
+
+        for back in 1..=4 {
+            reader.unget(1).unwrap();
+            assert_eq!(reader.location(), expected(TEXT.len() - back));
+        }
+        reader.unget(0).unwrap();
+        assert_eq!(reader.location(), expected(TEXT.len() - 4));
+    }
+
+    #[test]
+    fn ungets_are_limited_to_the_kept_bytes() {
+        let mut reader = reader(4);
+        assert!(reader.unget(1).is_err());
+        for _ in 0..TEXT.len() {
+            reader.get().unwrap();
+        }
+        assert!(matches!(reader.get(), Err(e) if e.kind() == io::ErrorKind::UnexpectedEof));
+
+        let kept = TEXT.len() - reader.base.offset as usize;
+        assert!(kept >= reader.capacity());
+        assert!(reader.unget(kept + 1).is_err());
+        assert_eq!(reader.pos(), TEXT.len() as u64);
+        reader.unget(kept).unwrap();
+        assert_eq!(reader.location(), expected(TEXT.len() - kept));
+        assert_eq!(reader.peek(3).unwrap(), &TEXT[TEXT.len() - kept..][..3]);
+    }
 
+    #[test]
+    fn marks_nest() {
+        let mut reader = reader(2);
+        reader.get().unwrap();
+        reader.mark();
+        reader.seek(SeekFrom::Current(10)).unwrap();
+        reader.mark();
+        reader.seek(SeekFrom::Current(15)).unwrap();
+
+        reader.reset().unwrap();
+        assert_eq!(reader.pos(), 11);
+        assert_eq!(reader.location(), expected(11));
+        reader.reset().unwrap();
+        assert_eq!(reader.pos(), 1);
+        assert_eq!(reader.location(), expected(1));
+        assert!(reader.reset().is_err());
+
+        reader.mark();
+        reader.get().unwrap();
+        reader.release().unwrap();
+        assert!(reader.release().is_err());
+        assert_eq!(reader.pos(), 2);
+    }
+
+    #[test]
+    fn marked_bytes_outlive_compactions() {
+        let mut reader = reader(2);
+        reader.mark();
+        let mut read = vec![];
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(read, TEXT);
+        reader.reset().unwrap();
+        assert_eq!(reader.pos(), 0);
+        assert_eq!(reader.peek(5).unwrap(), b"class");
+    }
+
+    #[test]
+    fn seeks_back_across_compactions() {
+        let mut reader = reader(2);
+        reader.seek(SeekFrom::End(0)).unwrap();
+        assert!(reader.base.offset > 0);
+        assert!(reader.checkpoints.len() > 1);
+
+        for offset in (0..TEXT.len()).rev() {
+            assert_eq!(reader.seek(SeekFrom::Start(offset as u64)).unwrap(), offset as u64);
+            assert_eq!(reader.location(), expected(offset));
+            assert_eq!(reader.get().unwrap(), TEXT[offset]);
+        }
+        assert_eq!(reader.seek(SeekFrom::End(-3)).unwrap(), TEXT.len() as u64 - 3);
+        assert_eq!(reader.location(), expected(TEXT.len() - 3));
+        assert!(reader.seek(SeekFrom::Current(-(TEXT.len() as i64))).is_err());
+        assert!(reader.seek(SeekFrom::End(1)).is_err());
+    }
+}
@@ -0,0 +1,346 @@
+//! The stream traits the format parsers are written against. With the `std` feature these are the
+//! ones from `std::io` and `byteorder`, without it minimal stand-ins covering what the parsers use.
+
+#[cfg(feature = "std")]
+pub use std::io::{Cursor, Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+#[cfg(feature = "std")]
+pub use byteorder::{ReadBytesExt, WriteBytesExt};
+
+#[cfg(not(feature = "std"))]
+pub use minimal::*;
+
+#[cfg(not(feature = "std"))]
+mod minimal {
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+    use core::fmt;
+    use byteorder::ByteOrder;
+
+    const READ_CHUNK: usize = 8 * 1024;
+
+    #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+    #[non_exhaustive]
+    pub enum ErrorKind {
+        InvalidInput,
+        InvalidData,
+        UnexpectedEof,
+        WriteZero,
+        Interrupted,
+        Unsupported,
+        Other,
+    }
+
+    #[derive(Debug)]
+    pub struct Error {
+        kind:  ErrorKind,
+        error: Option<Box<dyn core::error::Error + Send + Sync>>,
+    }
+
+    pub type Result<T> = core::result::Result<T, Error>;
+
+    #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+    pub enum SeekFrom {
+        Start(u64),
+        End(i64),
+        Current(i64),
+    }
+
+    /// Reads bytes from the underlying data, see `std::io::Read`.
+    pub trait Read {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+
+        fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<()> {
+            while !buf.is_empty() {
+                match self.read(buf) {
+                    Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                    Ok(read) => buf = &mut buf[read..],
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {},
+                    Err(e) => return Err(e)
+                }
+            }
+            Ok(())
+        }
+
+        fn read_to_end(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
+            let start = buf.len();
+            let mut chunk = [0u8; READ_CHUNK];
+            loop {
+                match self.read(&mut chunk) {
+                    Ok(0) => return Ok(buf.len() - start),
+                    Ok(read) => buf.extend_from_slice(&chunk[..read]),
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {},
+                    Err(e) => return Err(e)
+                }
+            }
+        }
+
+        fn by_ref(&mut self) -> &mut Self where Self: Sized { self }
+
+        fn take(self, limit: u64) -> Take<Self> where Self: Sized { Take { inner: self, limit } }
+    }
+
+    /// Writes bytes to the underlying data, see `std::io::Write`.
+    pub trait Write {
+        fn write(&mut self, buf: &[u8]) -> Result<usize>;
+
+        fn flush(&mut self) -> Result<()>;
+
+        fn write_all(&mut self, mut buf: &[u8]) -> Result<()> {
+            while !buf.is_empty() {
+                match self.write(buf) {
+                    Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                    Ok(written) => buf = &buf[written..],
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {},
+                    Err(e) => return Err(e)
+                }
+            }
+            Ok(())
+        }
+
+        fn by_ref(&mut self) -> &mut Self where Self: Sized { self }
+    }
+
+    /// Moves the position within the underlying data, see `std::io::Seek`.
+    pub trait Seek {
+        fn seek(&mut self, pos: SeekFrom) -> Result<u64>;
+
+        fn rewind(&mut self) -> Result<()> {
+            self.seek(SeekFrom::Start(0))?;
+            Ok(())
+        }
+
+        fn stream_position(&mut self) -> Result<u64> { self.seek(SeekFrom::Current(0)) }
+    }
+
+    /// Reads at most `limit` bytes from the inner reader.
+    #[derive(Debug)]
+    pub struct Take<R> {
+        inner: R,
+        limit: u64,
+    }
+
+    /// Reads, writes and seeks within bytes held in memory.
+    #[derive(Debug, Clone, Default, Eq, PartialEq)]
+    pub struct Cursor<T> {
+        inner:    T,
+        position: u64,
+    }
+
+    /// Reads the primitives of the byte order given, see `byteorder::ReadBytesExt`.
+    pub trait ReadBytesExt: Read {
+        fn read_u8(&mut self) -> Result<u8> {
+            let mut bytes = [0u8; 1];
+            self.read_exact(&mut bytes)?;
+            Ok(bytes[0])
+        }
+
+        fn read_i8(&mut self) -> Result<i8> { Ok(self.read_u8()? as i8) }
+
+        fn read_u16<B: ByteOrder>(&mut self) -> Result<u16> { Ok(B::read_u16(&read_array::<_, 2>(self)?)) }
+
+        fn read_i16<B: ByteOrder>(&mut self) -> Result<i16> { Ok(B::read_i16(&read_array::<_, 2>(self)?)) }
+
+        fn read_u32<B: ByteOrder>(&mut self) -> Result<u32> { Ok(B::read_u32(&read_array::<_, 4>(self)?)) }
+
+        fn read_i32<B: ByteOrder>(&mut self) -> Result<i32> { Ok(B::read_i32(&read_array::<_, 4>(self)?)) }
+
+        fn read_u64<B: ByteOrder>(&mut self) -> Result<u64> { Ok(B::read_u64(&read_array::<_, 8>(self)?)) }
+
+        fn read_i64<B: ByteOrder>(&mut self) -> Result<i64> { Ok(B::read_i64(&read_array::<_, 8>(self)?)) }
+
+        fn read_f32<B: ByteOrder>(&mut self) -> Result<f32> { Ok(B::read_f32(&read_array::<_, 4>(self)?)) }
+
+        fn read_f64<B: ByteOrder>(&mut self) -> Result<f64> { Ok(B::read_f64(&read_array::<_, 8>(self)?)) }
+    }
+
+    /// Writes the primitives in the byte order given, see `byteorder::WriteBytesExt`.
+    pub trait WriteBytesExt: Write {
+        fn write_u8(&mut self, value: u8) -> Result<()> { self.write_all(&[value]) }
+
+        fn write_i8(&mut self, value: i8) -> Result<()> { self.write_all(&[value as u8]) }
+
+        fn write_u16<B: ByteOrder>(&mut self, value: u16) -> Result<()> { write_with(self, 2, |bytes| B::write_u16(bytes, value)) }
+
+        fn write_i16<B: ByteOrder>(&mut self, value: i16) -> Result<()> { write_with(self, 2, |bytes| B::write_i16(bytes, value)) }
+
+        fn write_u32<B: ByteOrder>(&mut self, value: u32) -> Result<()> { write_with(self, 4, |bytes| B::write_u32(bytes, value)) }
+
+        fn write_i32<B: ByteOrder>(&mut self, value: i32) -> Result<()> { write_with(self, 4, |bytes| B::write_i32(bytes, value)) }
+
+        fn write_u64<B: ByteOrder>(&mut self, value: u64) -> Result<()> { write_with(self, 8, |bytes| B::write_u64(bytes, value)) }
+
+        fn write_i64<B: ByteOrder>(&mut self, value: i64) -> Result<()> { write_with(self, 8, |bytes| B::write_i64(bytes, value)) }
+
+        fn write_f32<B: ByteOrder>(&mut self, value: f32) -> Result<()> { write_with(self, 4, |bytes| B::write_f32(bytes, value)) }
+
+        fn write_f64<B: ByteOrder>(&mut self, value: f64) -> Result<()> { write_with(self, 8, |bytes| B::write_f64(bytes, value)) }
+    }
+
+    fn read_array<R: Read + ?Sized, const N: usize>(reader: &mut R) -> Result<[u8; N]> {
+        let mut bytes = [0u8; N];
+        reader.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn write_with<W: Write + ?Sized>(writer: &mut W, length: usize, write: impl FnOnce(&mut [u8])) -> Result<()> {
+        let mut bytes = [0u8; 8];
+        write(&mut bytes[..length]);
+        writer.write_all(&bytes[..length])
+    }
+
+    impl Error {
+        pub fn new(kind: ErrorKind, error: impl Into<Box<dyn core::error::Error + Send + Sync>>) -> Self {
+            Self { kind, error: Some(error.into()) }
+        }
+
+        pub fn other(error: impl Into<Box<dyn core::error::Error + Send + Sync>>) -> Self { Self::new(ErrorKind::Other, error) }
+
+        pub fn kind(&self) -> ErrorKind { self.kind }
+
+        pub fn get_ref(&self) -> Option<&(dyn core::error::Error + Send + Sync + 'static)> { self.error.as_deref() }
+
+        pub fn into_inner(self) -> Option<Box<dyn core::error::Error + Send + Sync>> { self.error }
+    }
+
+    impl From<ErrorKind> for Error {
+        fn from(kind: ErrorKind) -> Self { Self { kind, error: None } }
+    }
+
+    impl fmt::Display for ErrorKind {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(match self {
+                ErrorKind::InvalidInput => "invalid input parameter",
+                ErrorKind::InvalidData => "invalid data",
+                ErrorKind::UnexpectedEof => "unexpected end of file",
+                ErrorKind::WriteZero => "write zero",
+                ErrorKind::Interrupted => "operation interrupted",
+                ErrorKind::Unsupported => "unsupported",
+                ErrorKind::Other => "other error",
+            })
+        }
+    }
+
+    impl fmt::Display for Error {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match &self.error {
+                Some(error) => write!(f, "{error}"),
+                None => write!(f, "{}", self.kind)
+            }
+        }
+    }
+
+    impl core::error::Error for Error {
+        fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+            self.error.as_ref().and_then(|error| error.source())
+        }
+    }
+
+    impl<R: Read + ?Sized> Read for &mut R {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> { (**self).read(buf) }
+    }
+
+    impl<W: Write + ?Sized> Write for &mut W {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> { (**self).write(buf) }
+
+        fn flush(&mut self) -> Result<()> { (**self).flush() }
+    }
+
+    impl<S: Seek + ?Sized> Seek for &mut S {
+        fn seek(&mut self, pos: SeekFrom) -> Result<u64> { (**self).seek(pos) }
+    }
+
+    impl Read for &[u8] {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let count = self.len().min(buf.len());
+            let (head, tail) = self.split_at(count);
+            buf[..count].copy_from_slice(head);
+            *self = tail;
+            Ok(count)
+        }
+    }
+
+    impl Write for Vec<u8> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            self.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<()> { Ok(()) }
+    }
+
+    impl<R> Take<R> {
+        pub fn limit(&self) -> u64 { self.limit }
+
+        pub fn into_inner(self) -> R { self.inner }
+    }
+
+    impl<R: Read> Read for Take<R> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let count = buf.len().min(usize::try_from(self.limit).unwrap_or(usize::MAX));
+            let read = self.inner.read(&mut buf[..count])?;
+            self.limit -= read as u64;
+            Ok(read)
+        }
+    }
+
+    impl<T> Cursor<T> {
+        pub fn new(inner: T) -> Self { Self { inner, position: 0 } }
+
+        pub fn into_inner(self) -> T { self.inner }
+
+        pub fn get_ref(&self) -> &T { &self.inner }
+
+        pub fn get_mut(&mut self) -> &mut T { &mut self.inner }
+
+        pub fn position(&self) -> u64 { self.position }
+
+        pub fn set_position(&mut self, position: u64) { self.position = position }
+    }
+
+    impl<T: AsRef<[u8]>> Cursor<T> {
+        fn remaining(&self) -> &[u8] {
+            let data = self.inner.as_ref();
+            &data[usize::try_from(self.position).map_or(data.len(), |position| position.min(data.len()))..]
+        }
+    }
+
+    impl<T: AsRef<[u8]>> Read for Cursor<T> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let read = self.remaining().read(buf)?;
+            self.position += read as u64;
+            Ok(read)
+        }
+    }
+
+    impl<T: AsRef<[u8]>> Seek for Cursor<T> {
+        fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+            let position = match pos {
+                SeekFrom::Start(offset) => Some(offset),
+                SeekFrom::End(delta) => (self.inner.as_ref().len() as u64).checked_add_signed(delta),
+                SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+            }.ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Cannot seek to a negative or overflowing position."))?;
+            self.position = position;
+            Ok(position)
+        }
+    }
+
+    impl Write for Cursor<Vec<u8>> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            let start = usize::try_from(self.position)
+                .map_err(|_| Error::new(ErrorKind::InvalidInput, "The cursor is past the addressable memory."))?;
+            let end = start + buf.len();
+            if self.inner.len() < end {
+                self.inner.resize(end, 0);
+            }
+            self.inner[start..end].copy_from_slice(buf);
+            self.position = end as u64;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<()> { Ok(()) }
+    }
+
+    impl<R: Read + ?Sized> ReadBytesExt for R {}
+
+    impl<W: Write + ?Sized> WriteBytesExt for W {}
+}
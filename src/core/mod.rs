@@ -1 +1,2 @@
-pub mod io;    pub use io::*;
+pub mod io;      pub use io::*;
+pub mod profile; pub use profile::*;
@@ -0,0 +1,219 @@
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::RangeInclusive;
+use crate::core::io::codec::{CodecKind, CodecRegistry, TEXTURE_CODEC_FORMAT};
+
+/// The games this crate reads files for, in release order.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+pub enum GameTitle {
+    Flashpoint,
+    Arma,
+    Arma2,
+    #[default]
+    Arma3,
+    DayZ,
+}
+
+/// The preprocessor directives, each game understands a subset of them.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Directive {
+    Include,
+    Define,
+    Undef,
+    IfDef,
+    IfNDef,
+    If,
+    Else,
+    EndIf,
+}
+
+/// Describes the game files are read for, the layout of a format may differ between games even
+/// when they share its version.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct GameProfile {
+    title:         GameTitle,
+    directives:    Vec<Directive>,
+    has_include:   bool,
+    evaluation:    bool,
+    rap_versions:  RangeInclusive<u32>,
+    texture_codec: CodecKind,
+}
+
+impl Directive {
+    pub const ALL: &'static [Directive] = &[
+        Directive::Include, Directive::Define, Directive::Undef, Directive::IfDef,
+        Directive::IfNDef, Directive::If, Directive::Else, Directive::EndIf
+    ];
+
+    /// The directive written as `#name`.
+    pub fn from_name(name: &[u8]) -> Option<Self> {
+        Self::ALL.iter().copied().find(|directive| directive.name().as_bytes() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Directive::Include => "include",
+            Directive::Define => "define",
+            Directive::Undef => "undef",
+            Directive::IfDef => "ifdef",
+            Directive::IfNDef => "ifndef",
+            Directive::If => "if",
+            Directive::Else => "else",
+            Directive::EndIf => "endif",
+        }
+    }
+}
+
+impl fmt::Display for Directive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.name())
+    }
+}
+
+impl GameProfile {
+    /// The rapified config layout of Flashpoint.
+    pub const RAP_VERSION_FLASHPOINT: u32 = 1;
+    /// The rapified config layout with the enum table added by Arma.
+    pub const RAP_VERSION_ENUMS: u32 = 2;
+
+    /// The profile of the title with the defaults of its latest release.
+    pub fn new(title: GameTitle) -> Self {
+        let mut directives = Directive::ALL.to_vec();
+        if title != GameTitle::Arma3 {
+            directives.retain(|directive| *directive != Directive::If);
+        }
+        Self {
+            title,
+            directives,
+            has_include: title == GameTitle::Arma3,
+            evaluation: title != GameTitle::Flashpoint,
+            rap_versions: match title {
+                GameTitle::Flashpoint => Self::RAP_VERSION_FLASHPOINT..=Self::RAP_VERSION_FLASHPOINT,
+                _ => Self::RAP_VERSION_FLASHPOINT..=Self::RAP_VERSION_ENUMS,
+            },
+            texture_codec: match title {
+                GameTitle::Flashpoint | GameTitle::Arma => CodecKind::Lzss,
+                GameTitle::Arma2 | GameTitle::Arma3 | GameTitle::DayZ => CodecKind::Lzo,
+            },
+        }
+    }
+
+    pub fn with_directives(mut self, directives: &[Directive]) -> Self {
+        self.directives = directives.to_vec();
+        self
+    }
+
+    pub fn with_has_include(mut self, has_include: bool) -> Self {
+        self.has_include = has_include;
+        self
+    }
+
+    pub fn with_evaluation(mut self, evaluation: bool) -> Self {
+        self.evaluation = evaluation;
+        self
+    }
+
+    pub fn with_rap_versions(mut self, rap_versions: RangeInclusive<u32>) -> Self {
+        self.rap_versions = rap_versions;
+        self
+    }
+
+    pub fn with_texture_codec(mut self, texture_codec: CodecKind) -> Self {
+        self.texture_codec = texture_codec;
+        self
+    }
+
+    pub fn title(&self) -> GameTitle { self.title }
+
+    pub fn directives(&self) -> &[Directive] { &self.directives }
+
+    pub fn supports(&self, directive: Directive) -> bool { self.directives.contains(&directive) }
+
+    /// Whether `__has_include` can be used in `#if` conditions.
+    pub fn has_include(&self) -> bool { self.has_include }
+
+    /// Whether configs may use `__EXEC` and `__EVAL`.
+    pub fn evaluation(&self) -> bool { self.evaluation }
+
+    /// The rapified config layouts the game reads, numbered like the `RAP_VERSION_*` constants.
+    pub fn rap_versions(&self) -> &RangeInclusive<u32> { &self.rap_versions }
+
+    pub fn supports_rap_version(&self, version: u32) -> bool { self.rap_versions.contains(&version) }
+
+    /// The codec compressed texture data is stored with.
+    pub fn texture_codec(&self) -> CodecKind { self.texture_codec }
+
+    /// The codecs of the formats read by this crate as the game stores them.
+    pub fn codecs(&self) -> CodecRegistry {
+        CodecRegistry::default().with(TEXTURE_CODEC_FORMAT, 0, self.texture_codec.codec())
+    }
+}
+
+impl Default for GameProfile {
+    fn default() -> Self { Self::new(GameTitle::default()) }
+}
+
+impl From<GameTitle> for GameProfile {
+    fn from(title: GameTitle) -> Self { Self::new(title) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TITLES: [GameTitle; 5] = [GameTitle::Flashpoint, GameTitle::Arma, GameTitle::Arma2, GameTitle::Arma3, GameTitle::DayZ];
+
+    #[test]
+    fn only_arma_3_has_if_and_has_include() {
+        for title in TITLES {
+            let profile = GameProfile::new(title);
+            assert_eq!(profile.supports(Directive::If), title == GameTitle::Arma3, "{title:?}");
+            assert_eq!(profile.has_include(), title == GameTitle::Arma3, "{title:?}");
+        }
+    }
+
+    #[test]
+    fn every_title_has_the_other_directives() {
+        for title in TITLES {
+            let profile = GameProfile::new(title);
+            for &directive in Directive::ALL.iter().filter(|&&directive| directive != Directive::If) {
+                assert!(profile.supports(directive), "{title:?} {directive}");
+            }
+        }
+    }
+
+    #[test]
+    fn title_defaults() {
+        let flashpoint = GameProfile::new(GameTitle::Flashpoint);
+        assert!(!flashpoint.evaluation());
+        assert_eq!(flashpoint.texture_codec(), CodecKind::Lzss);
+        assert!(flashpoint.supports_rap_version(GameProfile::RAP_VERSION_FLASHPOINT));
+        assert!(!flashpoint.supports_rap_version(GameProfile::RAP_VERSION_ENUMS));
+
+        let arma = GameProfile::new(GameTitle::Arma);
+        assert!(arma.evaluation());
+        assert_eq!(arma.texture_codec(), CodecKind::Lzss);
+
+        for title in [GameTitle::Arma, GameTitle::Arma2, GameTitle::Arma3, GameTitle::DayZ] {
+            let profile = GameProfile::new(title);
+            assert!(profile.evaluation(), "{title:?}");
+            assert_eq!(profile.rap_versions(), &(GameProfile::RAP_VERSION_FLASHPOINT..=GameProfile::RAP_VERSION_ENUMS), "{title:?}");
+        }
+        for title in [GameTitle::Arma2, GameTitle::Arma3, GameTitle::DayZ] {
+            assert_eq!(GameProfile::new(title).texture_codec(), CodecKind::Lzo, "{title:?}");
+        }
+        assert_eq!(GameProfile::default(), GameProfile::new(GameTitle::Arma3));
+    }
+
+    #[test]
+    fn defaults_can_be_overridden() {
+        let profile = GameProfile::new(GameTitle::Arma2)
+            .with_directives(&[Directive::Include, Directive::If])
+            .with_has_include(true)
+            .with_rap_versions(2..=2);
+        assert!(profile.supports(Directive::If));
+        assert!(!profile.supports(Directive::Define));
+        assert!(profile.has_include());
+        assert!(!profile.supports_rap_version(GameProfile::RAP_VERSION_FLASHPOINT));
+    }
+}
@@ -1,2 +1,17 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+extern crate self as bis;
+extern crate alloc;
+
 pub mod rv;   pub use rv::*;
 pub mod core; pub use core::*;
+// Both globs bring in an `io` module, at the crate root it is the core one.
+pub use self::core::io;
+pub use bis_derive::{Binarizable, Debinarizable};
+
+/// Items the derive and `magic_enum!` expansions refer to, so they also build in crates without
+/// `std`.
+#[doc(hidden)]
+pub mod __private {
+    pub use alloc::string::String;
+    pub use alloc::vec::Vec;
+}
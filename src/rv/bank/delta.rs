@@ -0,0 +1,425 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::io;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use sha1::{Digest, Sha1};
+use thiserror::Error;
+use crate::EntryHash;
+use crate::bank::io::{BankSkimError, BankSkimOptions, ChecksumWriter, PboReader};
+
+const DELTA_MAGIC: &[u8; 4] = b"BDLT";
+const DELTA_VERSION: u32 = 1;
+const TRAILER_LENGTH: u64 = 21;
+const MIN_BLOCK_SIZE: usize = 64;
+const MAX_BLOCK_COUNT: usize = 1 << 20;
+const COPY_CHUNK_SIZE: usize = 64 * 1024;
+const HASH_BASE: u64 = 0x100000001b3;
+
+const OP_COPY: u8 = 0;
+const OP_INSERT: u8 = 1;
+
+#[derive(Error, Debug)]
+pub enum BankDeltaError {
+    #[error("Bank Delta Error: The source bank does not match the bank this patch was generated from.")]
+    SourceMismatch,
+    #[error("Bank Delta Error: The patch is not a supported bank delta.")]
+    Malformed,
+    #[error("Bank Delta Error: The patch copies data from outside of the source bank.")]
+    CopyOutOfBounds,
+    #[error("Bank Delta Error: The reconstructed bank does not match the expected size or digest.")]
+    TargetMismatch,
+    #[error("Bank Delta Error: The checksum trailer of the reconstructed bank is invalid.")]
+    InvalidChecksum,
+    #[error(transparent)]
+    Skim(#[from] BankSkimError),
+    #[error(transparent)]
+    IO(#[from] io::Error),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum DeltaOp {
+    Copy { offset: u64, length: u64 },
+    Insert(Vec<u8>)
+}
+
+/// A patch rebuilding one bank from another, made of ranges copied from the source bank and
+/// literal data only present in the target.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct BankDelta {
+    pub(crate) source_size:  u64,
+    pub(crate) source_hash:  EntryHash,
+    pub(crate) target_size:  u64,
+    pub(crate) target_hash:  EntryHash,
+    pub(crate) checksummed:  bool,
+    pub(crate) ops:          Vec<DeltaOp>,
+}
+
+impl BankDelta {
+    /// Generates a patch from `source` to `target`. Entries whose packed data is unchanged are
+    /// copied whole, wherever they moved to, everything else is matched block by block.
+    pub fn generate(source: &[u8], target: &[u8]) -> Result<Self, BankDeltaError> {
+        let source_ranges = entry_ranges(source)?;
+        let target_ranges = entry_ranges(target)?;
+
+        let mut whole_entries: HashMap<(usize, EntryHash), usize> = HashMap::new();
+        for &(start, end) in &source_ranges {
+            whole_entries.entry((end - start, hash(&source[start..end]))).or_insert(start);
+        }
+
+        let mut builder = DeltaBuilder::new(source);
+        let mut cursor = 0;
+        for (start, end) in target_ranges {
+            if start < cursor {
+                continue
+            }
+
+            builder.match_blocks(&target[cursor..start]);
+            match whole_entries.get(&(end - start, hash(&target[start..end]))) {
+                Some(&offset) if end > start => builder.copy(offset, end - start),
+                _ => builder.match_blocks(&target[start..end])
+            }
+            cursor = end;
+        }
+        builder.match_blocks(&target[cursor..]);
+
+        Ok(Self {
+            source_size: source.len() as u64,
+            source_hash: hash(source),
+            target_size: target.len() as u64,
+            target_hash: hash(target),
+            checksummed: has_valid_trailer(target),
+            ops: builder.finish(),
+        })
+    }
+
+    pub fn ops(&self) -> &[DeltaOp] { &self.ops }
+
+    /// The amount of literal bytes carried by this patch.
+    pub fn insert_size(&self) -> u64 {
+        self.ops.iter().map(|op| match op {
+            DeltaOp::Insert(data) => data.len() as u64,
+            DeltaOp::Copy { .. } => 0
+        }).sum()
+    }
+
+    /// Rebuilds the target bank from the source bank, verifying the source, the reconstructed
+    /// output and (when the target had one) its checksum trailer along the way.
+    pub fn apply<R: Read + Seek, W: Write>(&self, source: &mut R, target: W) -> Result<W, BankDeltaError> {
+        source.seek(SeekFrom::Start(0))?;
+        let mut source_hasher = ChecksumWriter::new(io::sink());
+        let source_size = io::copy(source, &mut source_hasher)?;
+        if source_size != self.source_size || source_hasher.digest() != self.source_hash {
+            return Err(BankDeltaError::SourceMismatch)
+        }
+
+        let mut output = DeltaOutput::new(target, self.target_size);
+        let mut buffer = vec![0u8; COPY_CHUNK_SIZE];
+        for op in &self.ops {
+            match op {
+                DeltaOp::Insert(data) => output.emit(data)?,
+                &DeltaOp::Copy { offset, length } => {
+                    if offset.checked_add(length).is_none_or(|end| end > source_size) {
+                        return Err(BankDeltaError::CopyOutOfBounds)
+                    }
+
+                    source.seek(SeekFrom::Start(offset))?;
+                    let mut remaining = length;
+                    while remaining > 0 {
+                        let chunk = remaining.min(COPY_CHUNK_SIZE as u64) as usize;
+                        source.read_exact(&mut buffer[..chunk])?;
+                        output.emit(&buffer[..chunk])?;
+                        remaining -= chunk as u64;
+                    }
+                }
+            }
+        }
+
+        output.finish(self)
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), BankDeltaError> {
+        writer.write_all(DELTA_MAGIC)?;
+        writer.write_u32::<LittleEndian>(DELTA_VERSION)?;
+        writer.write_u64::<LittleEndian>(self.source_size)?;
+        writer.write_all(&self.source_hash)?;
+        writer.write_u64::<LittleEndian>(self.target_size)?;
+        writer.write_all(&self.target_hash)?;
+        writer.write_u8(self.checksummed as u8)?;
+        writer.write_u64::<LittleEndian>(self.ops.len() as u64)?;
+        for op in &self.ops {
+            match op {
+                &DeltaOp::Copy { offset, length } => {
+                    writer.write_u8(OP_COPY)?;
+                    writer.write_u64::<LittleEndian>(offset)?;
+                    writer.write_u64::<LittleEndian>(length)?;
+                }
+                DeltaOp::Insert(data) => {
+                    writer.write_u8(OP_INSERT)?;
+                    writer.write_u64::<LittleEndian>(data.len() as u64)?;
+                    writer.write_all(data)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> Result<Self, BankDeltaError> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != DELTA_MAGIC || reader.read_u32::<LittleEndian>()? != DELTA_VERSION {
+            return Err(BankDeltaError::Malformed)
+        }
+
+        let source_size = reader.read_u64::<LittleEndian>()?;
+        let mut source_hash = EntryHash::default();
+        reader.read_exact(&mut source_hash)?;
+        let target_size = reader.read_u64::<LittleEndian>()?;
+        let mut target_hash = EntryHash::default();
+        reader.read_exact(&mut target_hash)?;
+        let checksummed = reader.read_u8()? != 0;
+
+        let op_count = reader.read_u64::<LittleEndian>()?;
+        let mut ops = vec![];
+        for _ in 0..op_count {
+            ops.push(match reader.read_u8()? {
+                OP_COPY => DeltaOp::Copy {
+                    offset: reader.read_u64::<LittleEndian>()?,
+                    length: reader.read_u64::<LittleEndian>()?,
+                },
+                OP_INSERT => {
+                    let length = reader.read_u64::<LittleEndian>()?;
+                    let mut data = vec![];
+                    if reader.take(length).read_to_end(&mut data)? as u64 != length {
+                        return Err(BankDeltaError::Malformed)
+                    }
+                    DeltaOp::Insert(data)
+                }
+                _ => return Err(BankDeltaError::Malformed)
+            });
+        }
+
+        Ok(Self { source_size, source_hash, target_size, target_hash, checksummed, ops })
+    }
+}
+
+fn hash(data: &[u8]) -> EntryHash { Sha1::digest(data).into() }
+
+fn has_valid_trailer(data: &[u8]) -> bool {
+    let Some(trailer_start) = data.len().checked_sub(TRAILER_LENGTH as usize) else { return false };
+    data[trailer_start] == 0 && hash(&data[..trailer_start]) == data[trailer_start + 1..]
+}
+
+/// Locates the packed data of every entry in a bank, sorted by position.
+fn entry_ranges(bank: &[u8]) -> Result<Vec<(usize, usize)>, BankDeltaError> {
+    let skim = PboReader::skim_archive(Cursor::new(bank), BankSkimOptions::default())?;
+    let mut ranges: Vec<(usize, usize)> = skim.entries.iter()
+        .map(|(entry, &offset)| (offset as usize, offset as usize + entry.size_packed as usize))
+        .filter(|&(start, end)| end > start && end <= bank.len())
+        .collect();
+    ranges.sort();
+    Ok(ranges)
+}
+
+struct DeltaBuilder<'a> {
+    source:     &'a [u8],
+    block_size: usize,
+    blocks:     HashMap<u64, usize>,
+    ops:        Vec<DeltaOp>,
+    literal:    Vec<u8>,
+}
+
+impl<'a> DeltaBuilder<'a> {
+    fn new(source: &'a [u8]) -> Self {
+        let block_size = (source.len() / MAX_BLOCK_COUNT + 1).next_power_of_two().max(MIN_BLOCK_SIZE);
+        let mut blocks = HashMap::new();
+        for (index, block) in source.chunks_exact(block_size).enumerate() {
+            blocks.entry(rolling_hash(block)).or_insert(index * block_size);
+        }
+
+        Self { source, block_size, blocks, ops: vec![], literal: vec![] }
+    }
+
+    fn copy(&mut self, offset: usize, length: usize) {
+        self.flush_literal();
+        if let Some(DeltaOp::Copy { offset: last_offset, length: last_length }) = self.ops.last_mut() {
+            if *last_offset + *last_length == offset as u64 {
+                *last_length += length as u64;
+                return
+            }
+        }
+        self.ops.push(DeltaOp::Copy { offset: offset as u64, length: length as u64 });
+    }
+
+    fn flush_literal(&mut self) {
+        if !self.literal.is_empty() {
+            self.ops.push(DeltaOp::Insert(std::mem::take(&mut self.literal)));
+        }
+    }
+
+    /// Rsync style matching: a rolling hash over the region is looked up against the hashes of
+    /// the source's fixed blocks, matches are then grown in both directions byte by byte.
+    fn match_blocks(&mut self, region: &[u8]) {
+        let block_size = self.block_size;
+        if region.len() < block_size {
+            self.literal.extend_from_slice(region);
+            return
+        }
+
+        let top_power = (1..block_size).fold(1u64, |power, _| power.wrapping_mul(HASH_BASE));
+        let mut literal_start = 0;
+        let mut index = 0;
+        let mut current = rolling_hash(&region[..block_size]);
+        loop {
+            let found = self.blocks.get(&current).copied()
+                .filter(|&offset| self.source[offset..offset + block_size] == region[index..index + block_size]);
+
+            if let Some(offset) = found {
+                let mut length = block_size;
+                while index + length < region.len() && offset + length < self.source.len() &&
+                    region[index + length] == self.source[offset + length] {
+                    length += 1;
+                }
+                let mut back = 0;
+                while index - back > literal_start && offset - back > 0 &&
+                    region[index - back - 1] == self.source[offset - back - 1] {
+                    back += 1;
+                }
+
+                self.literal.extend_from_slice(&region[literal_start..index - back]);
+                self.copy(offset - back, length + back);
+                index += length;
+                literal_start = index;
+                if index + block_size > region.len() { break }
+                current = rolling_hash(&region[index..index + block_size]);
+                continue
+            }
+
+            if index + block_size >= region.len() { break }
+            current = current
+                .wrapping_sub((region[index] as u64).wrapping_mul(top_power))
+                .wrapping_mul(HASH_BASE)
+                .wrapping_add(region[index + block_size] as u64);
+            index += 1;
+        }
+        self.literal.extend_from_slice(&region[literal_start..]);
+    }
+
+    fn finish(mut self) -> Vec<DeltaOp> {
+        self.flush_literal();
+        self.ops
+    }
+}
+
+fn rolling_hash(block: &[u8]) -> u64 {
+    block.iter().fold(0u64, |hash, &c| hash.wrapping_mul(HASH_BASE).wrapping_add(c as u64))
+}
+
+/// Receives the reconstructed bank, keeping track of the digest up to the checksum trailer and
+/// of the trailer itself.
+struct DeltaOutput<W: Write> {
+    writer:          ChecksumWriter<W>,
+    trailer_start:   u64,
+    trailer_digest:  Option<EntryHash>,
+    trailer:         Vec<u8>,
+}
+
+impl<W: Write> DeltaOutput<W> {
+    fn new(writer: W, target_size: u64) -> Self {
+        Self {
+            writer: ChecksumWriter::new(writer),
+            trailer_start: target_size.saturating_sub(TRAILER_LENGTH),
+            trailer_digest: None,
+            trailer: vec![],
+        }
+    }
+
+    fn emit(&mut self, data: &[u8]) -> Result<(), BankDeltaError> {
+        let position = self.writer.position();
+        let (body, trailer) = match position < self.trailer_start {
+            true => data.split_at(data.len().min((self.trailer_start - position) as usize)),
+            false => data.split_at(0)
+        };
+
+        self.writer.write_all(body)?;
+        if self.trailer_digest.is_none() && self.writer.position() >= self.trailer_start {
+            self.trailer_digest = Some(self.writer.digest());
+        }
+        self.trailer.extend_from_slice(trailer);
+        Ok(self.writer.write_all(trailer)?)
+    }
+
+    fn finish(mut self, delta: &BankDelta) -> Result<W, BankDeltaError> {
+        self.writer.flush()?;
+        if self.writer.position() != delta.target_size || self.writer.digest() != delta.target_hash {
+            return Err(BankDeltaError::TargetMismatch)
+        }
+
+        if delta.checksummed {
+            let valid = match (self.trailer_digest, self.trailer.split_first()) {
+                (Some(digest), Some((0, stored))) => digest == stored,
+                _ => false
+            };
+            if !valid {
+                return Err(BankDeltaError::InvalidChecksum)
+            }
+        }
+
+        Ok(self.writer.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BankProperties, BankSkimEntry, EntryMime};
+    use crate::bank::io::PboWriter;
+
+    fn noise(seed: u64, length: usize) -> Vec<u8> {
+        let mut state = seed;
+        (0..length).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 24) as u8
+        }).collect()
+    }
+
+    fn pack(version: &str, files: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut properties = BankProperties::new();
+        properties.set_prefix("tests\\delta");
+        properties.set_version(version);
+        let entries: Vec<(BankSkimEntry, Vec<u8>)> = files.iter()
+            .map(|(name, data)| (BankSkimEntry::new(name, EntryMime::Decompressed, 0, 0), data.clone()))
+            .collect();
+        PboWriter::write_archive(vec![], &properties, &entries).unwrap()
+    }
+
+    #[test]
+    fn round_trip_reproduces_target() {
+        let texture = noise(1, 200_000);
+        let mut edited = texture.clone();
+        edited[120_000] ^= 0xff;
+        edited.splice(500..500, noise(2, 37));
+
+        let source = pack("1", &[("a.paa", texture), ("b.sqf", noise(3, 5000)), ("c.sqf", noise(4, 7000))]);
+        let target = pack("2", &[("c.sqf", noise(4, 7000)), ("a.paa", edited), ("d.sqf", noise(5, 100))]);
+
+        let delta = BankDelta::generate(&source, &target).unwrap();
+        assert!(delta.insert_size() < target.len() as u64 / 10);
+        assert_eq!(delta.apply(&mut Cursor::new(&source), vec![]).unwrap(), target);
+
+        let mut patch = vec![];
+        delta.write(&mut patch).unwrap();
+        let read = BankDelta::read(&mut Cursor::new(&patch)).unwrap();
+        assert_eq!(read, delta);
+        assert_eq!(read.apply(&mut Cursor::new(&source), vec![]).unwrap(), target);
+    }
+
+    #[test]
+    fn rejects_other_source() {
+        let source = pack("1", &[("a.sqf", noise(1, 1000))]);
+        let target = pack("2", &[("a.sqf", noise(2, 1000))]);
+        let delta = BankDelta::generate(&source, &target).unwrap();
+        assert!(matches!(delta.apply(&mut Cursor::new(&target), vec![]), Err(BankDeltaError::SourceMismatch)));
+    }
+}
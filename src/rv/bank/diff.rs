@@ -0,0 +1,489 @@
+use std::collections::{BTreeSet, HashMap};
+use std::iter;
+use std::io::{Read, Seek};
+use sha1::{Digest, Sha1};
+use crate::{BankProperties, BankSkimEntry, EntryHash, PboFileSkim};
+use crate::bank::io::EntryError;
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct BankDiffOptions {
+    pub(crate) text_diff:      bool,
+    pub(crate) context_lines:  usize,
+    pub(crate) max_text_size:  u32,
+}
+
+impl Default for BankDiffOptions {
+    fn default() -> Self {
+        Self {
+            text_diff: false,
+            context_lines: 3,
+            max_text_size: 4 * 1024 * 1024,
+        }
+    }
+}
+
+impl BankDiffOptions {
+    pub fn with_text_diff(mut self, context_lines: usize) -> Self {
+        self.text_diff = true;
+        self.context_lines = context_lines;
+        self
+    }
+}
+
+/// A header key whose values differ, keys may appear more than once so all values are listed.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PropertyChange {
+    pub key: String,
+    pub old: Vec<String>,
+    pub new: Vec<String>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum EntryChange {
+    Added(BankSkimEntry),
+    Removed(BankSkimEntry),
+    /// More than one entry of either bank resolves to the name, these are not compared.
+    Duplicated {
+        name: String,
+        old:  Vec<BankSkimEntry>,
+        new:  Vec<BankSkimEntry>,
+    },
+    Modified {
+        old:                 BankSkimEntry,
+        new:                 BankSkimEntry,
+        content_changed:     bool,
+        compression_changed: bool,
+        text_diff:           Option<String>,
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct BankDiff {
+    pub properties: Vec<PropertyChange>,
+    pub entries:    Vec<EntryChange>,
+}
+
+impl BankDiff {
+    pub fn is_empty(&self) -> bool { self.properties.is_empty() && self.entries.is_empty() }
+}
+
+impl<R: Read + Seek> PboFileSkim<R> {
+    /// Compares this bank against a newer one. Entries are matched by name and only reported as
+    /// modified when their unpacked content or their compression differ. Names are compared
+    /// case-insensitively, names shared by several entries of a bank are reported as duplicated.
+    pub fn diff<N: Read + Seek>(&mut self, new: &mut PboFileSkim<N>, options: &BankDiffOptions) -> Result<BankDiff, EntryError> {
+        let mut diff = BankDiff {
+            properties: diff_properties(&self.properties, &new.properties),
+            entries: vec![],
+        };
+
+        let old_entries = named_entries(self);
+        let new_entries = named_entries(new);
+        let names: BTreeSet<&String> = old_entries.keys().chain(new_entries.keys()).collect();
+        for name in names {
+            let old_named = old_entries.get(name).map(Vec::as_slice).unwrap_or_default();
+            let new_named = new_entries.get(name).map(Vec::as_slice).unwrap_or_default();
+            if old_named.len() > 1 || new_named.len() > 1 {
+                diff.entries.push(EntryChange::Duplicated {
+                    name: name.clone(),
+                    old: old_named.to_vec(),
+                    new: new_named.to_vec(),
+                });
+                continue
+            }
+
+            let change = match (old_named.first(), new_named.first()) {
+                (Some(old), None) => EntryChange::Removed(old.clone()),
+                (None, Some(new_entry)) => EntryChange::Added(new_entry.clone()),
+                (Some(old), Some(new_entry)) => {
+                    let old_data = self.read_entry(old)?;
+                    let new_data = new.read_entry(new_entry)?;
+                    let content_changed = old_data.len() != new_data.len() ||
+                        hash_data(&old_data) != hash_data(&new_data);
+                    let compression_changed = old.mime != new_entry.mime;
+                    if !content_changed && !compression_changed {
+                        continue
+                    }
+
+                    let text_diff = match options.text_diff && content_changed {
+                        true => unified_diff(name, &old_data, &new_data, options),
+                        false => None
+                    };
+                    EntryChange::Modified {
+                        old: old.clone(),
+                        new: new_entry.clone(),
+                        content_changed,
+                        compression_changed,
+                        text_diff
+                    }
+                },
+                (None, None) => continue
+            };
+            diff.entries.push(change);
+        }
+
+        Ok(diff)
+    }
+}
+
+fn named_entries<R: Read + Seek>(skim: &PboFileSkim<R>) -> HashMap<String, Vec<BankSkimEntry>> {
+    let mut named: HashMap<String, Vec<BankSkimEntry>> = HashMap::new();
+    for entry in skim.entries.keys().filter(|entry| !entry.filename.is_empty()) {
+        named.entry(entry.filename.to_lowercase()).or_default().push(entry.clone());
+    }
+    named
+}
+
+fn hash_data(data: &[u8]) -> EntryHash {
+    Sha1::digest(data).into()
+}
+
+fn diff_properties(old: &BankProperties, new: &BankProperties) -> Vec<PropertyChange> {
+    let keys: BTreeSet<String> = old.iter().chain(new.iter())
+        .map(|property| property.key.to_lowercase())
+        .collect();
+    keys.into_iter()
+        .map(|key| PropertyChange {
+            old: old.get_all(&key).map(str::to_string).collect(),
+            new: new.get_all(&key).map(str::to_string).collect(),
+            key,
+        })
+        .filter(|change| change.old != change.new)
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum LineEdit {
+    Equal,
+    Delete,
+    Insert
+}
+
+/// Produces a unified diff between two text entries, binary or oversized entries yield nothing.
+fn unified_diff(name: &str, old: &[u8], new: &[u8], options: &BankDiffOptions) -> Option<String> {
+    let is_text = |data: &[u8]| data.len() <= options.max_text_size as usize && !data.contains(&0);
+    if !is_text(old) || !is_text(new) {
+        return None
+    }
+
+    let old_lines = split_lines(old);
+    let new_lines = split_lines(new);
+    let edits = diff_lines(&old_lines, &new_lines);
+
+    // Pair every edit with the line positions it applies to in the old and new text.
+    let mut positioned = Vec::with_capacity(edits.len());
+    let (mut old_idx, mut new_idx) = (0, 0);
+    for edit in edits {
+        positioned.push((edit, old_idx, new_idx));
+        match edit {
+            LineEdit::Equal => { old_idx += 1; new_idx += 1 },
+            LineEdit::Delete => old_idx += 1,
+            LineEdit::Insert => new_idx += 1,
+        }
+    }
+
+    let changes: Vec<usize> = positioned.iter().enumerate()
+        .filter(|(_, (edit, _, _))| *edit != LineEdit::Equal)
+        .map(|(i, _)| i)
+        .collect();
+    if changes.is_empty() {
+        return None
+    }
+
+    let mut out = format!("--- a/{}\n+++ b/{}\n", name, name);
+    let context = options.context_lines;
+    let mut group_start = 0;
+    for i in 0..changes.len() {
+        let is_last = i + 1 == changes.len() || changes[i + 1] - changes[i] > context * 2 + 1;
+        if !is_last {
+            continue
+        }
+
+        let start = changes[group_start].saturating_sub(context);
+        let end = (changes[i] + context + 1).min(positioned.len());
+        let hunk = &positioned[start..end];
+        let old_count = hunk.iter().filter(|(edit, _, _)| *edit != LineEdit::Insert).count();
+        let new_count = hunk.iter().filter(|(edit, _, _)| *edit != LineEdit::Delete).count();
+        let (_, old_start, new_start) = hunk[0];
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            if old_count == 0 { old_start } else { old_start + 1 }, old_count,
+            if new_count == 0 { new_start } else { new_start + 1 }, new_count
+        ));
+        for &(edit, old_idx, new_idx) in hunk {
+            let (marker, line) = match edit {
+                LineEdit::Equal => (' ', old_lines[old_idx]),
+                LineEdit::Delete => ('-', old_lines[old_idx]),
+                LineEdit::Insert => ('+', new_lines[new_idx]),
+            };
+            out.push(marker);
+            out.push_str(&String::from_utf8_lossy(line));
+            out.push('\n');
+        }
+        group_start = i + 1;
+    }
+
+    Some(out)
+}
+
+fn split_lines(data: &[u8]) -> Vec<&[u8]> {
+    let data = data.strip_suffix(b"\n").unwrap_or(data);
+    if data.is_empty() {
+        return vec![]
+    }
+
+    data.split(|&c| c == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .collect()
+}
+
+/// Myers' O(ND) shortest edit script between two sets of lines, found in linear space by
+/// splitting every section around its middle snake.
+fn diff_lines(old: &[&[u8]], new: &[&[u8]]) -> Vec<LineEdit> {
+    let mut edits = Vec::with_capacity(old.len().max(new.len()));
+    diff_section(old, new, &mut edits);
+    edits
+}
+
+fn diff_section(old: &[&[u8]], new: &[&[u8]], edits: &mut Vec<LineEdit>) {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let (old, new) = (&old[prefix..], &new[prefix..]);
+    let suffix = old.iter().rev().zip(new.iter().rev()).take_while(|(a, b)| a == b).count();
+    let (old, new) = (&old[..old.len() - suffix], &new[..new.len() - suffix]);
+
+    edits.extend(iter::repeat_n(LineEdit::Equal, prefix));
+    if old.is_empty() || new.is_empty() {
+        edits.extend(iter::repeat_n(LineEdit::Delete, old.len()));
+        edits.extend(iter::repeat_n(LineEdit::Insert, new.len()));
+    } else {
+        // Both sides differ at their first and last line, so the script has at least two edits
+        // and each half around the middle snake is strictly shorter.
+        let (start_x, start_y, end_x, end_y) = middle_snake(old, new);
+        diff_section(&old[..start_x], &new[..start_y], edits);
+        edits.extend(iter::repeat_n(LineEdit::Equal, end_x - start_x));
+        diff_section(&old[end_x..], &new[end_y..], edits);
+    }
+    edits.extend(iter::repeat_n(LineEdit::Equal, suffix));
+}
+
+/// Walks the edit graph from both ends at once until the paths overlap, returning the start and
+/// end of the snake where they met.
+fn middle_snake(old: &[&[u8]], new: &[&[u8]]) -> (usize, usize, usize, usize) {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let delta = n - m;
+    let max = (n + m + 1) / 2;
+    let offset = max + 1;
+    let mut forward = vec![0isize; 2 * offset as usize + 1];
+    let mut backward = vec![0isize; 2 * offset as usize + 1];
+    let at = |k: isize| (k + offset) as usize;
+
+    for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let mut x = match k == -d || (k != d && forward[at(k - 1)] < forward[at(k + 1)]) {
+                true => forward[at(k + 1)],
+                false => forward[at(k - 1)] + 1
+            };
+            let mut y = x - k;
+            let (snake_x, snake_y) = (x, y);
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[at(k)] = x;
+            let reverse_k = delta - k;
+            if delta % 2 != 0 && reverse_k.abs() < d && x + backward[at(reverse_k)] >= n {
+                return (snake_x as usize, snake_y as usize, x as usize, y as usize)
+            }
+        }
+
+        // The backward walk runs over both sets reversed, diagonal k here is delta - k forwards.
+        for k in (-d..=d).step_by(2) {
+            let mut x = match k == -d || (k != d && backward[at(k - 1)] < backward[at(k + 1)]) {
+                true => backward[at(k + 1)],
+                false => backward[at(k - 1)] + 1
+            };
+            let mut y = x - k;
+            let (snake_x, snake_y) = (x, y);
+            while x < n && y < m && old[(n - 1 - x) as usize] == new[(m - 1 - y) as usize] {
+                x += 1;
+                y += 1;
+            }
+            backward[at(k)] = x;
+            let forward_k = delta - k;
+            if delta % 2 == 0 && forward_k.abs() <= d && forward[at(forward_k)] + x >= n {
+                return ((n - x) as usize, (m - y) as usize, (n - snake_x) as usize, (m - snake_y) as usize)
+            }
+        }
+    }
+
+    unreachable!("the forward and backward paths always meet")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use proptest::prelude::*;
+    use crate::EntryMime;
+    use crate::bank::io::{BankSkimOptions, PboReader, PboWriter};
+    use crate::core::io::lzss;
+
+    fn bank(properties: &BankProperties, entries: &[(BankSkimEntry, Vec<u8>)]) -> PboFileSkim<Cursor<Vec<u8>>> {
+        let data = PboWriter::write_archive(vec![], properties, entries).unwrap();
+        PboReader::skim_archive(Cursor::new(data), BankSkimOptions::default()).unwrap()
+    }
+
+    fn stored(name: &str, data: &[u8]) -> (BankSkimEntry, Vec<u8>) {
+        (BankSkimEntry::new(name, EntryMime::Decompressed, 0, 0), data.to_vec())
+    }
+
+    fn text_diff(old: &str, new: &str, context_lines: usize) -> Option<String> {
+        let options = BankDiffOptions::default().with_text_diff(context_lines);
+        unified_diff("a.txt", old.as_bytes(), new.as_bytes(), &options)
+    }
+
+    fn numbered(lines: impl IntoIterator<Item = usize>) -> String {
+        lines.into_iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    #[test]
+    fn empty_inputs_have_no_differences() {
+        assert!(diff_lines(&[], &[]).is_empty());
+        assert_eq!(text_diff("", "", 3), None);
+        assert_eq!(text_diff("a\n", "a", 3), None);
+    }
+
+    #[test]
+    fn new_text_is_all_inserts() {
+        assert_eq!(text_diff("", "a\nb\n", 3).unwrap(), "--- a/a.txt\n+++ b/a.txt\n@@ -0,0 +1,2 @@\n+a\n+b\n");
+    }
+
+    #[test]
+    fn removed_text_is_all_deletes() {
+        assert_eq!(text_diff("a\nb\n", "", 3).unwrap(), "--- a/a.txt\n+++ b/a.txt\n@@ -1,2 +0,0 @@\n-a\n-b\n");
+    }
+
+    #[test]
+    fn changes_at_both_ends_get_their_own_hunks() {
+        let diff = text_diff(&numbered(1..=6), &numbered([0, 2, 3, 4, 5, 7]), 1).unwrap();
+        assert_eq!(diff, "--- a/a.txt\n+++ b/a.txt\n@@ -1,2 +1,2 @@\n-1\n+0\n 2\n@@ -5,2 +5,2 @@\n 5\n-6\n+7\n");
+    }
+
+    #[test]
+    fn hunks_merge_when_their_context_overlaps() {
+        let old = numbered(1..=10);
+        let new = numbered((1..=10).filter(|&line| line != 3 && line != 7));
+
+        let split = text_diff(&old, &new, 1).unwrap();
+        assert_eq!(split, "--- a/a.txt\n+++ b/a.txt\n@@ -2,3 +2,2 @@\n 2\n-3\n 4\n@@ -6,3 +5,2 @@\n 6\n-7\n 8\n");
+        let merged = text_diff(&old, &new, 2).unwrap();
+        assert_eq!(merged, "--- a/a.txt\n+++ b/a.txt\n@@ -1,9 +1,7 @@\n 1\n 2\n-3\n 4\n 5\n 6\n-7\n 8\n 9\n");
+    }
+
+    #[test]
+    fn binary_entries_have_no_text_diff() {
+        assert_eq!(text_diff("a\0", "b\0", 3), None);
+    }
+
+    #[test]
+    fn entries_are_matched_by_name() {
+        let mut old = bank(&BankProperties::new(), &[stored("a.txt", b"a\n"), stored("b.txt", b"b"), stored("c.txt", b"c")]);
+        let mut new = bank(&BankProperties::new(), &[stored("A.txt", b"b\n"), stored("c.txt", b"c"), stored("d.txt", b"d")]);
+
+        let diff = old.diff(&mut new, &BankDiffOptions::default().with_text_diff(0)).unwrap();
+        assert!(diff.properties.is_empty());
+        assert_eq!(diff.entries.len(), 3);
+        assert!(matches!(&diff.entries[0], EntryChange::Modified {
+            content_changed: true,
+            compression_changed: false,
+            text_diff: Some(text),
+            ..
+        } if text == "--- a/a.txt\n+++ b/a.txt\n@@ -1,1 +1,1 @@\n-a\n+b\n"));
+        assert!(matches!(&diff.entries[1], EntryChange::Removed(entry) if entry.filename == "b.txt"));
+        assert!(matches!(&diff.entries[2], EntryChange::Added(entry) if entry.filename == "d.txt"));
+    }
+
+    #[test]
+    fn duplicated_names_are_not_compared() {
+        let duplicated = (BankSkimEntry::new("a.txt", EntryMime::Decompressed, 0, 1), b"b".to_vec());
+        let mut old = bank(&BankProperties::new(), &[stored("a.txt", b"a"), duplicated]);
+        let mut new = bank(&BankProperties::new(), &[stored("a.txt", b"a")]);
+
+        let diff = old.diff(&mut new, &BankDiffOptions::default()).unwrap();
+        assert_eq!(diff.entries.len(), 1);
+        assert!(matches!(&diff.entries[0], EntryChange::Duplicated { name, old, new }
+            if name == "a.txt" && old.len() == 2 && new.len() == 1));
+    }
+
+    #[test]
+    fn property_changes_list_every_value() {
+        let mut old_properties = BankProperties::new();
+        old_properties.set_prefix("a");
+        old_properties.set_version("1");
+        let mut new_properties = BankProperties::new();
+        new_properties.set_prefix("b");
+        new_properties.set_version("1");
+        new_properties.set_product("c");
+
+        let diff = bank(&old_properties, &[]).diff(&mut bank(&new_properties, &[]), &BankDiffOptions::default()).unwrap();
+        assert_eq!(diff.properties, [
+            PropertyChange { key: "prefix".to_string(), old: vec!["a".to_string()], new: vec!["b".to_string()] },
+            PropertyChange { key: "product".to_string(), old: vec![], new: vec!["c".to_string()] },
+        ]);
+        assert!(diff.entries.is_empty());
+    }
+
+    #[test]
+    fn compression_changes_are_reported_without_content_changes() {
+        let data = b"abcabcabcabcabcabc";
+        let compressed = (BankSkimEntry::new("a.txt", EntryMime::Compressed, data.len() as u32, 0), lzss::encode(data));
+        let mut old = bank(&BankProperties::new(), &[stored("a.txt", data)]);
+        let mut new = bank(&BankProperties::new(), &[compressed]);
+
+        let diff = old.diff(&mut new, &BankDiffOptions::default().with_text_diff(3)).unwrap();
+        assert!(matches!(&diff.entries[..], [EntryChange::Modified {
+            content_changed: false,
+            compression_changed: true,
+            text_diff: None,
+            ..
+        }]));
+        assert!(old.diff(&mut bank(&BankProperties::new(), &[stored("a.txt", data)]), &BankDiffOptions::default()).unwrap().is_empty());
+    }
+
+    /// The length of the longest common subsequence, which any shortest edit script keeps.
+    fn common_lines(old: &[&[u8]], new: &[&[u8]]) -> usize {
+        let mut lengths = vec![vec![0; new.len() + 1]; old.len() + 1];
+        for i in (0..old.len()).rev() {
+            for j in (0..new.len()).rev() {
+                lengths[i][j] = match old[i] == new[j] {
+                    true => lengths[i + 1][j + 1] + 1,
+                    false => lengths[i + 1][j].max(lengths[i][j + 1])
+                };
+            }
+        }
+        lengths[0][0]
+    }
+
+    proptest! {
+        #[test]
+        fn edit_scripts_are_shortest(old in prop::collection::vec(0u8..3, 0..24), new in prop::collection::vec(0u8..3, 0..24)) {
+            const LINES: [&[u8]; 3] = [b"a", b"b", b"c"];
+            let lines = |text: &[u8]| text.iter().map(|&line| LINES[line as usize]).collect::<Vec<_>>();
+            let (old_lines, new_lines) = (lines(&old), lines(&new));
+            let edits = diff_lines(&old_lines, &new_lines);
+
+            let kept_old: Vec<u8> = old.iter().zip(edits.iter().filter(|&&edit| edit != LineEdit::Insert))
+                .filter(|(_, &edit)| edit == LineEdit::Equal)
+                .map(|(&line, _)| line)
+                .collect();
+            let kept_new: Vec<u8> = new.iter().zip(edits.iter().filter(|&&edit| edit != LineEdit::Delete))
+                .filter(|(_, &edit)| edit == LineEdit::Equal)
+                .map(|(&line, _)| line)
+                .collect();
+            prop_assert_eq!(edits.iter().filter(|&&edit| edit != LineEdit::Insert).count(), old.len());
+            prop_assert_eq!(edits.iter().filter(|&&edit| edit != LineEdit::Delete).count(), new.len());
+            prop_assert_eq!(&kept_old, &kept_new);
+            prop_assert_eq!(kept_old.len(), common_lines(&old_lines, &new_lines));
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use sha1::{Digest, Sha1};
+use crate::{BankSkimEntry, EntryHash};
+use crate::bank::io::EntryError;
+use crate::bank::fs::BankFilesystem;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum DedupMode {
+    /// Compare entries as they are stored, a compressed and an uncompressed copy never match.
+    #[default]
+    Packed,
+    /// Compare the unpacked content of entries, this requires decompressing candidates.
+    Unpacked
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct DuplicateOccurrence {
+    pub bank:        String,
+    pub entry:       String,
+    pub size_packed: u32,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct DuplicateGroup {
+    pub hash:        EntryHash,
+    pub size:        u32,
+    pub occurrences: Vec<DuplicateOccurrence>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Default)]
+pub struct DedupReport {
+    pub groups: Vec<DuplicateGroup>,
+}
+
+impl DuplicateGroup {
+    /// The space taken by every copy except for the smallest one.
+    pub fn wasted_space(&self) -> u64 {
+        let total: u64 = self.occurrences.iter().map(|it| it.size_packed as u64).sum();
+        let kept = self.occurrences.iter().map(|it| it.size_packed as u64).min().unwrap_or(0);
+        total - kept
+    }
+}
+
+impl DedupReport {
+    pub fn wasted_space(&self) -> u64 { self.groups.iter().map(DuplicateGroup::wasted_space).sum() }
+}
+
+impl BankFilesystem {
+    /// Groups identical entries across every mounted bank. Entries are first bucketed by size so
+    /// only entries that could possibly match are ever read and hashed. Groups are sorted by the
+    /// space they waste, largest first.
+    pub fn find_duplicates(&mut self, mode: DedupMode) -> Result<DedupReport, EntryError> {
+        let size_of = |entry: &BankSkimEntry| match mode {
+            DedupMode::Packed => entry.size_packed,
+            DedupMode::Unpacked => entry.size(),
+        };
+
+        let mut by_size: HashMap<u32, Vec<(usize, BankSkimEntry)>> = HashMap::new();
+        for (index, bank) in self.banks.iter().enumerate() {
+            for entry in bank.skim.entries() {
+                if entry.filename.is_empty() || size_of(entry) == 0 || bank.is_deleted(entry) {
+                    continue
+                }
+                by_size.entry(size_of(entry)).or_default().push((index, entry.clone()));
+            }
+        }
+
+        let mut by_hash: HashMap<(u32, EntryHash), Vec<DuplicateOccurrence>> = HashMap::new();
+        for (size, candidates) in by_size.into_iter().filter(|(_, candidates)| candidates.len() > 1) {
+            for (index, entry) in candidates {
+                let bank = &mut self.banks[index];
+                let data = match mode {
+                    DedupMode::Packed => bank.skim.read_packed_entry(&entry)?,
+                    DedupMode::Unpacked => bank.skim.read_entry(&entry)?,
+                };
+                by_hash.entry((size, Sha1::digest(data).into())).or_default().push(DuplicateOccurrence {
+                    bank: bank.prefix().to_string(),
+                    entry: entry.filename.clone(),
+                    size_packed: entry.size_packed,
+                });
+            }
+        }
+
+        let mut groups: Vec<DuplicateGroup> = by_hash.into_iter()
+            .filter(|(_, occurrences)| occurrences.len() > 1)
+            .map(|((size, hash), mut occurrences)| {
+                occurrences.sort_by(|a, b| (&a.bank, &a.entry).cmp(&(&b.bank, &b.entry)));
+                DuplicateGroup { hash, size, occurrences }
+            })
+            .collect();
+        groups.sort_by(|a, b| b.wasted_space().cmp(&a.wasted_space()).then(a.hash.cmp(&b.hash)));
+
+        Ok(DedupReport { groups })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::{BankProperties, EntryMime};
+    use crate::bank::io::{BankSkimOptions, PboWriter};
+    use crate::core::io::lzss;
+
+    /// Mounts a bank with the given prefix, the file backing it is removed once opened.
+    fn mount(filesystem: &mut BankFilesystem, prefix: &str, entries: &[(&str, EntryMime, Vec<u8>)]) {
+        let mut properties = BankProperties::new();
+        properties.set_prefix(prefix);
+        let entries: Vec<_> = entries.iter()
+            .map(|(name, mime, data)| (BankSkimEntry::new(name, *mime, 0, 0), data.clone()))
+            .map(|(entry, data)| match entry.mime {
+                EntryMime::Compressed => (BankSkimEntry { size_unpacked: data.len() as u32, ..entry }, lzss::encode(&data)),
+                _ => (entry, data)
+            })
+            .collect();
+
+        let path = std::env::temp_dir().join(format!("bis-dedup-{}-{}.pbo", std::process::id(), prefix));
+        fs::write(&path, PboWriter::write_archive(vec![], &properties, &entries).unwrap()).unwrap();
+        let loaded = filesystem.load_bank(&path, BankSkimOptions::default());
+        let _ = fs::remove_file(&path);
+        loaded.unwrap();
+    }
+
+    fn occurrences(group: &DuplicateGroup) -> Vec<(&str, &str)> {
+        group.occurrences.iter().map(|it| (it.bank.as_str(), it.entry.as_str())).collect()
+    }
+
+    #[test]
+    fn identical_entries_are_grouped_across_banks() {
+        let mut filesystem = BankFilesystem::new();
+        mount(&mut filesystem, "dedup-a", &[
+            ("a", EntryMime::Decompressed, b"same".to_vec()),
+            ("b", EntryMime::Decompressed, b"diff".to_vec()),
+            ("empty", EntryMime::Decompressed, vec![]),
+        ]);
+        mount(&mut filesystem, "dedup-b", &[
+            ("c", EntryMime::Decompressed, b"same".to_vec()),
+            ("d", EntryMime::Decompressed, b"same".to_vec()),
+            ("empty", EntryMime::Decompressed, vec![]),
+        ]);
+
+        let report = filesystem.find_duplicates(DedupMode::Packed).unwrap();
+        assert_eq!(report.groups.len(), 1);
+        assert_eq!(report.groups[0].size, 4);
+        assert_eq!(occurrences(&report.groups[0]), [("dedup-a", "a"), ("dedup-b", "c"), ("dedup-b", "d")]);
+        assert_eq!(report.wasted_space(), 8);
+    }
+
+    #[test]
+    fn unpacked_mode_matches_across_compression() {
+        let data = b"abcabcabcabcabcabcabcabc".to_vec();
+        let mut filesystem = BankFilesystem::new();
+        mount(&mut filesystem, "dedup-unpacked", &[
+            ("a", EntryMime::Decompressed, data.clone()),
+            ("b", EntryMime::Compressed, data.clone()),
+        ]);
+
+        assert!(filesystem.find_duplicates(DedupMode::Packed).unwrap().groups.is_empty());
+        let report = filesystem.find_duplicates(DedupMode::Unpacked).unwrap();
+        assert_eq!(report.groups.len(), 1);
+        assert_eq!(report.groups[0].size, data.len() as u32);
+        //The compressed copy is the one kept.
+        assert_eq!(report.wasted_space(), data.len() as u64);
+    }
+
+    #[test]
+    fn groups_are_sorted_by_wasted_space() {
+        let mut filesystem = BankFilesystem::new();
+        mount(&mut filesystem, "dedup-sorted", &[
+            ("a", EntryMime::Decompressed, b"small".to_vec()),
+            ("b", EntryMime::Decompressed, b"small".to_vec()),
+            ("c", EntryMime::Decompressed, b"larger".to_vec()),
+            ("d", EntryMime::Decompressed, b"larger".to_vec()),
+        ]);
+
+        let report = filesystem.find_duplicates(DedupMode::Packed).unwrap();
+        let wasted: Vec<_> = report.groups.iter().map(DuplicateGroup::wasted_space).collect();
+        assert_eq!(wasted, [6, 5]);
+    }
+}
@@ -1,7 +1,6 @@
 use std::io;
-use log::error;
 use thiserror::Error;
-use crate::BankSkimError;
+use crate::bank::io::{BankSkimError, BankWriteError, EntryError};
 
 #[derive(Error, Debug)]
 pub enum BankLoadError {
@@ -13,4 +12,14 @@ pub enum BankLoadError {
     PreexistingPrefix(String),
     #[error("Failed to get filename as prefix.")]
     FileNameUnknown
-}
\ No newline at end of file
+}
+
+#[derive(Error, Debug)]
+pub enum BankSaveError {
+    #[error("Failed to save bank with prefix {0} as no bank is loaded with that prefix. ")]
+    PrefixNotFound(String),
+    #[error(transparent)]
+    EntryRead(#[from] EntryError),
+    #[error(transparent)]
+    EntryWrite(#[from] BankWriteError),
+}
@@ -1,18 +1,24 @@
 mod error;
+pub mod search;
+pub mod dedup;
 
 use std::collections::HashMap;
 use std::fmt::{Debug};
 use std::fs::File;
-use std::hash::{Hash, Hasher};
-use std::io::Cursor;
+use std::io::{Cursor, Write};
 use std::path::Path;
-use crate::{BankSkimEntry, EntryMime, HEADER_PREFIX_MAGIC, PboFileSkim, PboReader};
-use crate::fs::error::BankLoadError;
-use crate::options::BankSkimOptions;
+use std::time::SystemTime;
+use crate::{BankSkimEntry, EntryMime, PboFileSkim, time_to_timestamp};
+use crate::bank::io::{BankSkimOptions, PboReader, PboWriter};
+use crate::bank::pack::{CompressionPolicy, TimestampPolicy};
+use crate::core::io::lzss::LzssEncoder;
+pub use error::{BankLoadError, BankSaveError};
+pub use search::*;
+pub use dedup::*;
 
 
-#[derive(Debug)]
-struct BankFilesystem {
+#[derive(Debug, Default)]
+pub struct BankFilesystem {
     banks:    Vec<BankFileMeta>,
 }
 
@@ -25,7 +31,7 @@ struct BankFileMeta {
     deleted_entries:   Vec<String>,
 }
 
-#[derive(Debug, Hash)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 struct CachedEntry {
     cached_from:   Option<Box<BankSkimEntry>>,
     data_altered:  bool,
@@ -38,7 +44,7 @@ struct CachedEntry {
     mime:          Option<EntryMime>,
 }
 
-#[derive(Debug, Hash)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 enum CachedTimestamp {
     Generate,
     Custom(u32)
@@ -47,7 +53,7 @@ enum CachedTimestamp {
 impl BankFileMeta {
     pub fn locate_file(&self, name: &str) -> Option<&CachedEntry> {
         self.open_entries.iter()
-            .find(|(&ref entry, _)| entry.name.eq_ignore_ascii_case(name))
+            .find(|(entry, _)| entry.name.eq_ignore_ascii_case(name))
             .map(|(entry, _)| entry)
         // Look in skim if not found
     }
@@ -61,32 +67,70 @@ impl BankFileMeta {
 }
 
 impl BankFilesystem {
-    pub fn bank_for_prefix(&self, prefix: &str) -> Option<&BankFileMeta> {
-        self.banks.iter().find(|&meta| {
-            match &meta.changed_prefix {
-                None => meta.prefix.eq_ignore_ascii_case(prefix),
-                Some(it) => it.eq_ignore_ascii_case(prefix)
-            }
-        })
+    pub fn new() -> Self { Self::default() }
+
+    fn bank_for_prefix(&self, prefix: &str) -> Option<&BankFileMeta> {
+        self.banks.iter().find(|&meta| meta.prefix().eq_ignore_ascii_case(prefix))
     }
 
     pub fn load_bank(&mut self, path: &Path, options: BankSkimOptions) -> Result<(), BankLoadError> {
         let file = File::open(path)?;
         let archive = PboReader::skim_archive(file, options)?;
-        let prefix = match archive.properties.get(HEADER_PREFIX_MAGIC) {
+        let prefix = match archive.properties.prefix() {
             None => match path.file_stem() {
                 None => Err(BankLoadError::FileNameUnknown),
                 Some(it) => Ok(
                     it.to_str().ok_or(BankLoadError::FileNameUnknown)?.to_string()
                 )
             }
-            Some(it) => Ok(it.clone())
+            Some(it) => Ok(it.to_string())
         }?;
 
+        if self.bank_for_prefix(&prefix).is_some() {
+            return Err(BankLoadError::PreexistingPrefix(prefix))
+        }
         self.banks.push(BankFileMeta::new(prefix, archive));
         Ok(())
     }
 
+    fn bank_for_prefix_mut(&mut self, prefix: &str) -> Result<&mut BankFileMeta, BankSaveError> {
+        self.banks.iter_mut()
+            .find(|meta| meta.prefix().eq_ignore_ascii_case(prefix))
+            .ok_or_else(|| BankSaveError::PrefixNotFound(prefix.to_string()))
+    }
+
+    /// Replaces the data of an entry, or adds it when the bank has no entry with that name. The
+    /// entry is given a new timestamp when the bank is saved.
+    pub fn write_entry(&mut self, prefix: &str, name: &str, data: Vec<u8>) -> Result<(), BankSaveError> {
+        let bank = self.bank_for_prefix_mut(prefix)?;
+        let (mut entry, _) = bank.take_entry(name)?;
+        entry.data_altered = true;
+        entry.timestamp = Some(CachedTimestamp::Generate);
+        bank.open_entries.insert(entry, Cursor::new(data));
+        Ok(())
+    }
+
+    /// Gives an entry the timestamp it is saved with, regardless of the policy used.
+    pub fn set_entry_timestamp(&mut self, prefix: &str, name: &str, timestamp: u32) -> Result<(), BankSaveError> {
+        let bank = self.bank_for_prefix_mut(prefix)?;
+        let (mut entry, data) = bank.take_entry(name)?;
+        entry.timestamp = Some(CachedTimestamp::Custom(timestamp));
+        bank.open_entries.insert(entry, data);
+        Ok(())
+    }
+
+    /// Writes the bank loaded with the given prefix along with its pending changes, entries whose
+    /// timestamp is to be generated get one from `timestamps`. Written data is stored as decided by
+    /// `compression`, entries that were not written keep the compression they were stored with.
+    pub fn save_bank<W: Write>(
+        &mut self,
+        prefix: &str,
+        writer: W,
+        timestamps: &TimestampPolicy,
+        compression: &CompressionPolicy
+    ) -> Result<W, BankSaveError> {
+        self.bank_for_prefix_mut(prefix)?.write(writer, timestamps, compression)
+    }
 }
 
 impl BankFileMeta {
@@ -100,22 +144,129 @@ impl BankFileMeta {
         }
     }
 
-    fn unchanged(&self) -> bool {
-        self.changed_prefix.is_none() &&
-            self.open_entries.iter().all(|(entry, _)| entry.unchanged()) &&
-            self.deleted_entries.is_empty()
+    fn prefix(&self) -> &str {
+        match &self.changed_prefix {
+            None => &self.prefix,
+            Some(it) => it
+        }
+    }
+
+    fn is_deleted(&self, entry: &BankSkimEntry) -> bool {
+        self.deleted_entries.iter().any(|name| name.eq_ignore_ascii_case(&entry.filename))
+    }
+
+    /// The cached entry opened from the given entry of the bank, if it was opened.
+    fn opened(&self, entry: &BankSkimEntry) -> Option<(&CachedEntry, &Cursor<Vec<u8>>)> {
+        self.open_entries.iter().find(|(cached, _)| cached.cached_from.as_deref() == Some(entry))
+    }
+
+    /// Takes an opened entry out of the cache, opening it from the bank (or as a new entry) when it
+    /// was not cached yet. The entry is expected to be put back once changed.
+    fn take_entry(&mut self, name: &str) -> Result<(CachedEntry, Cursor<Vec<u8>>), BankSaveError> {
+        if let Some(cached) = self.locate_file(name).cloned() {
+            let data = self.open_entries.remove(&cached).unwrap_or_default();
+            return Ok((cached, data))
+        }
+
+        let original = self.skim.get_entry(name).cloned();
+        let data = match &original {
+            Some(original) => self.skim.read_entry(original)?,
+            None => vec![]
+        };
+        Ok((CachedEntry::new(name, original), Cursor::new(data)))
+    }
+
+    /// Writes the entries of the bank in their original order followed by the ones added since it
+    /// was loaded. Entries that were not opened are copied as they are stored.
+    fn write<W: Write>(&mut self, writer: W, policy: &TimestampPolicy, compression: &CompressionPolicy) -> Result<W, BankSaveError> {
+        let mut properties = self.skim.properties.clone();
+        if let Some(prefix) = &self.changed_prefix {
+            properties.set_prefix(prefix);
+        }
+
+        let mut originals: Vec<(BankSkimEntry, u64)> = self.skim.entries.iter()
+            .filter(|(entry, _)| !self.is_deleted(entry))
+            .map(|(entry, &offset)| (entry.clone(), offset))
+            .collect();
+        originals.sort_by_key(|&(_, offset)| offset);
+
+        let mut encoder = LzssEncoder::new(compression.level());
+        let mut entries = Vec::with_capacity(originals.len() + self.open_entries.len());
+        for (original, _) in originals {
+            entries.push(match self.opened(&original) {
+                Some((cached, data)) if cached.data_altered => cached.written_entry(data.get_ref(), policy, compression, &mut encoder),
+                Some((cached, _)) => {
+                    let entry = BankSkimEntry {
+                        filename: cached.written_name().to_string(),
+                        timestamp: cached.resolve_timestamp(policy),
+                        ..original.clone()
+                    };
+                    (entry, self.skim.read_packed_entry(&original)?)
+                },
+                None => {
+                    let data = self.skim.read_packed_entry(&original)?;
+                    (original, data)
+                }
+            });
+        }
+        entries.extend(self.open_entries.iter()
+            .filter(|(cached, _)| cached.cached_from.is_none())
+            .map(|(cached, data)| cached.written_entry(data.get_ref(), policy, compression, &mut encoder)));
+
+        Ok(PboWriter::write_archive(writer, &properties, &entries)?)
+    }
+}
+
+impl CachedTimestamp {
+    /// The timestamp the entry should be written with. Generated timestamps are the time of
+    /// writing, unless the policy fixes every timestamp to the same value.
+    fn resolve(&self, policy: &TimestampPolicy, now: SystemTime) -> u32 {
+        match (self, policy) {
+            (CachedTimestamp::Custom(timestamp), _) => *timestamp,
+            (CachedTimestamp::Generate, TimestampPolicy::Fixed(timestamp)) => *timestamp,
+            (CachedTimestamp::Generate, _) => time_to_timestamp(now)
+        }
     }
 }
 
 impl CachedEntry {
-    fn unchanged(&self) -> bool {
-        !self.data_altered &&
-            self.timestamp.is_none() &&
-            self.changed_name.is_none() &&
-            self.packed_size.is_none() &&
-            self.size.is_none() &&
-            self.mime.is_none() &&
-            self.offset.is_none()
+    fn new(name: &str, cached_from: Option<BankSkimEntry>) -> Self {
+        Self {
+            cached_from: cached_from.map(Box::new),
+            data_altered: false,
+            name: name.to_string(),
+            changed_name: None,
+            timestamp: None,
+            offset: None,
+            size: None,
+            packed_size: None,
+            mime: None,
+        }
+    }
+
+    fn resolve_timestamp(&self, policy: &TimestampPolicy) -> u32 {
+        let original = self.cached_from.as_ref().map_or(0, |entry| entry.timestamp);
+        match &self.timestamp {
+            None => original,
+            Some(timestamp) => timestamp.resolve(policy, SystemTime::now())
+        }
+    }
+
+    fn written_name(&self) -> &str {
+        self.changed_name.as_deref().unwrap_or(&self.name)
+    }
+
+    /// The entry written for altered or added data, compressed when the policy allows it.
+    fn written_entry(
+        &self,
+        data: &[u8],
+        policy: &TimestampPolicy,
+        compression: &CompressionPolicy,
+        encoder: &mut LzssEncoder
+    ) -> (BankSkimEntry, Vec<u8>) {
+        let (mime, packed) = compression.apply(encoder, self.written_name(), data);
+        let entry = BankSkimEntry::new(self.written_name(), mime, data.len() as u32, self.resolve_timestamp(policy));
+        (entry, packed.unwrap_or_else(|| data.to_vec()))
     }
 }
 
@@ -159,3 +310,103 @@ impl CachedEntry {
 //     }
 // }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Cursor;
+    use crate::BankProperties;
+    use crate::bank::pack::CompressionRule;
+
+    const TEXT: &[u8] = b"class a {}; class b {}; class c {}; class d {};";
+
+    /// Writes a bank with the prefix `test` whose entries all carry the timestamp 5, returning its
+    /// path. The caller removes it once loaded.
+    fn write_bank(name: &str, entries: &[(&str, &[u8])]) -> std::path::PathBuf {
+        let mut properties = BankProperties::new();
+        properties.set_prefix("test");
+        let entries: Vec<_> = entries.iter()
+            .map(|&(name, data)| (BankSkimEntry::new(name, EntryMime::Decompressed, 0, 5), data))
+            .collect();
+
+        let path = std::env::temp_dir().join(format!("bis-fs-{}-{}.pbo", std::process::id(), name));
+        fs::write(&path, PboWriter::write_archive(vec![], &properties, &entries).unwrap()).unwrap();
+        path
+    }
+
+    fn mounted(name: &str, entries: &[(&str, &[u8])]) -> BankFilesystem {
+        let path = write_bank(name, entries);
+        let mut filesystem = BankFilesystem::new();
+        let loaded = filesystem.load_bank(&path, BankSkimOptions::default());
+        let _ = fs::remove_file(&path);
+        loaded.unwrap();
+        filesystem
+    }
+
+    fn saved(filesystem: &mut BankFilesystem, timestamps: &TimestampPolicy, compression: &CompressionPolicy) -> PboFileSkim<Cursor<Vec<u8>>> {
+        let data = filesystem.save_bank("test", vec![], timestamps, compression).unwrap();
+        PboReader::skim_archive(Cursor::new(data), BankSkimOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn written_entries_follow_the_compression_policy() {
+        let mut filesystem = mounted("compression", &[("a.sqf", b"a"), ("b.sqf", TEXT)]);
+        filesystem.write_entry("test", "a.sqf", TEXT.to_vec()).unwrap();
+        filesystem.write_entry("test", "c.paa", TEXT.to_vec()).unwrap();
+
+        let compression = CompressionPolicy::default().with_extension("sqf", CompressionRule::Compress);
+        let mut skim = saved(&mut filesystem, &TimestampPolicy::Preserve, &compression);
+        let mimes: Vec<_> = ["a.sqf", "b.sqf", "c.paa"].iter()
+            .map(|name| skim.get_entry(name).unwrap().mime())
+            .collect();
+        //Entries that were not written are copied as they were stored.
+        assert_eq!(mimes, [EntryMime::Compressed, EntryMime::Decompressed, EntryMime::Decompressed]);
+
+        let entry = skim.get_entry("a.sqf").unwrap().clone();
+        assert!(entry.size_packed() < TEXT.len() as u32);
+        assert_eq!(skim.read_entry(&entry).unwrap(), TEXT);
+    }
+
+    #[test]
+    fn generated_timestamps_are_not_preserved() {
+        let mut filesystem = mounted("generated", &[("a", b"a"), ("b", b"b")]);
+        filesystem.write_entry("test", "a", b"c".to_vec()).unwrap();
+
+        let before = time_to_timestamp(SystemTime::now());
+        let skim = saved(&mut filesystem, &TimestampPolicy::Preserve, &CompressionPolicy::default());
+        assert!(skim.get_entry("a").unwrap().timestamp() >= before);
+        assert_eq!(skim.get_entry("b").unwrap().timestamp(), 5);
+
+        let skim = saved(&mut filesystem, &TimestampPolicy::Fixed(7), &CompressionPolicy::default());
+        assert_eq!(skim.get_entry("a").unwrap().timestamp(), 7);
+        assert_eq!(skim.get_entry("b").unwrap().timestamp(), 5);
+    }
+
+    #[test]
+    fn custom_timestamps_ignore_the_policy() {
+        let mut filesystem = mounted("custom", &[("a", b"a")]);
+        filesystem.set_entry_timestamp("test", "a", 9).unwrap();
+
+        let mut skim = saved(&mut filesystem, &TimestampPolicy::Fixed(7), &CompressionPolicy::default());
+        let entry = skim.get_entry("a").unwrap().clone();
+        assert_eq!(entry.timestamp(), 9);
+        assert_eq!(skim.read_entry(&entry).unwrap(), b"a");
+    }
+
+    #[test]
+    fn prefixes_are_only_loaded_once() {
+        let path = write_bank("prefix", &[]);
+        let mut filesystem = BankFilesystem::new();
+        let first = filesystem.load_bank(&path, BankSkimOptions::default());
+        let second = filesystem.load_bank(&path, BankSkimOptions::default());
+        let _ = fs::remove_file(&path);
+
+        first.unwrap();
+        assert!(matches!(second, Err(BankLoadError::PreexistingPrefix(prefix)) if prefix == "test"));
+        assert!(matches!(
+            filesystem.write_entry("other", "a", vec![]),
+            Err(BankSaveError::PrefixNotFound(prefix)) if prefix == "other"
+        ));
+    }
+}
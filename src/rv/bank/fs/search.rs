@@ -0,0 +1,313 @@
+use std::collections::VecDeque;
+use std::slice::IterMut;
+use std::vec::IntoIter;
+use glob::{MatchOptions, Pattern, PatternError};
+use regex::bytes::Regex;
+use thiserror::Error;
+use crate::BankSkimEntry;
+use crate::bank::io::EntryError;
+use crate::bank::fs::{BankFileMeta, BankFilesystem, CachedEntry};
+
+/// The amount of bytes inspected at the start of an entry when deciding whether it is binary.
+const BINARY_SNIFF_LENGTH: usize = 8000;
+
+#[derive(Error, Debug)]
+pub enum SearchError {
+    #[error(transparent)]
+    Entry(#[from] EntryError),
+    #[error(transparent)]
+    Regex(#[from] regex::Error),
+    #[error(transparent)]
+    Glob(#[from] PatternError),
+}
+
+#[derive(Debug, Clone)]
+pub enum SearchPattern {
+    Literal(Vec<u8>),
+    Regex(Regex)
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+    pub(crate) pattern:          SearchPattern,
+    pub(crate) path_filter:      Option<Pattern>,
+    pub(crate) min_size:         u32,
+    pub(crate) max_size:         u32,
+    pub(crate) include_binary:   bool,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct SearchHit {
+    pub bank:      String,
+    pub entry:     String,
+    pub line:      usize,
+    pub column:    usize,
+    pub line_text: Vec<u8>,
+}
+
+/// Lazily walks every entry of every mounted bank, only reading an entry once all hits from the
+/// previous one have been handed out.
+pub struct SearchHits<'a> {
+    query:   &'a SearchQuery,
+    banks:   IterMut<'a, BankFileMeta>,
+    current: Option<(&'a mut BankFileMeta, IntoIter<SearchedEntry>)>,
+    pending: VecDeque<SearchHit>,
+}
+
+/// An entry as it is searched, either as stored in the bank or with the data it was given since.
+enum SearchedEntry {
+    Stored(BankSkimEntry),
+    Opened(CachedEntry),
+}
+
+impl SearchQuery {
+    fn new(pattern: SearchPattern) -> Self {
+        Self {
+            pattern,
+            path_filter: None,
+            min_size: 0,
+            max_size: u32::MAX,
+            include_binary: false,
+        }
+    }
+
+    pub fn literal(text: &str) -> Self { Self::new(SearchPattern::Literal(text.as_bytes().to_vec())) }
+
+    pub fn regex(expression: &str) -> Result<Self, SearchError> {
+        Ok(Self::new(SearchPattern::Regex(Regex::new(expression)?)))
+    }
+
+    /// Only search entries whose full path (`prefix/path/to/entry`) matches the given glob, paths
+    /// are matched case-insensitively using forward slashes.
+    pub fn with_path_glob(mut self, glob: &str) -> Result<Self, SearchError> {
+        self.path_filter = Some(Pattern::new(&glob.replace('\\', "/"))?);
+        Ok(self)
+    }
+
+    pub fn with_size_range(mut self, min_size: u32, max_size: u32) -> Self {
+        self.min_size = min_size;
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn with_binary(mut self, include_binary: bool) -> Self {
+        self.include_binary = include_binary;
+        self
+    }
+
+    fn accepts(&self, path: &str, size: u32) -> bool {
+        const OPTIONS: MatchOptions = MatchOptions {
+            case_sensitive: false,
+            require_literal_separator: true,
+            require_literal_leading_dot: false,
+        };
+
+        size >= self.min_size && size <= self.max_size && match &self.path_filter {
+            None => true,
+            Some(filter) => filter.matches_with(path, OPTIONS)
+        }
+    }
+
+    fn find_in_line(&self, line: &[u8]) -> Vec<usize> {
+        match &self.pattern {
+            SearchPattern::Regex(regex) => regex.find_iter(line).map(|m| m.start()).collect(),
+            SearchPattern::Literal(literal) if literal.is_empty() => vec![],
+            SearchPattern::Literal(literal) => {
+                let mut found = vec![];
+                let mut start = 0;
+                while start + literal.len() <= line.len() {
+                    if line[start..].starts_with(literal) {
+                        found.push(start);
+                        start += literal.len();
+                    } else { start += 1 }
+                }
+                found
+            }
+        }
+    }
+}
+
+impl BankFilesystem {
+    /// Searches the contents of every entry across all mounted banks, compressed entries are
+    /// unpacked transparently and entries written since loading are searched as written. Hits are
+    /// produced in bank order, then by entry name.
+    pub fn search<'a>(&'a mut self, query: &'a SearchQuery) -> SearchHits<'a> {
+        SearchHits {
+            query,
+            banks: self.banks.iter_mut(),
+            current: None,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl SearchedEntry {
+    /// The entries of a bank in the order they are searched in.
+    fn of_bank(bank: &BankFileMeta) -> Vec<Self> {
+        let mut entries: Vec<SearchedEntry> = bank.skim.entries()
+            .filter(|&entry| !entry.filename.is_empty() && !bank.is_deleted(entry) && bank.opened(entry).is_none())
+            .map(|entry| SearchedEntry::Stored(entry.clone()))
+            .chain(bank.open_entries.keys().map(|cached| SearchedEntry::Opened(cached.clone())))
+            .collect();
+        entries.sort_by(|a, b| a.name().cmp(b.name()));
+        entries
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            SearchedEntry::Stored(entry) => &entry.filename,
+            SearchedEntry::Opened(cached) => cached.written_name()
+        }
+    }
+}
+
+impl<'a> SearchHits<'a> {
+    fn scan_entry(query: &SearchQuery, pending: &mut VecDeque<SearchHit>, bank: &str, entry: &str, data: &[u8]) {
+        let sniffed = &data[..data.len().min(BINARY_SNIFF_LENGTH)];
+        if !query.include_binary && sniffed.contains(&0) {
+            return;
+        }
+
+        for (index, line) in data.split(|&c| c == b'\n').enumerate() {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            for start in query.find_in_line(line) {
+                pending.push_back(SearchHit {
+                    bank: bank.to_string(),
+                    entry: entry.to_string(),
+                    line: index + 1,
+                    column: start + 1,
+                    line_text: line.to_vec(),
+                });
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for SearchHits<'a> {
+    type Item = Result<SearchHit, SearchError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(hit) = self.pending.pop_front() {
+                return Some(Ok(hit));
+            }
+
+            let (bank, entries) = match &mut self.current {
+                Some(current) => current,
+                None => {
+                    let bank = self.banks.next()?;
+                    let entries = SearchedEntry::of_bank(bank);
+                    self.current.insert((bank, entries.into_iter()))
+                }
+            };
+
+            let Some(entry) = entries.next() else {
+                self.current = None;
+                continue;
+            };
+
+            let prefix = bank.prefix().trim_end_matches(['/', '\\']).to_string();
+            let path = format!("{}/{}", prefix, entry.name()).replace('\\', "/");
+            match entry {
+                SearchedEntry::Stored(entry) => {
+                    if !self.query.accepts(&path, entry.size()) {
+                        continue;
+                    }
+                    match bank.skim.read_entry(&entry) {
+                        Ok(data) => Self::scan_entry(self.query, &mut self.pending, &prefix, &entry.filename, &data),
+                        Err(e) => return Some(Err(e.into()))
+                    }
+                }
+                SearchedEntry::Opened(cached) => {
+                    let data = bank.open_entries.get(&cached).map_or(&[][..], |data| data.get_ref());
+                    if self.query.accepts(&path, data.len() as u32) {
+                        Self::scan_entry(self.query, &mut self.pending, &prefix, cached.written_name(), data);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::BankProperties;
+    use crate::EntryMime;
+    use crate::bank::io::{BankSkimOptions, PboWriter};
+
+    /// Mounts a bank with the prefix `test` holding the given entries, the file backing it is
+    /// removed once opened.
+    fn mounted(name: &str, entries: &[(&str, &[u8])]) -> BankFilesystem {
+        let mut properties = BankProperties::new();
+        properties.set_prefix("test");
+        let entries: Vec<_> = entries.iter()
+            .map(|&(name, data)| (BankSkimEntry::new(name, EntryMime::Decompressed, data.len() as u32, 0), data))
+            .collect();
+
+        let path = std::env::temp_dir().join(format!("bis-search-{}-{}.pbo", std::process::id(), name));
+        fs::write(&path, PboWriter::write_archive(vec![], &properties, &entries).unwrap()).unwrap();
+        let mut filesystem = BankFilesystem::new();
+        let loaded = filesystem.load_bank(&path, BankSkimOptions::default());
+        let _ = fs::remove_file(&path);
+        loaded.unwrap();
+        filesystem
+    }
+
+    fn search(filesystem: &mut BankFilesystem, query: &SearchQuery) -> Vec<(String, usize, usize)> {
+        filesystem.search(query)
+            .map(|hit| hit.unwrap())
+            .map(|hit| (hit.entry, hit.line, hit.column))
+            .collect()
+    }
+
+    #[test]
+    fn hits_are_ordered_by_entry_then_position() {
+        let mut filesystem = mounted("order", &[("b.txt", b"one\r\ntwo two"), ("a.txt", b"two")]);
+        let hits: Vec<SearchHit> = filesystem.search(&SearchQuery::literal("two")).map(|hit| hit.unwrap()).collect();
+
+        let positions: Vec<_> = hits.iter().map(|hit| (hit.entry.as_str(), hit.line, hit.column)).collect();
+        assert_eq!(positions, [("a.txt", 1, 1), ("b.txt", 2, 1), ("b.txt", 2, 5)]);
+        assert_eq!(hits[1].bank, "test");
+        assert_eq!(hits[1].line_text, b"two two");
+    }
+
+    #[test]
+    fn filters_restrict_the_searched_entries() {
+        let mut filesystem = mounted("filters", &[("a.txt", b"two"), ("dir\\b.txt", b"too long")]);
+
+        let query = SearchQuery::regex("t.o").unwrap().with_path_glob("test/dir/*").unwrap();
+        assert_eq!(search(&mut filesystem, &query), [("dir\\b.txt".to_string(), 1, 1)]);
+
+        let query = SearchQuery::regex("t.o").unwrap().with_size_range(0, 3);
+        assert_eq!(search(&mut filesystem, &query), [("a.txt".to_string(), 1, 1)]);
+    }
+
+    #[test]
+    fn binary_entries_are_only_searched_when_included() {
+        let mut filesystem = mounted("binary", &[("a.bin", b"two\0")]);
+
+        assert!(search(&mut filesystem, &SearchQuery::literal("two")).is_empty());
+        let query = SearchQuery::literal("two").with_binary(true);
+        assert_eq!(search(&mut filesystem, &query), [("a.bin".to_string(), 1, 1)]);
+    }
+
+    #[test]
+    fn written_entries_are_searched_as_written() {
+        let mut filesystem = mounted("written", &[("a.txt", b"two"), ("b.txt", b"two")]);
+        filesystem.write_entry("test", "a.txt", b"three".to_vec()).unwrap();
+        filesystem.write_entry("test", "c.txt", b"a two".to_vec()).unwrap();
+
+        let hits = search(&mut filesystem, &SearchQuery::literal("two"));
+        assert_eq!(hits, [("b.txt".to_string(), 1, 1), ("c.txt".to_string(), 1, 3)]);
+        let hits = search(&mut filesystem, &SearchQuery::literal("three"));
+        assert_eq!(hits, [("a.txt".to_string(), 1, 1)]);
+    }
+
+    #[test]
+    fn empty_literals_find_nothing() {
+        let mut filesystem = mounted("empty", &[("a.txt", b"two")]);
+        assert!(search(&mut filesystem, &SearchQuery::literal("")).is_empty());
+    }
+}
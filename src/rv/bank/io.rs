@@ -1,9 +1,18 @@
 
-use std::collections::HashMap;
-use std::io::{Read, Seek};
-use byteorder::{LittleEndian, ReadBytesExt};
-use crate::{BankSkimEntry, Debinarizable, DebinarizationOptions, DebinarizePredicateOption, EntryMime, magic_enum, PboFileSkim};
-use std::io;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use hashbrown::HashMap;
+use crate::core::io::stream::{self as io, Read, Seek, SeekFrom, Write, WriteBytesExt};
+use sha1::{Digest, Sha1};
+use crate::{BankProperties, BankSkimEntry, Binarizable, Debinarizable, DebinarizationOptions, DebinarizeError, DebinarizePredicateOption, EntryHash, EntryMime, PboFileSkim};
+use alloc::sync::Arc;
+use crate::core::io::codec::{BANK_CODEC_FORMAT, CodecError, CodecRegistry};
+use crate::core::io::binarizable::PositionedReader;
+use crate::core::io::context::{ContextReader, DebinarizationContext};
+use crate::core::io::lzss::LzssError;
+use crate::core::io::primitives::{BisReadExt, BisWriteExt};
+use crate::core::profile::{GameProfile, GameTitle};
 use thiserror::Error;
 
 const WIN_DIR: char = '\\';
@@ -26,6 +35,8 @@ pub enum BankSkimError {
     InvalidChecksum,
     #[error("Bank Debinarization Error: The options are configured to forbid obfuscated banks.")]
     Obfuscated,
+    #[error("Bank Debinarization Error: The bank ended before the entry list was terminated.")]
+    UnterminatedEntries,
     #[error(transparent)]
     EntryDebinarization(#[from] EntryMetadataError),
 }
@@ -36,7 +47,14 @@ pub enum EntryError {
     SeekFailed,
     #[error("Entry Read Error: The provided entry was not found in the bank.")]
     EntryNotFound,
-
+    #[error(transparent)]
+    Decompression(#[from] CodecError),
+    #[error("Entry Read Error: The entry is encrypted, only its metadata can be read.")]
+    Encrypted,
+    #[error("Entry Read Error: The entry is stored with the unknown mime {0:#x}, only its packed data can be read.")]
+    UnknownMime(i32),
+    #[error(transparent)]
+    IO(#[from] io::Error),
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -45,6 +63,23 @@ pub enum OffsetLocationStrategy {
     Calculate
 }
 
+/// The bank layouts used across titles, each preset selects which deviations from the modern
+/// layout the reader accepts.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum BankLayout {
+    /// Arma era banks, the first entry may be a version entry followed by header properties.
+    #[default]
+    Arma,
+    /// Operation Flashpoint banks, these never carry a version entry so an entry without a name
+    /// always ends the entry list.
+    Flashpoint,
+    /// Elite era banks, entries may be encrypted and the version entry may have its reserved
+    /// fields set.
+    Elite,
+    /// VBS era banks, laid out like Elite banks.
+    Vbs,
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct BankSkimOptions {
     pub(crate) offset_location_strategy:   OffsetLocationStrategy,
@@ -57,6 +92,11 @@ pub struct BankSkimOptions {
     pub(crate) remove_empty_entries:       bool,
     pub(crate) allow_obfuscated:           bool,
     pub(crate) require_valid_checksum:     bool,
+    pub(crate) allow_version_header:       bool,
+    pub(crate) allow_multiple_versions:    bool,
+    pub(crate) allow_version_reserved:     bool,
+    pub(crate) allow_encrypted_entries:    bool,
+    pub(crate) profile:                    GameProfile,
 }
 
 impl Default for BankSkimOptions {
@@ -72,6 +112,61 @@ impl Default for BankSkimOptions {
             remove_empty_entries: false,
             allow_obfuscated: false,
             require_valid_checksum: false,
+            allow_version_header: true,
+            allow_multiple_versions: true,
+            allow_version_reserved: false,
+            allow_encrypted_entries: false,
+            profile: GameProfile::default(),
+        }
+    }
+}
+
+impl BankLayout {
+    /// The layout the banks of a game are written in.
+    pub fn for_title(title: GameTitle) -> Self {
+        match title {
+            GameTitle::Flashpoint => BankLayout::Flashpoint,
+            GameTitle::Arma | GameTitle::Arma2 | GameTitle::Arma3 | GameTitle::DayZ => BankLayout::Arma,
+        }
+    }
+}
+
+impl BankSkimOptions {
+    /// The options accepting the banks written for the game the profile describes, which the bank
+    /// is then read for.
+    pub fn for_profile(profile: &GameProfile) -> Self {
+        Self { profile: profile.clone(), ..Self::for_layout(BankLayout::for_title(profile.title())) }
+    }
+
+    pub fn profile(&self) -> &GameProfile { &self.profile }
+
+    /// Whether a bank may carry more than one version entry, each adding its header properties.
+    pub fn with_multiple_versions(mut self, allow: bool) -> Self {
+        self.allow_multiple_versions = allow;
+        self
+    }
+
+    /// Whether encrypted entries are listed, only their metadata and packed data can be read.
+    pub fn with_encrypted_entries(mut self, allow: bool) -> Self {
+        self.allow_encrypted_entries = allow;
+        self
+    }
+
+    pub fn for_layout(layout: BankLayout) -> Self {
+        let defaults = Self::default();
+        match layout {
+            BankLayout::Arma => defaults,
+            BankLayout::Flashpoint => Self {
+                allow_version_header: false,
+                require_version_first: false,
+                require_version_entry: false,
+                ..defaults
+            },
+            BankLayout::Elite | BankLayout::Vbs => Self {
+                allow_version_reserved: true,
+                allow_encrypted_entries: true,
+                ..defaults
+            },
         }
     }
 }
@@ -84,10 +179,12 @@ impl DebinarizationOptions for BankSkimOptions {
 
 #[derive(Debug, Error)]
 pub enum EntryMetadataError {
-    #[error("Bank Debinarization Error: Entry mime not supported: {0}")]
-    EntryMimeNotSupported(i32),
     #[error("Bank Debinarization Error: The options are configured to forbid obfuscated entries.")]
     Obfuscated,
+    #[error("Bank Debinarization Error: The options are configured to forbid encrypted entries.")]
+    Encrypted,
+    #[error(transparent)]
+    Decompression(#[from] LzssError),
     #[error("Invalid Name")]
     EntryNameError(
         #[from] EntryNameError
@@ -102,6 +199,29 @@ pub enum EntryMetadataError {
     )
 }
 
+impl DebinarizeError for EntryMetadataError {
+    fn is_eof(&self) -> bool {
+        match self {
+            EntryMetadataError::EntryNameError(error) => error.is_eof(),
+            EntryMetadataError::IO(error) => error.is_eof(),
+            _ => false
+        }
+    }
+
+    fn is_recoverable(&self) -> bool {
+        matches!(self, EntryMetadataError::Obfuscated | EntryMetadataError::Encrypted)
+    }
+}
+
+impl DebinarizeError for EntryNameError {
+    fn is_eof(&self) -> bool {
+        match self {
+            EntryNameError::IO(error) => error.is_eof(),
+            _ => false
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum BankEncryptionError {
     #[error("The encryption format of this pbo is not supported")]
@@ -112,52 +232,74 @@ pub enum BankEncryptionError {
 pub enum EntryNameError {
     #[error("An entry was found with a weird name. I dont know how to handle this yet or if its possible.")]
     Underflow,
+    #[error("Bank Debinarization Error: The header property {0:?} is not valid UTF-8.")]
+    InvalidProperty(Vec<u8>),
     #[error(transparent)]
     IO(
         #[from] io::Error
     )
 }
 
-pub const HEADER_PREFIX_MAGIC: &str = "prefix";
-const HEADER_ENCRYPTION_MAGIC: &str = "hprotect";
-const SERIAL_MAGIC: &str = "registry";
-const PADDING_NAME: &str = "___dummypadding___";
-const ENCRYPTION_MAGIC: &str = "encryption";
-
-
-pub enum EncryptionType {
-    Header {
-        version: i32
-    },
-    Data {
-        headers_size: i32,
-        encoded_headers_size: i32
-    },
-    None
+#[derive(Debug, Error)]
+pub enum BankWriteError {
+    #[error("Bank Binarization Error: The entry name {0} is longer than the maximum path length.")]
+    NameTooLong(String),
+    #[error("Bank Binarization Error: The entry {0} is too large to be stored in a bank.")]
+    EntryTooLarge(String),
+    #[error(transparent)]
+    IO(#[from] io::Error)
 }
 
-pub fn get_encryption_mode<R: Read + Seek>(reader: &mut PboReader<R>, properties: &HashMap<String, String>) -> Result<EncryptionType, EntryMetadataError> {
+pub const HEADER_PREFIX_MAGIC: &str = "prefix";
+pub const HEADER_PRODUCT_MAGIC: &str = "product";
+pub const HEADER_VERSION_MAGIC: &str = "version";
+pub const HEADER_ENCRYPTION_MAGIC: &str = "hprotect";
+pub const SERIAL_MAGIC: &str = "registry";
+/// Every bank layout compresses its entries the same way.
+const ENTRY_CODEC_VERSION: u32 = 0;
+pub const ENCRYPTION_MAGIC: &str = "encryption";
+pub const MIKERO_MAGIC: &str = "Mikero";
+pub const HEMTT_MAGIC: &str = "hemtt";
 
-    // match properties.get(HEADER_ENCRYPTION_MAGIC) {
-    //     None => {}
-    //     Some(value) => {
-    //         let version = reader.read_i32::<LittleEndian>()?;
-    //     }
-    // }
-    Ok(EncryptionType::None)
-}
 
 
 #[derive(Clone, Debug)]
 pub struct PboReader<R: Read + Seek> {
-    reader:   R,
-    position: u64
+    reader: ContextReader<R>,
 }
 
 impl<R: Read + Seek> PboReader<R> {
+    /// Wraps a reader positioned at the start of a bank, for reading its parts one at a time.
+    pub fn new(reader: R) -> Self {
+        Self::with_context(reader, DebinarizationContext::new())
+    }
+
+    /// Wraps a reader positioned at the start of a bank, reading it in the given context. Entries
+    /// are unpacked with its codecs and the entries skipped while skimming are reported to it.
+    pub fn with_context(reader: R, context: DebinarizationContext) -> Self {
+        PboReader { reader: ContextReader::new(reader, context) }
+    }
+
+    pub fn context(&self) -> &DebinarizationContext { self.reader.context() }
+
+    pub fn context_mut(&mut self) -> &mut DebinarizationContext { self.reader.context_mut() }
+
     #[inline]
     pub fn skim_archive(reader: R, options: BankSkimOptions) -> Result<PboFileSkim<R>, BankSkimError> {
-        let mut reader = PboReader { reader, position: 0 };
+        Self::skim_archive_with_codecs(reader, options, Arc::new(CodecRegistry::default()))
+    }
+
+    /// Skims the bank, unpacking compressed entries with the codec registered for banks in the
+    /// given registry. The bank is read in a context for the profile of the options.
+    pub fn skim_archive_with_codecs(reader: R, options: BankSkimOptions, codecs: Arc<CodecRegistry>) -> Result<PboFileSkim<R>, BankSkimError> {
+        let context = DebinarizationContext::new().with_profile(options.profile.clone()).with_codecs(codecs);
+        Self::skim_archive_with_context(reader, options, context)
+    }
+
+    /// Skims the bank in the given context, see [`PboReader::with_context`]. The profile of the
+    /// context is left as is.
+    pub fn skim_archive_with_context(reader: R, options: BankSkimOptions, context: DebinarizationContext) -> Result<PboFileSkim<R>, BankSkimError> {
+        let mut reader = Self::with_context(reader, context);
         let (properties, entries) = reader.process_entries(&options)?;
 
 
@@ -169,11 +311,37 @@ impl<R: Read + Seek> PboReader<R> {
         })
     }
 
+    /// Reads the data of an entry stored at the given absolute offset, unpacking it if the entry
+    /// is marked as compressed. Entries with an unknown mime may be stored in any way, their data
+    /// is only available through [`PboReader::read_packed_entry_data`].
     pub fn read_entry_data(&mut self, entry: &BankSkimEntry, offset: &u64) -> Result<Vec<u8>, EntryError> {
-        // if self.reader.seek(SeekFrom::Start(*offset)).or_else(EntryError::SeekFailed)? == *offset {
-        //     //TODO: read compressed
-        // }
-        Err(EntryError::SeekFailed)
+        match entry.mime {
+            EntryMime::Encrypted => return Err(EntryError::Encrypted),
+            EntryMime::Unknown(mime) => return Err(EntryError::UnknownMime(mime)),
+            _ => {}
+        }
+
+        let data = self.read_packed_entry_data(entry, offset)?;
+        match entry.mime {
+            EntryMime::Compressed => Ok(self.context().codecs().decode(BANK_CODEC_FORMAT, ENTRY_CODEC_VERSION, &data, entry.size_unpacked as usize)?),
+            _ => Ok(data)
+        }
+    }
+
+    /// Reads the data of an entry exactly as it is stored in the bank.
+    pub fn read_packed_entry_data(&mut self, entry: &BankSkimEntry, offset: &u64) -> Result<Vec<u8>, EntryError> {
+        let position = self.reader.seek(SeekFrom::Start(*offset)).map_err(|_| EntryError::SeekFailed)?;
+        if position != *offset {
+            return Err(EntryError::SeekFailed)
+        }
+
+        //The packed size is read from the bank, nothing is allocated for it up front.
+        let mut data = Vec::new();
+        (&mut self.reader).take(entry.size_packed as u64).read_to_end(&mut data)?;
+        match data.len() == entry.size_packed as usize {
+            true => Ok(data),
+            false => Err(io::Error::from(io::ErrorKind::UnexpectedEof).into())
+        }
     }
 
     ///This function does some processing on the embedded entries in the bank file, and all though
@@ -184,76 +352,111 @@ impl<R: Read + Seek> PboReader<R> {
     /// When not using the deprecated offsets, the offsets are calculated it a pretty terrible way.
     /// In order to support this we end up doing all sorts of up/down casting.
     #[inline]
-    fn process_entries(&mut self, options: &BankSkimOptions) -> Result<(HashMap<String, String>, HashMap<BankSkimEntry, u64>), BankSkimError> {
-        let mut properties = HashMap::new();
+    fn process_entries(&mut self, options: &BankSkimOptions) -> Result<(BankProperties, HashMap<BankSkimEntry, u64>), BankSkimError> {
+        let mut properties = BankProperties::new();
         let entries: HashMap<BankSkimEntry, u64>;
-        let end_of_bank: i32;
-        let buffer_start: u64;
+        let header_end: u64;
         {
-            let mut e_offset: i32 = 0;
+            let mut e_offset: u64 = 0;
             let mut first: bool = true;
+            let mut first_data: bool = true;
+            let mut version_count: usize = 0;
+            let mut rejected: Option<BankSkimError> = None;
+            let mut terminated = false;
             let closure_entries = BankSkimEntry::debinarize_while(self, |e, closure_reader| {
-                match options.offset_location_strategy {
-                    OffsetLocationStrategy::Calculate => {
-                        e.start_offset = e_offset as u64;
-                        e_offset += e.size_packed as i32;
+                let was_first = core::mem::replace(&mut first, false);
+                if options.offset_location_strategy == OffsetLocationStrategy::Calculate {
+                    e.start_offset = e_offset;
+                    e_offset += e.size_packed as u64;
+                }
+                if empty_name(e) {
+                    if !options.allow_version_header || e.mime != EntryMime::Version {
+                        terminated = true;
+                        return Ok(DebinarizePredicateOption::Break)
+                    }
+                    if !is_version(e) && !options.allow_version_reserved {
+                        rejected = Some(BankSkimError::VersionNotBlanked);
+                        return Ok(DebinarizePredicateOption::Break)
                     }
-                    _ => {}
+
+                    if version_count > 0 && !options.allow_multiple_versions {
+                        rejected = Some(BankSkimError::MultipleVersionsFound);
+                        return Ok(DebinarizePredicateOption::Break)
+                    }
+                    version_count += 1;
+                    closure_reader.read_properties(&mut properties)?;
+                    return Ok(DebinarizePredicateOption::Skip)
                 }
-                return if e.start_offset <= 0 && options.remove_impossible_offsets {
-                    first = false;
-                    Ok(DebinarizePredicateOption::Skip)
-                } else {
-                    if empty_name(e) {
-                        return if is_version(e) {
-                            if first {
-                                closure_reader.read_properties(&mut properties)?;
-                                match get_encryption_mode::<R>(closure_reader, &properties)? {
-                                    EncryptionType::Header { .. } => {todo!()}
-                                    EncryptionType::Data { .. } => {todo!()}
-                                    EncryptionType::None => {}
-                                }
-                            }
-                            first = false;
-                            Ok(DebinarizePredicateOption::Ok)
-                        } else {
-                            first = false;
-                            Ok(DebinarizePredicateOption::Break)
-                        }
-                    } else { first = false; }
-                    e.filename = convert_dir_slash(&e.filename);
-                    Ok(DebinarizePredicateOption::Ok)
+
+                //Checked after the terminator, which has no offset either. The data of the first entry
+                // starts right after the header, so only it may have a zero offset.
+                let was_first_data = core::mem::replace(&mut first_data, false);
+                if e.start_offset == 0 && !was_first_data && options.remove_impossible_offsets {
+                    closure_reader.reader.warn(format!("Skipped the entry \"{}\" without an offset.", e.filename));
+                    return Ok(DebinarizePredicateOption::Skip)
                 }
+
+                if was_first && options.require_version_first {
+                    rejected = Some(BankSkimError::FirstNotVersion);
+                    return Ok(DebinarizePredicateOption::Break)
+                }
+                if e.mime == EntryMime::Encrypted && !options.allow_encrypted_entries {
+                    return Err(EntryMetadataError::Encrypted)
+                }
+                e.filename = convert_dir_slash(&e.filename);
+                Ok(DebinarizePredicateOption::Ok)
+            }).map_err(|error| match error.is_eof() {
+                //The list was cut off part way through an entry.
+                true => BankSkimError::UnterminatedEntries,
+                false => error.into()
             })?;
-            buffer_start = self.position;
+            if let Some(error) = rejected {
+                return Err(error)
+            }
+            if !terminated {
+                return Err(BankSkimError::UnterminatedEntries)
+            }
+            if version_count == 0 && options.require_version_entry {
+                return Err(BankSkimError::VersionNotFound)
+            }
+            header_end = self.reader.position();
             entries = closure_entries.into_iter().filter_map(|e| {
-                let start = e.start_offset.clone() + buffer_start;
-
-                if options.allow_offsets_to_header || start >= buffer_start {
-                    Some((e, start))
-                } else { None }
+                //Stored offsets are signed, negative ones point back into the header.
+                let start = match options.offset_location_strategy {
+                    OffsetLocationStrategy::Deprecated => header_end.checked_add_signed(e.start_offset as u32 as i32 as i64),
+                    OffsetLocationStrategy::Calculate => header_end.checked_add(e.start_offset),
+                };
+
+                match start {
+                    Some(start) if options.allow_offsets_to_header || start >= header_end => Some((e, start)),
+                    _ => {
+                        self.reader.warn(format!("Skipped the entry \"{}\" pointing into the header.", e.filename));
+                        None
+                    }
+                }
             }).collect();
-            end_of_bank = e_offset;
         }
         Ok((properties, entries))
     }
 
-    #[inline]
-    fn read_file_info(&mut self) -> Result<(bool, BankSkimEntry), BankSkimError> {
-        let entry = self.read_entry()?;
-        return Ok((is_version(&entry), entry))
-    }
-
+    /// Reads an entry in full before checking its mime, so that a rejected entry still leaves the
+    /// reader at the start of the next one.
     #[inline]
     fn read_entry(&mut self) -> Result<BankSkimEntry, EntryMetadataError> {
-        return Ok(
+        let filename = self.read_entry_name()?;
+        let mime = self.read_int()?;
+        let size_unpacked = self.read_int()? as u32;
+        let start_offset = self.read_int()? as u32 as u64;
+        let timestamp = self.read_int()? as u32;
+        let size_packed = self.read_int()? as u32;
+        Ok(
             BankSkimEntry {
-                filename: self.read_entry_name()?,
-                mime: self.read_mime()?,
-                size_unpacked: self.read_int()? as u32,
-                start_offset: self.read_int()? as u64,
-                timestamp: self.read_int()? as u32,
-                size_packed: self.read_int()? as u32,
+                filename,
+                mime: EntryMime::from(mime),
+                size_unpacked,
+                start_offset,
+                timestamp,
+                size_packed,
             }
         )
     }
@@ -261,52 +464,200 @@ impl<R: Read + Seek> PboReader<R> {
 
     #[inline]
     fn read_int(&mut self) -> Result<i32, io::Error> {
-        let val = self.reader.read_i32::<LittleEndian>()?;
-        self.position += 4;
-        Ok(val)
+        self.reader.read_i32_le()
     }
 
     #[inline]
     fn read_mime(&mut self) -> Result<EntryMime, EntryMetadataError> {
-        EntryMime::try_from(self.read_int()?)
+        Ok(EntryMime::from(self.read_int()?))
     }
 
+    /// Entry names are normalised rather than kept as is, invalid characters are replaced.
     #[inline]
     fn read_entry_name(&mut self) -> Result<String, EntryNameError> {
-        let mut vec = Vec::new();
-
-        for _ in 0..MAX_PATH_LENGTH {
-            self.position += 1;
-            match self.reader.read_u8()? as i32 {
-                0 => break,
-                i if i < 0 => {
-                    return Err(EntryNameError::Underflow)
-                },
-                current => vec.push(current as u8),
-            };
-        }
+        Ok(String::from_utf8_lossy(&self.read_asciiz()?).to_lowercase())
+    }
+
+    /// Reads a null terminated string of at most [`MAX_PATH_LENGTH`] bytes as is.
+    #[inline]
+    fn read_asciiz(&mut self) -> Result<Vec<u8>, EntryNameError> {
+        Ok(self.reader.read_asciiz_bytes(MAX_PATH_LENGTH as usize)?)
+    }
 
-        Ok(String::from_utf8(vec.to_owned()).unwrap().to_lowercase())
+    /// Header properties are written back unchanged, so ones that are not valid UTF-8 are
+    /// rejected instead of replacing their invalid characters.
+    #[inline]
+    fn read_property(&mut self) -> Result<String, EntryNameError> {
+        String::from_utf8(self.read_asciiz()?).map_err(|error| EntryNameError::InvalidProperty(error.into_bytes()))
     }
 
     #[inline]
-    fn read_properties(&mut self, properties: &mut HashMap<String, String>) -> Result<(), EntryMetadataError> {
+    fn read_properties(&mut self, properties: &mut BankProperties) -> Result<(), EntryMetadataError> {
         loop {
-            let name = self.read_entry_name()?;
+            let name = self.read_property()?;
 
             if name.is_empty() { break }
 
-            let value =if name.eq(HEADER_PREFIX_MAGIC) {
-                let mut it = self.read_entry_name()?;
-                it.push(BANK_DIR);
-                it
-            } else { self.read_entry_name()? };
-            properties.insert(name, value);
+            let value = self.read_property()?;
+            properties.push(name, value);
         }
         Ok(())
     }
 }
 
+/// Wraps a writer, keeping a running SHA-1 digest of everything written through it. Banks end
+/// with a zero byte followed by this digest.
+#[derive(Clone, Debug)]
+pub struct ChecksumWriter<W: Write> {
+    writer:   W,
+    hasher:   Sha1,
+    position: u64
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer, hasher: Sha1::new(), position: 0 }
+    }
+
+    pub fn position(&self) -> u64 { self.position }
+
+    pub fn digest(&self) -> EntryHash { self.hasher.clone().finalize().into() }
+
+    pub fn into_inner(self) -> W { self.writer }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.writer.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.position += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> { self.writer.flush() }
+}
+
+#[derive(Clone, Debug)]
+pub struct PboWriter<W: Write> {
+    writer: ChecksumWriter<W>
+}
+
+impl<W: Write> PboWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer: ChecksumWriter::new(writer) }
+    }
+
+    /// Writes a complete bank; the entries are given alongside their already packed data, so the
+    /// packed size and offset of each entry are taken from the data rather than the metadata.
+    pub fn write_archive<D: AsRef<[u8]>>(writer: W, properties: &BankProperties, entries: &[(BankSkimEntry, D)]) -> Result<W, BankWriteError> {
+        let mut writer = PboWriter::new(writer);
+        writer.write_version(properties)?;
+
+        let mut offset: u64 = 0;
+        for (entry, data) in entries {
+            let data = data.as_ref();
+            let size_packed = u32::try_from(data.len())
+                .map_err(|_| BankWriteError::EntryTooLarge(entry.filename.clone()))?;
+            writer.write_entry(&BankSkimEntry {
+                start_offset: offset,
+                size_packed,
+                ..entry.clone()
+            })?;
+            offset += data.len() as u64;
+        }
+        writer.write_terminator()?;
+
+        for (_, data) in entries {
+            writer.write_data(data.as_ref())?;
+        }
+        writer.finish()
+    }
+
+    pub fn position(&self) -> u64 { self.writer.position() }
+
+    pub fn write_version(&mut self, properties: &BankProperties) -> Result<(), BankWriteError> {
+        self.write_entry(&BankSkimEntry {
+            filename: String::new(),
+            mime: EntryMime::Version,
+            size_unpacked: 0,
+            start_offset: 0,
+            timestamp: 0,
+            size_packed: 0,
+        })?;
+        self.write_properties(properties)
+    }
+
+    pub fn write_terminator(&mut self) -> Result<(), BankWriteError> {
+        self.write_entry(&BankSkimEntry {
+            filename: String::new(),
+            mime: EntryMime::Decompressed,
+            size_unpacked: 0,
+            start_offset: 0,
+            timestamp: 0,
+            size_packed: 0,
+        })
+    }
+
+    #[inline]
+    pub fn write_entry(&mut self, entry: &BankSkimEntry) -> Result<(), BankWriteError> {
+        self.write_entry_name(&convert_dir_slash(&entry.filename))?;
+        self.write_mime(entry.mime)?;
+        self.write_int(entry.size_unpacked as i32)?;
+        self.write_int(entry.start_offset as i32)?;
+        self.write_int(entry.timestamp as i32)?;
+        self.write_int(entry.size_packed as i32)?;
+        Ok(())
+    }
+
+    #[inline]
+    pub fn write_data(&mut self, data: &[u8]) -> Result<(), BankWriteError> {
+        Ok(self.writer.write_all(data)?)
+    }
+
+    /// Writes the checksum trailer and hands back the underlying writer.
+    pub fn finish(mut self) -> Result<W, BankWriteError> {
+        let digest = self.writer.digest();
+        self.writer.write_u8(0)?;
+        self.writer.write_all(&digest)?;
+        self.writer.flush()?;
+        Ok(self.writer.into_inner())
+    }
+
+    #[inline]
+    fn write_int(&mut self, value: i32) -> Result<(), io::Error> {
+        self.writer.write_i32_le(value)
+    }
+
+    #[inline]
+    fn write_mime(&mut self, mime: EntryMime) -> Result<(), io::Error> {
+        self.write_int(i32::from(mime))
+    }
+
+    #[inline]
+    fn write_entry_name(&mut self, name: &str) -> Result<(), BankWriteError> {
+        if name.len() > MAX_PATH_LENGTH as usize {
+            return Err(BankWriteError::NameTooLong(name.to_string()))
+        }
+
+        self.writer.write_all(name.as_bytes())?;
+        Ok(self.writer.write_u8(0)?)
+    }
+
+    /// Writes the header properties in their original order.
+    #[inline]
+    fn write_properties(&mut self, properties: &BankProperties) -> Result<(), BankWriteError> {
+        for property in properties {
+            self.write_entry_name(&property.key)?;
+            self.write_entry_name(&property.value)?;
+        }
+        self.write_entry_name("")
+    }
+}
+
+impl<R: Read + Seek> PositionedReader for PboReader<R> {
+    fn position(&self) -> u64 { self.reader.position() }
+}
+
 impl<R: Read + Seek> Debinarizable<PboReader<R>> for EntryMime {
     type Error = EntryMetadataError;
 
@@ -323,6 +674,23 @@ impl<R: Read + Seek> Debinarizable<PboReader<R>> for BankSkimEntry {
     }
 }
 
+impl<W: Write> Binarizable<PboWriter<W>> for EntryMime {
+    type Error = BankWriteError;
+
+    fn binarize(&self, writer: &mut PboWriter<W>) -> Result<(), Self::Error> {
+        Ok(writer.write_mime(*self)?)
+    }
+}
+
+impl<W: Write> Binarizable<PboWriter<W>> for BankSkimEntry {
+    type Error = BankWriteError;
+
+    fn binarize(&self, writer: &mut PboWriter<W>) -> Result<(), Self::Error> {
+        writer.write_entry(self)
+    }
+}
+
+/// Whether the entry is a version entry with all of its reserved fields blanked.
 #[inline]
 fn is_version(entry: &BankSkimEntry) -> bool {
     entry.mime == EntryMime::Version && entry.size_packed == 0 && entry.timestamp == 0
@@ -365,10 +733,166 @@ pub fn normalize_path(path: &str, directory: bool) -> String {
 }
 
 #[inline]
-pub fn convert_dir_slash(name: &String) -> String {
+pub fn convert_dir_slash(name: &str) -> String {
     if !name.contains(UNIX_DIR) {
-        return name.clone();
+        return name.to_string();
     }
 
     name.replace(UNIX_DIR, "\\")
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use core::fmt::Debug;
+    use proptest::prelude::*;
+    use crate::core::io::context::Severity;
+    use crate::core::io::stream::Cursor;
+
+    type Writer = PboWriter<Vec<u8>>;
+    type Reader = PboReader<Cursor<Vec<u8>>>;
+
+    fn round_trip<T: Binarizable<Writer> + Debinarizable<Reader>>(value: &T) -> T
+        where <T as Binarizable<Writer>>::Error: Debug, <T as Debinarizable<Reader>>::Error: Debug
+    {
+        let mut writer = PboWriter::new(vec![]);
+        value.binarize(&mut writer).unwrap();
+        let data = writer.writer.into_inner();
+        T::debinarize(&mut PboReader::new(Cursor::new(data))).unwrap()
+    }
+
+    fn mime() -> impl Strategy<Value = EntryMime> {
+        prop_oneof![
+            Just(EntryMime::Decompressed),
+            Just(EntryMime::Compressed),
+            Just(EntryMime::Encrypted),
+            Just(EntryMime::Version),
+            any::<i32>().prop_map(EntryMime::from),
+        ]
+    }
+
+    /// Names as they are read back, lowercase and with backslashes as separators.
+    fn entry() -> impl Strategy<Value = BankSkimEntry> {
+        ("[a-z0-9_. \\\\-]{0,64}", mime(), any::<u32>(), any::<u32>(), any::<u32>(), any::<u32>())
+            .prop_map(|(filename, mime, size_unpacked, start_offset, timestamp, size_packed)| BankSkimEntry {
+                filename,
+                mime,
+                size_unpacked,
+                start_offset: start_offset as u64,
+                timestamp,
+                size_packed,
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn mime_round_trips(mime in mime()) {
+            prop_assert_eq!(round_trip(&mime), mime);
+        }
+
+        #[test]
+        fn entry_round_trips(entry in entry()) {
+            prop_assert_eq!(round_trip(&entry), entry);
+        }
+    }
+
+    #[test]
+    fn longest_name_round_trips() {
+        let entry = BankSkimEntry::new(&"a".repeat(MAX_PATH_LENGTH as usize), EntryMime::Decompressed, 1, 2);
+        assert_eq!(round_trip(&entry), entry);
+    }
+
+    /// Writes a bank holding one byte entries with the given names, overwriting the stored offset
+    /// of the named entry.
+    fn with_offset(names: &[&str], name: &str, offset: u32) -> Vec<u8> {
+        let entries: Vec<_> = names.iter()
+            .map(|name| (BankSkimEntry::new(name, EntryMime::Decompressed, 1, 0), name.as_bytes()))
+            .collect();
+        let mut data = PboWriter::write_archive(vec![], &BankProperties::new(), &entries).unwrap();
+
+        //The name is followed by the mime and unpacked size before the offset.
+        let field = data.windows(name.len() + 2)
+            .position(|window| window[0] == 0 && window[1..] == [name.as_bytes(), &[0]].concat())
+            .unwrap() + name.len() + 2 + 8;
+        data[field..field + 4].copy_from_slice(&offset.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn skipped_entries_are_reported() {
+        let data = with_offset(&["a", "b", "c"], "c", 0);
+        let options = BankSkimOptions { remove_impossible_offsets: true, ..BankSkimOptions::default() };
+
+        let mut skim = PboReader::skim_archive(Cursor::new(data), options).unwrap();
+        let diagnostics = skim.reader.context().diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert!(diagnostics[0].message.contains("\"c\""));
+
+        assert!(skim.get_entry("c").is_none());
+        for name in ["a", "b"] {
+            let entry = skim.get_entry(name).unwrap().clone();
+            assert_eq!(skim.read_entry(&entry).unwrap(), name.as_bytes());
+        }
+    }
+
+    #[test]
+    fn entries_pointing_into_the_header_are_skipped() {
+        let data = with_offset(&["a", "b"], "b", u32::MAX);
+
+        let skim = PboReader::skim_archive(Cursor::new(data.clone()), BankSkimOptions::default()).unwrap();
+        assert!(skim.get_entry("b").is_none());
+        let diagnostics = skim.reader.context().diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.contains("\"b\" pointing into the header"));
+
+        let options = BankSkimOptions { allow_offsets_to_header: true, ..BankSkimOptions::default() };
+        let mut skim = PboReader::skim_archive(Cursor::new(data), options).unwrap();
+        let entry = skim.get_entry("b").unwrap().clone();
+        //The byte right before the data is the last byte of the terminator.
+        assert_eq!(skim.read_entry(&entry).unwrap(), [0]);
+    }
+
+    #[test]
+    fn cut_off_entry_lists_are_unterminated() {
+        let data = with_offset(&["a", "b"], "b", 0);
+        let name = data.windows(3).position(|window| window == b"\0b\0").unwrap() + 1;
+
+        for cut in [name, name + 4] {
+            let result = PboReader::skim_archive(Cursor::new(data[..cut].to_vec()), BankSkimOptions::default());
+            assert!(matches!(result, Err(BankSkimError::UnterminatedEntries)));
+        }
+    }
+
+    #[test]
+    fn unknown_mimes_are_only_read_packed() {
+        let entries = [(BankSkimEntry::new("a", EntryMime::from(7), 2, 0), b"ab")];
+        let data = PboWriter::write_archive(vec![], &BankProperties::new(), &entries).unwrap();
+
+        let mut skim = PboReader::skim_archive(Cursor::new(data), BankSkimOptions::default()).unwrap();
+        let entry = skim.get_entry("a").unwrap().clone();
+        assert!(matches!(skim.read_entry(&entry), Err(EntryError::UnknownMime(7))));
+        assert_eq!(skim.read_packed_entry(&entry).unwrap(), b"ab");
+    }
+
+    #[test]
+    fn packed_sizes_past_the_end_are_rejected() {
+        let entries = [(BankSkimEntry::new("a", EntryMime::Decompressed, 2, 0), b"ab")];
+        let data = PboWriter::write_archive(vec![], &BankProperties::new(), &entries).unwrap();
+
+        let mut skim = PboReader::skim_archive(Cursor::new(data), BankSkimOptions::default()).unwrap();
+        let (entry, &offset) = skim.entries.iter().next().unwrap();
+        let entry = BankSkimEntry { size_packed: u32::MAX, ..entry.clone() };
+        let error = skim.reader.read_packed_entry_data(&entry, &offset).unwrap_err();
+        assert!(matches!(error, EntryError::IO(e) if e.kind() == io::ErrorKind::UnexpectedEof));
+    }
+
+    #[test]
+    fn skims_for_the_profile_of_the_options() {
+        let data = PboWriter::write_archive(vec![], &BankProperties::new(), &[] as &[(BankSkimEntry, &[u8])]).unwrap();
+        let profile = GameProfile::new(GameTitle::Arma2);
+        let skim = PboReader::skim_archive(Cursor::new(data), BankSkimOptions::for_profile(&profile)).unwrap();
+        assert_eq!(skim.reader.context().profile(), &profile);
+    }
+}
@@ -0,0 +1,448 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, Write};
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use crate::EntryHash;
+use crate::bank::io::{BankSkimError, BankSkimOptions, ChecksumWriter, EntryError, PboReader};
+
+const BANK_EXTENSION: &str = "pbo";
+const BANK_FOLDER: &str = "addons";
+const KEY_EXTENSION: &str = "bikey";
+
+#[derive(Error, Debug)]
+pub enum ManifestError {
+    #[error("Manifest Error: Malformed manifest on line {0}.")]
+    Malformed(usize),
+    #[error("Manifest Error: The path {0} is not inside of the mod folder.")]
+    InvalidPath(PathBuf),
+    #[error("Manifest Error: The name {0:?} contains a tab or line break and can not be written.")]
+    UnwritableName(String),
+    #[error(transparent)]
+    Skim(#[from] BankSkimError),
+    #[error(transparent)]
+    Entry(#[from] EntryError),
+    #[error(transparent)]
+    IO(#[from] io::Error),
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum ModFileKind {
+    Key,
+    Loose
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct EntryManifest {
+    pub name:        String,
+    pub size:        u32,
+    pub size_packed: u32,
+    pub hash:        EntryHash,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct BankManifest {
+    pub path:    String,
+    pub prefix:  String,
+    pub size:    u64,
+    pub hash:    EntryHash,
+    pub entries: Vec<EntryManifest>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct FileManifest {
+    pub path: String,
+    pub kind: ModFileKind,
+    pub size: u64,
+    pub hash: EntryHash,
+}
+
+/// Describes every file of a mod folder, banks (the `.pbo` files directly inside of `addons`) are
+/// additionally broken down into their entries. Paths are relative to the mod folder and always use
+/// forward slashes.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Default)]
+pub struct ModManifest {
+    pub banks: Vec<BankManifest>,
+    pub files: Vec<FileManifest>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ManifestMismatch {
+    /// The file is not present locally.
+    Missing(String),
+    /// The file is present but its content differs.
+    Modified(String),
+    /// The bank is present but differs, only the listed entries have to be replaced.
+    BankModified { path: String, entries: Vec<String> },
+    /// The file is present locally but not part of the manifest.
+    Extraneous(String),
+}
+
+impl ModManifest {
+    pub fn generate(root: &Path) -> Result<Self, ManifestError> {
+        let mut manifest = ModManifest::default();
+        for path in walk(root)? {
+            let relative = relative_path(root, &path)?;
+            match extension(&path).as_deref() {
+                Some(BANK_EXTENSION) if is_bank_path(&relative) => manifest.banks.push(BankManifest::generate(&path, relative)?),
+                Some(KEY_EXTENSION) => manifest.files.push(FileManifest::generate(&path, relative, ModFileKind::Key)?),
+                _ => manifest.files.push(FileManifest::generate(&path, relative, ModFileKind::Loose)?),
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Compares a local mod folder against this manifest, listing everything that would have to
+    /// be downloaded (or removed) to make the folder match.
+    pub fn verify(&self, root: &Path) -> Result<Vec<ManifestMismatch>, ManifestError> {
+        let mut mismatches = vec![];
+        let mut local: HashMap<String, PathBuf> = HashMap::new();
+        for path in walk(root)? {
+            local.insert(relative_path(root, &path)?.to_lowercase(), path);
+        }
+
+        for bank in &self.banks {
+            let Some(path) = local.remove(&bank.path.to_lowercase()) else {
+                mismatches.push(ManifestMismatch::Missing(bank.path.clone()));
+                continue
+            };
+            if hash_file(&path)? == (bank.size, bank.hash) {
+                continue
+            }
+
+            let local_bank = match BankManifest::generate(&path, bank.path.clone()) {
+                Ok(it) => it,
+                Err(_) => {
+                    mismatches.push(ManifestMismatch::Modified(bank.path.clone()));
+                    continue
+                }
+            };
+            let local_entries: HashMap<&str, &EntryHash> = local_bank.entries.iter()
+                .map(|entry| (entry.name.as_str(), &entry.hash))
+                .collect();
+            mismatches.push(ManifestMismatch::BankModified {
+                path: bank.path.clone(),
+                entries: bank.entries.iter()
+                    .filter(|&entry| local_entries.get(entry.name.as_str()) != Some(&&entry.hash))
+                    .map(|entry| entry.name.clone())
+                    .collect(),
+            });
+        }
+
+        for file in &self.files {
+            match local.remove(&file.path.to_lowercase()) {
+                None => mismatches.push(ManifestMismatch::Missing(file.path.clone())),
+                Some(path) => if hash_file(&path)? != (file.size, file.hash) {
+                    mismatches.push(ManifestMismatch::Modified(file.path.clone()))
+                }
+            }
+        }
+
+        let mut extraneous: Vec<String> = local.into_values()
+            .map(|path| relative_path(root, &path))
+            .collect::<Result<_, _>>()?;
+        extraneous.sort();
+        mismatches.extend(extraneous.into_iter().map(ManifestMismatch::Extraneous));
+        Ok(mismatches)
+    }
+
+    /// Writes the manifest as tab separated lines, entry lines belong to the bank line before
+    /// them. Names containing tabs or line breaks can not be represented and are rejected.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), ManifestError> {
+        for bank in &self.banks {
+            writeln!(writer, "bank\t{}\t{}\t{}\t{}", field(&bank.path)?, bank.size, to_hex(&bank.hash), field(&bank.prefix)?)?;
+            for entry in &bank.entries {
+                writeln!(writer, "entry\t{}\t{}\t{}\t{}", field(&entry.name)?, entry.size, entry.size_packed, to_hex(&entry.hash))?;
+            }
+        }
+        for file in &self.files {
+            let kind = match file.kind {
+                ModFileKind::Key => "key",
+                ModFileKind::Loose => "file",
+            };
+            writeln!(writer, "{}\t{}\t{}\t{}", kind, field(&file.path)?, file.size, to_hex(&file.hash))?;
+        }
+        Ok(())
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> Result<Self, ManifestError> {
+        let mut manifest = ModManifest::default();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue
+            }
+
+            let malformed = || ManifestError::Malformed(index + 1);
+            let fields: Vec<&str> = line.split('\t').collect();
+            match fields.as_slice() {
+                ["bank", path, size, hash, prefix] => manifest.banks.push(BankManifest {
+                    path: path.to_string(),
+                    prefix: prefix.to_string(),
+                    size: size.parse().map_err(|_| malformed())?,
+                    hash: from_hex(hash).ok_or_else(malformed)?,
+                    entries: vec![],
+                }),
+                ["entry", name, size, size_packed, hash] => manifest.banks.last_mut().ok_or_else(malformed)?
+                    .entries.push(EntryManifest {
+                        name: name.to_string(),
+                        size: size.parse().map_err(|_| malformed())?,
+                        size_packed: size_packed.parse().map_err(|_| malformed())?,
+                        hash: from_hex(hash).ok_or_else(malformed)?,
+                    }),
+                [kind @ ("key" | "file"), path, size, hash] => manifest.files.push(FileManifest {
+                    path: path.to_string(),
+                    kind: if *kind == "key" { ModFileKind::Key } else { ModFileKind::Loose },
+                    size: size.parse().map_err(|_| malformed())?,
+                    hash: from_hex(hash).ok_or_else(malformed)?,
+                }),
+                _ => return Err(malformed())
+            }
+        }
+        Ok(manifest)
+    }
+}
+
+impl BankManifest {
+    fn generate(path: &Path, relative: String) -> Result<Self, ManifestError> {
+        let (size, hash) = hash_file(path)?;
+        //Encrypted entries are listed as well, they are hashed as stored.
+        let options = BankSkimOptions::default().with_encrypted_entries(true);
+        let mut skim = PboReader::skim_archive(File::open(path)?, options)?;
+        let mut skim_entries: Vec<_> = skim.entries()
+            .filter(|entry| !entry.filename().is_empty())
+            .cloned()
+            .collect();
+        skim_entries.sort_by(|a, b| a.filename().cmp(b.filename()));
+
+        let mut entries = Vec::with_capacity(skim_entries.len());
+        for entry in skim_entries {
+            entries.push(EntryManifest {
+                name: entry.filename().to_string(),
+                size: entry.size(),
+                size_packed: entry.size_packed(),
+                hash: skim.hash_entry(&entry)?,
+            });
+        }
+
+        Ok(Self {
+            path: relative,
+            prefix: skim.properties().prefix().unwrap_or_default().to_string(),
+            size,
+            hash,
+            entries,
+        })
+    }
+}
+
+impl FileManifest {
+    fn generate(path: &Path, relative: String, kind: ModFileKind) -> Result<Self, ManifestError> {
+        let (size, hash) = hash_file(path)?;
+        Ok(Self { path: relative, kind, size, hash })
+    }
+}
+
+fn hash_file(path: &Path) -> Result<(u64, EntryHash), io::Error> {
+    let mut hasher = ChecksumWriter::new(io::sink());
+    let size = io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok((size, hasher.digest()))
+}
+
+/// Lists every file below the given folder, sorted so that manifests are reproducible.
+fn walk(root: &Path) -> Result<Vec<PathBuf>, io::Error> {
+    let mut files = vec![];
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(directory) = pending.pop() {
+        for entry in std::fs::read_dir(directory)? {
+            let entry = entry?;
+            match entry.file_type()?.is_dir() {
+                true => pending.push(entry.path()),
+                false => files.push(entry.path())
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn relative_path(root: &Path, path: &Path) -> Result<String, ManifestError> {
+    let relative = path.strip_prefix(root).map_err(|_| ManifestError::InvalidPath(path.to_path_buf()))?;
+    Ok(relative.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/"))
+}
+
+/// Only banks directly inside of the `addons` folder are loaded by the game.
+fn is_bank_path(relative: &str) -> bool {
+    matches!(relative.split_once('/'), Some((folder, name)) if folder.eq_ignore_ascii_case(BANK_FOLDER) && !name.contains('/'))
+}
+
+fn field(text: &str) -> Result<&str, ManifestError> {
+    match text.contains(['\t', '\n', '\r']) {
+        true => Err(ManifestError::UnwritableName(text.to_string())),
+        false => Ok(text)
+    }
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension().map(|it| it.to_string_lossy().to_lowercase())
+}
+
+fn to_hex(hash: &EntryHash) -> String {
+    hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text: &str) -> Option<EntryHash> {
+    let mut hash = EntryHash::default();
+    if text.len() != hash.len() * 2 || !text.is_ascii() {
+        return None
+    }
+
+    for (i, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use sha1::{Digest, Sha1};
+    use crate::{BankProperties, BankSkimEntry, EntryMime};
+    use crate::bank::io::PboWriter;
+
+    /// A mod folder below the temporary directory, removed again once dropped.
+    struct ModFolder(PathBuf);
+
+    impl ModFolder {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!("bis-manifest-{}-{}", std::process::id(), name));
+            let _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(&root).unwrap();
+            ModFolder(root)
+        }
+
+        fn add(&self, path: &str, data: &[u8]) {
+            let path = self.0.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, data).unwrap();
+        }
+
+        fn remove(&self, path: &str) {
+            fs::remove_file(self.0.join(path)).unwrap();
+        }
+    }
+
+    impl Drop for ModFolder {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn bank(entries: &[(&str, EntryMime, &[u8])]) -> Vec<u8> {
+        let mut properties = BankProperties::new();
+        properties.set_prefix("x\\y");
+        let entries: Vec<_> = entries.iter()
+            .map(|&(name, mime, data)| (BankSkimEntry::new(name, mime, 0, 0), data))
+            .collect();
+        PboWriter::write_archive(vec![], &properties, &entries).unwrap()
+    }
+
+    fn hash(data: &[u8]) -> EntryHash { Sha1::digest(data).into() }
+
+    fn sample(name: &str) -> ModFolder {
+        let folder = ModFolder::new(name);
+        folder.add("addons/a.pbo", &bank(&[("b.sqf", EntryMime::Decompressed, b"b"), ("a.sqf", EntryMime::Decompressed, b"a")]));
+        folder.add("keys/a.bikey", b"key");
+        folder.add("mod.cpp", b"name");
+        folder.add("optional/c.pbo", b"not read");
+        folder
+    }
+
+    #[test]
+    fn generates_banks_keys_and_files() {
+        let folder = sample("generate");
+        let manifest = ModManifest::generate(&folder.0).unwrap();
+
+        assert_eq!(manifest.banks.len(), 1);
+        let bank = &manifest.banks[0];
+        assert_eq!((bank.path.as_str(), bank.prefix.as_str()), ("addons/a.pbo", "x\\y"));
+        assert_eq!(bank.hash, hash(&fs::read(folder.0.join("addons/a.pbo")).unwrap()));
+        assert_eq!(bank.entries, [
+            EntryManifest { name: "a.sqf".to_string(), size: 1, size_packed: 1, hash: hash(b"a") },
+            EntryManifest { name: "b.sqf".to_string(), size: 1, size_packed: 1, hash: hash(b"b") },
+        ]);
+
+        let files: Vec<_> = manifest.files.iter().map(|file| (file.path.as_str(), file.kind, file.size)).collect();
+        assert_eq!(files, [
+            ("keys/a.bikey", ModFileKind::Key, 3),
+            ("mod.cpp", ModFileKind::Loose, 4),
+            ("optional/c.pbo", ModFileKind::Loose, 8),
+        ]);
+    }
+
+    #[test]
+    fn entries_that_can_not_be_unpacked_are_hashed_as_stored() {
+        let folder = ModFolder::new("packed");
+        folder.add("addons/a.pbo", &bank(&[("a", EntryMime::Encrypted, b"secret"), ("b", EntryMime::from(7), b"other")]));
+
+        let manifest = ModManifest::generate(&folder.0).unwrap();
+        let hashes: Vec<_> = manifest.banks[0].entries.iter().map(|entry| entry.hash).collect();
+        assert_eq!(hashes, [hash(b"secret"), hash(b"other")]);
+    }
+
+    #[test]
+    fn written_manifests_read_back() {
+        let folder = sample("round-trip");
+        let manifest = ModManifest::generate(&folder.0).unwrap();
+
+        let mut written = vec![];
+        manifest.write(&mut written).unwrap();
+        assert_eq!(ModManifest::read(written.as_slice()).unwrap(), manifest);
+    }
+
+    #[test]
+    fn names_with_separators_are_not_written() {
+        let mut manifest = ModManifest::default();
+        manifest.files.push(FileManifest { path: "a\tb".to_string(), kind: ModFileKind::Loose, size: 0, hash: hash(b"") });
+        assert!(matches!(manifest.write(&mut vec![]), Err(ManifestError::UnwritableName(name)) if name == "a\tb"));
+    }
+
+    #[test]
+    fn malformed_lines_are_reported() {
+        let entry_first = format!("\nentry\ta\t1\t1\t{}\n", to_hex(&hash(b"a")));
+        assert!(matches!(ModManifest::read(entry_first.as_bytes()), Err(ManifestError::Malformed(2))));
+        assert!(matches!(ModManifest::read("file\ta\t1\tzz\n".as_bytes()), Err(ManifestError::Malformed(1))));
+        assert!(matches!(ModManifest::read("folder\ta\n".as_bytes()), Err(ManifestError::Malformed(1))));
+    }
+
+    #[test]
+    fn verify_lists_what_differs() {
+        let folder = sample("verify");
+        let manifest = ModManifest::generate(&folder.0).unwrap();
+        assert!(manifest.verify(&folder.0).unwrap().is_empty());
+
+        folder.add("addons/a.pbo", &bank(&[("a.sqf", EntryMime::Decompressed, b"a"), ("b.sqf", EntryMime::Decompressed, b"changed")]));
+        folder.remove("keys/a.bikey");
+        folder.add("mod.cpp", b"other");
+        folder.add("addons/new.pbo", b"new");
+        assert_eq!(manifest.verify(&folder.0).unwrap(), [
+            ManifestMismatch::BankModified { path: "addons/a.pbo".to_string(), entries: vec!["b.sqf".to_string()] },
+            ManifestMismatch::Missing("keys/a.bikey".to_string()),
+            ManifestMismatch::Modified("mod.cpp".to_string()),
+            ManifestMismatch::Extraneous("addons/new.pbo".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn unreadable_banks_are_modified_as_a_whole() {
+        let folder = sample("unreadable");
+        let manifest = ModManifest::generate(&folder.0).unwrap();
+
+        folder.add("addons/a.pbo", b"garbage");
+        assert_eq!(manifest.verify(&folder.0).unwrap(), [ManifestMismatch::Modified("addons/a.pbo".to_string())]);
+    }
+}
@@ -1,18 +1,40 @@
 
 pub mod path;
 pub mod io;
+#[cfg(feature = "std")]
+pub mod fs;
+#[cfg(feature = "std")]
+pub mod diff;
+#[cfg(feature = "std")]
+pub mod delta;
+#[cfg(feature = "std")]
+pub mod manifest;
+pub mod properties;
+#[cfg(feature = "std")]
+pub mod pack;
 
-use std::collections::HashMap;
-use std::io::{Read, Seek};
-use crate::bank::io::{BankSkimOptions, EntryError, EntryMetadataError};
+pub use properties::*;
+
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use hashbrown::HashMap;
+use crate::bank::io::{BankSkimOptions, EntryError};
 use crate::rv::io::PboReader;
+use crate::core::io::codec::CodecRegistry;
+use crate::core::io::stream::{Read, Seek};
 use crate::magic_enum;
+use sha1::{Digest, Sha1};
+
+/// SHA-1 digest of the unpacked contents of an entry.
+pub type EntryHash = [u8; 20];
 
 magic_enum! {
     i32,
     EntryMime,
-    EntryMetadataError,
-    EntryMimeNotSupported {
+    Unknown {
         Decompressed = 0x00000000,
         Compressed   = 0x43707273,
         Encrypted    = 0x456e6372,
@@ -26,7 +48,7 @@ pub struct PboFileSkim<R: Read + Seek> {
     pub(crate) reader:        PboReader<R>,
     pub(crate) entries:       HashMap<BankSkimEntry, u64>,
     pub(crate) options:       BankSkimOptions,
-    pub(crate) properties:    HashMap<String, String>
+    pub(crate) properties:    BankProperties
 }
 
 impl<R: Read + Seek> PboFileSkim<R> {
@@ -36,8 +58,42 @@ impl<R: Read + Seek> PboFileSkim<R> {
         })
     }
 
+    /// Replaces the codecs compressed entries are unpacked with.
+    pub fn set_codecs(&mut self, codecs: Arc<CodecRegistry>) {
+        self.reader.context_mut().set_codecs(codecs);
+    }
+
     pub fn read_entry(&mut self, entry: &BankSkimEntry) -> Result<Vec<u8>, EntryError> {
-        self.reader.read_entry_data(entry, self.entries.get(entry).unwrap())
+        let offset = *self.entries.get(entry).ok_or(EntryError::EntryNotFound)?;
+        self.reader.read_entry_data(entry, &offset)
+    }
+
+    pub fn read_packed_entry(&mut self, entry: &BankSkimEntry) -> Result<Vec<u8>, EntryError> {
+        let offset = *self.entries.get(entry).ok_or(EntryError::EntryNotFound)?;
+        self.reader.read_packed_entry_data(entry, &offset)
+    }
+
+    /// Hashes the unpacked contents of an entry, entries that can not be unpacked (encrypted ones
+    /// or ones with an unknown mime) are hashed as they are stored.
+    pub fn hash_entry(&mut self, entry: &BankSkimEntry) -> Result<EntryHash, EntryError> {
+        let data = match self.read_entry(entry) {
+            Err(EntryError::Encrypted | EntryError::UnknownMime(_)) => self.read_packed_entry(entry)?,
+            data => data?
+        };
+        Ok(Sha1::digest(data).into())
+    }
+
+    pub fn properties(&self) -> &BankProperties {
+        &self.properties
+    }
+
+    /// The options the bank was skimmed with.
+    pub fn options(&self) -> &BankSkimOptions {
+        &self.options
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &BankSkimEntry> {
+        self.entries.keys()
     }
 }
 
@@ -51,6 +107,56 @@ pub struct BankSkimEntry {
     pub(crate) size_packed:   u32,
 }
 
+impl BankSkimEntry {
+    /// Creates the metadata for an entry about to be written, the offset and packed size are
+    /// filled in by the writer.
+    pub fn new(filename: &str, mime: EntryMime, size_unpacked: u32, timestamp: u32) -> Self {
+        Self {
+            filename: filename.to_string(),
+            mime,
+            size_unpacked,
+            start_offset: 0,
+            timestamp,
+            size_packed: 0,
+        }
+    }
+
+    pub fn filename(&self) -> &str { &self.filename }
+
+    pub fn mime(&self) -> EntryMime { self.mime }
 
+    pub fn timestamp(&self) -> u32 { self.timestamp }
 
+    #[cfg(feature = "std")]
+    pub fn modified(&self) -> SystemTime { timestamp_to_time(self.timestamp) }
 
+    #[cfg(feature = "std")]
+    pub fn set_modified(&mut self, time: SystemTime) { self.timestamp = time_to_timestamp(time) }
+
+    pub fn size_packed(&self) -> u32 { self.size_packed }
+
+    /// The size of the entry once unpacked, uncompressed entries usually leave the unpacked size
+    /// blank so the packed size is used instead.
+    pub fn size(&self) -> u32 {
+        match self.mime {
+            EntryMime::Compressed => self.size_unpacked,
+            _ => self.size_packed
+        }
+    }
+}
+
+/// Converts an entry timestamp, stored as seconds since the unix epoch, to a system time.
+#[cfg(feature = "std")]
+pub fn timestamp_to_time(timestamp: u32) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(timestamp as u64)
+}
+
+/// Converts a system time to an entry timestamp, times outside of the range a timestamp can hold
+/// are clamped.
+#[cfg(feature = "std")]
+pub fn time_to_timestamp(time: SystemTime) -> u32 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs().min(u32::MAX as u64) as u32,
+        Err(_) => 0
+    }
+}
@@ -0,0 +1,410 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::time::SystemTime;
+use crate::{BankProperties, BankSkimEntry, EntryMime, time_to_timestamp};
+use crate::bank::io::{BankWriteError, PboWriter};
+use crate::core::io::lzss::{LzssEncoder, LzssLevel};
+
+const STORED_EXTENSIONS: [&str; 12] = ["paa", "pac", "ogg", "wss", "wav", "p3d", "rtm", "wrp", "jpg", "png", "lip", "bik"];
+const COMPRESSED_EXTENSIONS: [&str; 14] = [
+    "sqf", "sqs", "fsm", "cpp", "hpp", "h", "hh", "inc", "ext", "sqm", "rvmat", "bisurf", "xml", "csv"
+];
+
+/// Decides which timestamp an entry is written with.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum TimestampPolicy {
+    /// Keep the timestamp the entry was added with.
+    #[default]
+    Preserve,
+    /// Use the modification time of the file the entry was packed from, entries that were not
+    /// packed from a file keep their timestamp.
+    SourceModified,
+    /// Use the time the bank is written at.
+    Now,
+    /// Use the same value for every entry.
+    Fixed(u32),
+}
+
+impl TimestampPolicy {
+    /// Zeroed timestamps, so that packing the same files always produces the same bank.
+    pub fn reproducible() -> Self { TimestampPolicy::Fixed(0) }
+
+    pub fn resolve(&self, original: u32, source_modified: Option<SystemTime>, now: SystemTime) -> u32 {
+        match self {
+            TimestampPolicy::Preserve => original,
+            TimestampPolicy::SourceModified => source_modified.map_or(original, time_to_timestamp),
+            TimestampPolicy::Now => time_to_timestamp(now),
+            TimestampPolicy::Fixed(timestamp) => *timestamp,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum CompressionRule {
+    Compress,
+    Store
+}
+
+/// Decides which entries are written compressed. An entry is only compressed when its extension
+/// allows it, it is at least `min_size` bytes long and compressing it saves at least
+/// `min_savings` percent, otherwise it is stored as is.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct CompressionPolicy {
+    extensions:   BTreeMap<String, CompressionRule>,
+    default_rule: CompressionRule,
+    min_size:     u32,
+    min_savings:  u8,
+    level:        LzssLevel,
+}
+
+/// What ended up being written for a single entry.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct PackedEntry {
+    pub name:        String,
+    pub mime:        EntryMime,
+    pub size:        u32,
+    pub size_packed: u32,
+}
+
+impl Default for CompressionPolicy {
+    /// Stores every entry uncompressed.
+    fn default() -> Self {
+        Self {
+            extensions: BTreeMap::new(),
+            default_rule: CompressionRule::Store,
+            min_size: 0,
+            min_savings: 0,
+            level: LzssLevel::default(),
+        }
+    }
+}
+
+impl CompressionPolicy {
+    /// Mirrors the official packers, text formats are compressed while formats the engine expects
+    /// to read raw (textures, sounds, models) are always stored.
+    pub fn official() -> Self {
+        let mut policy = Self::default().with_min_size(1024).with_min_savings(10);
+        for extension in STORED_EXTENSIONS {
+            policy = policy.with_extension(extension, CompressionRule::Store);
+        }
+        for extension in COMPRESSED_EXTENSIONS {
+            policy = policy.with_extension(extension, CompressionRule::Compress);
+        }
+        policy
+    }
+
+    /// Sets the rule for files with the given extension, extensions are compared
+    /// case-insensitively and may be given with or without the leading dot.
+    pub fn with_extension(mut self, extension: &str, rule: CompressionRule) -> Self {
+        self.extensions.insert(extension.trim_start_matches('.').to_lowercase(), rule);
+        self
+    }
+
+    /// The rule used for extensions without an explicit rule.
+    pub fn with_default_rule(mut self, rule: CompressionRule) -> Self {
+        self.default_rule = rule;
+        self
+    }
+
+    pub fn with_min_size(mut self, min_size: u32) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// The percentage compressing has to save for the compressed data to be kept, capped at 100.
+    pub fn with_min_savings(mut self, percent: u8) -> Self {
+        self.min_savings = percent.min(100);
+        self
+    }
+
+    pub fn with_level(mut self, level: LzssLevel) -> Self {
+        self.level = level;
+        self
+    }
+
+    pub fn level(&self) -> LzssLevel { self.level }
+
+    pub fn rule_for(&self, name: &str) -> CompressionRule {
+        let filename = name.rsplit(['\\', '/']).next().unwrap_or(name);
+        filename.rsplit_once('.')
+            .and_then(|(_, extension)| self.extensions.get(&extension.to_lowercase()))
+            .copied()
+            .unwrap_or(self.default_rule)
+    }
+
+    /// Whether compressing the entry should be attempted at all.
+    pub fn should_compress(&self, name: &str, size: usize) -> bool {
+        size > 0 && size >= self.min_size as usize && self.rule_for(name) == CompressionRule::Compress
+    }
+
+    /// Whether the compressed data saves enough to be kept over the original.
+    pub fn accepts(&self, size: usize, size_packed: usize) -> bool {
+        let saved = size.saturating_sub(size_packed) as u64;
+        size_packed < size && saved * 100 >= self.min_savings as u64 * size as u64
+    }
+
+    /// Compresses the data if the policy allows it, returning the mime to store the entry with
+    /// and the compressed data when it was kept. The encoder is expected to be created with the
+    /// level of this policy.
+    pub fn apply(&self, encoder: &mut LzssEncoder, name: &str, data: &[u8]) -> (EntryMime, Option<Vec<u8>>) {
+        if !self.should_compress(name, data.len()) {
+            return (EntryMime::Decompressed, None)
+        }
+
+        let packed = encoder.encode(data);
+        match self.accepts(data.len(), packed.len()) {
+            true => (EntryMime::Compressed, Some(packed)),
+            false => (EntryMime::Decompressed, None)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+struct PackEntry {
+    name:            String,
+    data:            Vec<u8>,
+    timestamp:       u32,
+    source_modified: Option<SystemTime>,
+}
+
+/// Collects unpacked entries and writes them out as a bank, applying the configured policies to
+/// every entry.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Default)]
+pub struct BankPacker {
+    properties:       BankProperties,
+    entries:          Vec<PackEntry>,
+    timestamp_policy: TimestampPolicy,
+    compression:      CompressionPolicy,
+}
+
+impl BankPacker {
+    pub fn new(properties: BankProperties) -> Self {
+        Self { properties, ..Self::default() }
+    }
+
+    pub fn with_timestamp_policy(mut self, policy: TimestampPolicy) -> Self {
+        self.timestamp_policy = policy;
+        self
+    }
+
+    pub fn with_compression(mut self, policy: CompressionPolicy) -> Self {
+        self.compression = policy;
+        self
+    }
+
+    pub fn properties_mut(&mut self) -> &mut BankProperties { &mut self.properties }
+
+    pub fn add_entry(&mut self, name: &str, data: Vec<u8>, timestamp: u32) {
+        self.entries.push(PackEntry {
+            name: name.to_string(),
+            data,
+            timestamp,
+            source_modified: None,
+        });
+    }
+
+    pub fn add_file(&mut self, name: &str, path: &Path) -> Result<(), BankWriteError> {
+        let source_modified = fs::metadata(path)?.modified().ok();
+        self.entries.push(PackEntry {
+            name: name.to_string(),
+            data: fs::read(path)?,
+            timestamp: 0,
+            source_modified,
+        });
+        Ok(())
+    }
+
+    /// Adds every file below the given folder, named by their path relative to it.
+    pub fn add_directory(&mut self, root: &Path) -> Result<(), BankWriteError> {
+        let mut pending = vec![root.to_path_buf()];
+        let mut files = vec![];
+        while let Some(directory) = pending.pop() {
+            for entry in fs::read_dir(directory)? {
+                let entry = entry?;
+                match entry.file_type()?.is_dir() {
+                    true => pending.push(entry.path()),
+                    false => files.push(entry.path())
+                }
+            }
+        }
+        files.sort();
+
+        for path in files {
+            let name = path.strip_prefix(root).unwrap_or(&path).components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("\\");
+            self.add_file(&name, &path)?;
+        }
+        Ok(())
+    }
+
+    pub fn write<W: Write>(&self, writer: W) -> Result<W, BankWriteError> {
+        Ok(self.write_with_report(writer)?.0)
+    }
+
+    /// Writes the bank, also returning how every entry ended up being stored.
+    pub fn write_with_report<W: Write>(&self, writer: W) -> Result<(W, Vec<PackedEntry>), BankWriteError> {
+        let now = SystemTime::now();
+        let mut encoder = LzssEncoder::new(self.compression.level);
+        let mut report = Vec::with_capacity(self.entries.len());
+        let mut entries: Vec<(BankSkimEntry, Vec<u8>)> = Vec::with_capacity(self.entries.len());
+        for entry in &self.entries {
+            let size = u32::try_from(entry.data.len())
+                .map_err(|_| BankWriteError::EntryTooLarge(entry.name.clone()))?;
+            let timestamp = self.timestamp_policy.resolve(entry.timestamp, entry.source_modified, now);
+            let (mime, packed) = self.compression.apply(&mut encoder, &entry.name, &entry.data);
+            let data = packed.unwrap_or_else(|| entry.data.clone());
+            let size_unpacked = match mime {
+                EntryMime::Compressed => size,
+                _ => 0
+            };
+
+            report.push(PackedEntry {
+                name: entry.name.clone(),
+                mime,
+                size,
+                size_packed: data.len() as u32,
+            });
+            entries.push((BankSkimEntry::new(&entry.name, mime, size_unpacked, timestamp), data));
+        }
+        Ok((PboWriter::write_archive(writer, &self.properties, &entries)?, report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::time::{Duration, UNIX_EPOCH};
+    use crate::bank::io::{BankSkimOptions, PboReader};
+    use crate::core::io::lzss;
+
+    fn at(seconds: u64) -> SystemTime { UNIX_EPOCH + Duration::from_secs(seconds) }
+
+    #[test]
+    fn timestamp_policies_pick_their_source() {
+        let (modified, now) = (Some(at(20)), at(30));
+        assert_eq!(TimestampPolicy::Preserve.resolve(10, modified, now), 10);
+        assert_eq!(TimestampPolicy::SourceModified.resolve(10, modified, now), 20);
+        assert_eq!(TimestampPolicy::SourceModified.resolve(10, None, now), 10);
+        assert_eq!(TimestampPolicy::Now.resolve(10, modified, now), 30);
+        assert_eq!(TimestampPolicy::Fixed(40).resolve(10, modified, now), 40);
+        assert_eq!(TimestampPolicy::reproducible().resolve(10, modified, now), 0);
+    }
+
+    #[test]
+    fn timestamps_saturate_outside_of_their_range() {
+        assert_eq!(TimestampPolicy::Now.resolve(10, None, at(u32::MAX as u64 + 1)), u32::MAX);
+        assert_eq!(TimestampPolicy::Now.resolve(10, None, UNIX_EPOCH - Duration::from_secs(1)), 0);
+    }
+
+    fn noise(seed: u32, length: usize) -> Vec<u8> {
+        let mut state = seed.max(1);
+        (0..length).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            (state >> 24) as u8
+        }).collect()
+    }
+
+    fn text(length: usize) -> Vec<u8> {
+        b"class a { value = 1; };\n".iter().copied().cycle().take(length).collect()
+    }
+
+    #[test]
+    fn rules_are_picked_by_extension() {
+        let policy = CompressionPolicy::default()
+            .with_extension(".SQF", CompressionRule::Compress)
+            .with_default_rule(CompressionRule::Store);
+        assert_eq!(policy.rule_for("addons\\dir.paa\\a.Sqf"), CompressionRule::Compress);
+        assert_eq!(policy.rule_for("dir.sqf/a"), CompressionRule::Store);
+        assert_eq!(policy.rule_for("a.txt"), CompressionRule::Store);
+
+        let official = CompressionPolicy::official();
+        assert_eq!(official.rule_for("a.paa"), CompressionRule::Store);
+        assert_eq!(official.rule_for("a.cpp"), CompressionRule::Compress);
+        assert_eq!(official.rule_for("a.unknown"), CompressionRule::Store);
+    }
+
+    #[test]
+    fn small_entries_are_not_compressed() {
+        let policy = CompressionPolicy::default().with_default_rule(CompressionRule::Compress).with_min_size(64);
+        assert!(!policy.should_compress("a", 0));
+        assert!(!policy.should_compress("a", 63));
+        assert!(policy.should_compress("a", 64));
+    }
+
+    #[test]
+    fn compressed_data_has_to_save_enough() {
+        let policy = CompressionPolicy::default().with_min_savings(10);
+        assert!(policy.accepts(100, 90));
+        assert!(!policy.accepts(100, 91));
+        assert!(!policy.accepts(100, 100));
+        assert!(!CompressionPolicy::default().accepts(100, 101));
+        assert!(CompressionPolicy::default().with_min_savings(200).accepts(100, 0));
+    }
+
+    #[test]
+    fn incompressible_data_is_stored() {
+        let policy = CompressionPolicy::default().with_default_rule(CompressionRule::Compress);
+        let mut encoder = LzssEncoder::new(policy.level());
+
+        assert_eq!(policy.apply(&mut encoder, "a", &noise(1, 4096)), (EntryMime::Decompressed, None));
+        let (mime, packed) = policy.apply(&mut encoder, "a", &text(4096));
+        assert_eq!(mime, EntryMime::Compressed);
+        assert_eq!(lzss::decode(&packed.unwrap(), 4096).unwrap(), text(4096));
+    }
+
+    #[test]
+    fn packers_report_how_entries_were_stored() {
+        let mut packer = BankPacker::new(BankProperties::new())
+            .with_compression(CompressionPolicy::official())
+            .with_timestamp_policy(TimestampPolicy::reproducible());
+        packer.properties_mut().set_prefix("x");
+        packer.add_entry("script.sqf", text(2048), 10);
+        packer.add_entry("small.sqf", text(16), 10);
+        packer.add_entry("texture.paa", text(2048), 10);
+        packer.add_entry("noise.sqf", noise(2, 2048), 10);
+
+        let (data, report) = packer.write_with_report(vec![]).unwrap();
+        let stored: Vec<_> = report.iter().map(|entry| (entry.name.as_str(), entry.mime)).collect();
+        assert_eq!(stored, [
+            ("script.sqf", EntryMime::Compressed),
+            ("small.sqf", EntryMime::Decompressed),
+            ("texture.paa", EntryMime::Decompressed),
+            ("noise.sqf", EntryMime::Decompressed),
+        ]);
+        assert!(report[0].size_packed < report[0].size);
+        assert_eq!((report[2].size, report[2].size_packed), (2048, 2048));
+
+        let mut skim = PboReader::skim_archive(Cursor::new(data), BankSkimOptions::default()).unwrap();
+        assert_eq!(skim.properties().prefix(), Some("x"));
+        for (name, expected) in [("script.sqf", text(2048)), ("texture.paa", text(2048))] {
+            let entry = skim.get_entry(name).unwrap().clone();
+            assert_eq!(entry.timestamp(), 0);
+            assert_eq!(skim.read_entry(&entry).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn directories_are_added_with_relative_names() {
+        let root = std::env::temp_dir().join(format!("bis-pack-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("dir")).unwrap();
+        fs::write(root.join("b.txt"), b"b").unwrap();
+        fs::write(root.join("dir").join("a.txt"), b"a").unwrap();
+
+        let mut packer = BankPacker::default();
+        let added = packer.add_directory(&root);
+        let _ = fs::remove_dir_all(&root);
+        added.unwrap();
+
+        let (_, report) = packer.write_with_report(vec![]).unwrap();
+        let names: Vec<_> = report.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["b.txt", "dir\\a.txt"]);
+    }
+}
@@ -0,0 +1,226 @@
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+use crate::bank::io::{BANK_DIR, ENCRYPTION_MAGIC, HEADER_ENCRYPTION_MAGIC, HEADER_PREFIX_MAGIC, HEADER_PRODUCT_MAGIC, HEADER_VERSION_MAGIC, HEMTT_MAGIC, MIKERO_MAGIC, SERIAL_MAGIC};
+
+/// The header keys known to the engine or written by common packing tools, anything else is kept
+/// as is.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum BankPropertyKey {
+    Prefix,
+    Product,
+    Version,
+    HeaderProtection,
+    Registry,
+    Encryption,
+    Mikero,
+    Hemtt,
+    Other(String)
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct BankProperty {
+    pub key:   String,
+    pub value: String,
+}
+
+/// The key/value pairs stored after the version entry of a bank. Pairs keep the order they were
+/// read in and duplicate keys are preserved so that headers round-trip unchanged.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Default)]
+pub struct BankProperties {
+    pairs: Vec<BankProperty>
+}
+
+impl BankPropertyKey {
+    pub fn as_str(&self) -> &str {
+        match self {
+            BankPropertyKey::Prefix => HEADER_PREFIX_MAGIC,
+            BankPropertyKey::Product => HEADER_PRODUCT_MAGIC,
+            BankPropertyKey::Version => HEADER_VERSION_MAGIC,
+            BankPropertyKey::HeaderProtection => HEADER_ENCRYPTION_MAGIC,
+            BankPropertyKey::Registry => SERIAL_MAGIC,
+            BankPropertyKey::Encryption => ENCRYPTION_MAGIC,
+            BankPropertyKey::Mikero => MIKERO_MAGIC,
+            BankPropertyKey::Hemtt => HEMTT_MAGIC,
+            BankPropertyKey::Other(it) => it,
+        }
+    }
+}
+
+impl From<&str> for BankPropertyKey {
+    fn from(value: &str) -> Self {
+        [
+            BankPropertyKey::Prefix,
+            BankPropertyKey::Product,
+            BankPropertyKey::Version,
+            BankPropertyKey::HeaderProtection,
+            BankPropertyKey::Registry,
+            BankPropertyKey::Encryption,
+            BankPropertyKey::Mikero,
+            BankPropertyKey::Hemtt,
+        ].into_iter()
+            .find(|key| key.as_str().eq_ignore_ascii_case(value))
+            .unwrap_or_else(|| BankPropertyKey::Other(value.to_string()))
+    }
+}
+
+impl AsRef<str> for BankPropertyKey {
+    fn as_ref(&self) -> &str { self.as_str() }
+}
+
+impl Display for BankPropertyKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result { f.write_str(self.as_str()) }
+}
+
+impl BankProperty {
+    pub fn new(key: &str, value: &str) -> Self {
+        Self { key: key.to_string(), value: value.to_string() }
+    }
+
+    pub fn kind(&self) -> BankPropertyKey { BankPropertyKey::from(self.key.as_str()) }
+}
+
+impl BankProperties {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn len(&self) -> usize { self.pairs.len() }
+
+    pub fn is_empty(&self) -> bool { self.pairs.is_empty() }
+
+    pub fn iter(&self) -> impl Iterator<Item = &BankProperty> { self.pairs.iter() }
+
+    /// The value of the first pair with the given key, keys are compared case-insensitively.
+    pub fn get(&self, key: impl AsRef<str>) -> Option<&str> {
+        self.get_all(key).next()
+    }
+
+    pub fn get_all(&self, key: impl AsRef<str>) -> impl Iterator<Item = &str> {
+        self.pairs.iter()
+            .filter(move |pair| pair.key.eq_ignore_ascii_case(key.as_ref()))
+            .map(|pair| pair.value.as_str())
+    }
+
+    pub fn contains(&self, key: impl AsRef<str>) -> bool { self.get(key).is_some() }
+
+    /// The prefix of the bank without any trailing separators.
+    pub fn prefix(&self) -> Option<&str> {
+        self.get(BankPropertyKey::Prefix).map(|it| it.trim_end_matches([BANK_DIR, '\\']))
+    }
+
+    pub fn product(&self) -> Option<&str> { self.get(BankPropertyKey::Product) }
+
+    pub fn version(&self) -> Option<&str> { self.get(BankPropertyKey::Version) }
+
+    /// Appends a pair, keeping any existing pairs with the same key.
+    pub fn push(&mut self, key: impl AsRef<str>, value: impl AsRef<str>) {
+        self.pairs.push(BankProperty::new(key.as_ref(), value.as_ref()));
+    }
+
+    /// Replaces the value of the first pair with the given key, dropping any duplicates, or appends
+    /// a new pair when the key is not present yet.
+    pub fn set(&mut self, key: impl AsRef<str>, value: impl AsRef<str>) {
+        let (key, value) = (key.as_ref(), value.as_ref());
+        match self.pairs.iter().position(|pair| pair.key.eq_ignore_ascii_case(key)) {
+            None => self.push(key, value),
+            Some(index) => {
+                self.pairs[index].value = value.to_string();
+                let mut position = 0;
+                self.pairs.retain(|pair| {
+                    let keep = position <= index || !pair.key.eq_ignore_ascii_case(key);
+                    position += 1;
+                    keep
+                });
+            }
+        }
+    }
+
+    /// Removes every pair with the given key, returning their values in order.
+    pub fn remove(&mut self, key: impl AsRef<str>) -> Vec<String> {
+        let key = key.as_ref();
+        let mut removed = vec![];
+        self.pairs.retain(|pair| {
+            let matches = pair.key.eq_ignore_ascii_case(key);
+            if matches { removed.push(pair.value.clone()) }
+            !matches
+        });
+        removed
+    }
+
+    pub fn set_prefix(&mut self, prefix: &str) { self.set(BankPropertyKey::Prefix, prefix) }
+
+    pub fn set_product(&mut self, product: &str) { self.set(BankPropertyKey::Product, product) }
+
+    pub fn set_version(&mut self, version: &str) { self.set(BankPropertyKey::Version, version) }
+}
+
+impl FromIterator<BankProperty> for BankProperties {
+    fn from_iter<T: IntoIterator<Item = BankProperty>>(iter: T) -> Self {
+        Self { pairs: iter.into_iter().collect() }
+    }
+}
+
+impl<'a> IntoIterator for &'a BankProperties {
+    type Item = &'a BankProperty;
+    type IntoIter = core::slice::Iter<'a, BankProperty>;
+
+    fn into_iter(self) -> Self::IntoIter { self.pairs.iter() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties(pairs: &[(&str, &str)]) -> BankProperties {
+        pairs.iter().map(|&(key, value)| BankProperty::new(key, value)).collect()
+    }
+
+    fn pairs(properties: &BankProperties) -> Vec<(&str, &str)> {
+        properties.iter().map(|pair| (pair.key.as_str(), pair.value.as_str())).collect()
+    }
+
+    #[test]
+    fn keys_are_recognised_case_insensitively() {
+        assert_eq!(BankPropertyKey::from("PREFIX"), BankPropertyKey::Prefix);
+        assert_eq!(BankPropertyKey::from("mikero"), BankPropertyKey::Mikero);
+        assert_eq!(BankPropertyKey::from("HEMTT"), BankPropertyKey::Hemtt);
+        assert_eq!(BankPropertyKey::from("author"), BankPropertyKey::Other("author".to_string()));
+        assert_eq!(BankProperty::new("Hprotect", "1").kind(), BankPropertyKey::HeaderProtection);
+        assert_eq!(BankPropertyKey::Registry.to_string(), "registry");
+    }
+
+    #[test]
+    fn lookups_return_every_value_in_order() {
+        let properties = properties(&[("prefix", "a\\b\\"), ("author", "x"), ("Author", "y")]);
+
+        assert_eq!(properties.get("AUTHOR"), Some("x"));
+        assert_eq!(properties.get_all(String::from("author")).collect::<Vec<_>>(), ["x", "y"]);
+        assert_eq!(properties.prefix(), Some("a\\b"));
+        assert_eq!(properties.product(), None);
+        assert!(properties.contains(BankPropertyKey::Prefix));
+        assert!(!properties.contains("version"));
+    }
+
+    #[test]
+    fn set_replaces_the_first_pair_and_drops_duplicates() {
+        let mut properties = properties(&[("a", "1"), ("b", "2"), ("A", "3"), ("c", "4")]);
+        properties.set("a", String::from("5"));
+        assert_eq!(pairs(&properties), [("a", "5"), ("b", "2"), ("c", "4")]);
+
+        properties.set_version("6");
+        assert_eq!(pairs(&properties).last(), Some(&("version", "6")));
+    }
+
+    #[test]
+    fn push_keeps_duplicates_and_remove_drops_them() {
+        let mut properties = BankProperties::new();
+        properties.push("a", "1");
+        properties.push(BankPropertyKey::Product, String::from("2"));
+        properties.push(String::from("A"), "3");
+        assert_eq!(properties.len(), 3);
+
+        assert_eq!(properties.remove("a"), ["1", "3"]);
+        assert_eq!(pairs(&properties), [("product", "2")]);
+        assert!(properties.remove("a").is_empty());
+    }
+}
@@ -1,4 +1,13 @@
-pub mod vfs; pub use vfs::*;
+#[cfg(feature = "std")]
+pub mod vfs;
+#[cfg(feature = "std")]
+pub use vfs::*;
 pub mod bank; pub use bank::*;
-pub mod process; pub use process::*;
-pub mod param; pub use param::*;
\ No newline at end of file
+#[cfg(feature = "std")]
+pub mod process;
+#[cfg(feature = "std")]
+pub use process::{lexer::*, process::*};
+#[cfg(feature = "std")]
+pub mod param;
+#[cfg(feature = "std")]
+pub use param::*;
@@ -1,30 +1,36 @@
-use std::{io};
+use std::io;
 use bex::lexer::{Lexer, ScopedToken};
 use bex::read::Analyser;
-use log::error;
 use thiserror::Error;
+use crate::core::profile::{GameProfile, GameTitle};
 
 #[derive(Error, Debug)]
 pub enum ParamLexerError {
     #[error(transparent)]
     IO(#[from] io::Error),
-    #[error("EOF Reached")]
-    EndReached,
     #[error("Missing expected token.")]
     ExpectedToken,
-    #[error("Unknown string escape.")]
-    UnknownEscape
+    #[error("A quoted string was not closed before the end of its line.")]
+    UnterminatedString,
+    #[error("The parentheses of __EXEC or __EVAL were not closed.")]
+    UnterminatedExecute,
+    #[error("{0:?} does not support __EXEC or __EVAL.")]
+    EvaluationUnsupported(GameTitle)
 }
 type LexerResult<T> = Result<T, ParamLexerError>;
 
-impl Default for ParamLexicalScope { fn default() -> Self { Self::Statement } }
-
+/// Where the lexer is, tokens move it along: assignments start an expression, braces in one open
+/// and close arrays and semicolons end it.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
 pub enum ParamLexicalScope {
+    #[default]
     Statement,
     Expression,
-    ArrayExpression
+    /// Inside as many nested arrays.
+    ArrayExpression(usize)
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum ParamToken {
     ClassKeyword, DeleteKeyword, EnumKeyword,
     SemiColon, Colon, Comma, Ampersand, EOF,
@@ -35,22 +41,65 @@ pub enum ParamToken {
     Integer(i32), Float(f32), Double(f64), Unknown(Vec<u8>)
 }
 
+/// Lexes configs the way the game the profile describes reads them.
+pub struct ParamLexer {
+    lexer:   Lexer<u8>,
+    profile: GameProfile
+}
+
+impl ParamLexer {
+    pub fn new(lexer: Lexer<u8>, profile: GameProfile) -> Self {
+        Self { lexer, profile }
+    }
+
+    pub fn profile(&self) -> &GameProfile { &self.profile }
+
+    pub fn next_token(&mut self, scope: &mut ParamLexicalScope) -> LexerResult<ParamToken> {
+        match <ParamToken as ScopedToken<u8>>::next_token(&mut self.lexer, scope)? {
+            ParamToken::ExecuteDirective(_) if !self.profile.evaluation() => {
+                Err(ParamLexerError::EvaluationUnsupported(self.profile.title()))
+            },
+            token => Ok(token)
+        }
+    }
+}
+
 impl ScopedToken<u8> for ParamToken {
     type Scope = ParamLexicalScope;
     type Error = ParamLexerError;
 
     fn next_token(lexer: &mut Lexer<u8>, scope: &mut Self::Scope) -> Result<Self, Self::Error> {
-        match scope {
-            ParamLexicalScope::Statement => next_statement(lexer),
-            ParamLexicalScope::Expression => next_expression(lexer, false),
-            ParamLexicalScope::ArrayExpression => next_expression(lexer, true),
-        }
+        let token = match scope {
+            ParamLexicalScope::Statement => next_statement(lexer)?,
+            ParamLexicalScope::Expression => next_expression(lexer, false)?,
+            ParamLexicalScope::ArrayExpression(_) => next_expression(lexer, true)?,
+        };
+        *scope = match (*scope, &token) {
+            (_, ParamToken::SemiColon) => ParamLexicalScope::Statement,
+            (ParamLexicalScope::Statement, ParamToken::Assign | ParamToken::AddAssign | ParamToken::SubAssign) => ParamLexicalScope::Expression,
+            (ParamLexicalScope::Expression, ParamToken::LeftCurly) => ParamLexicalScope::ArrayExpression(1),
+            (ParamLexicalScope::ArrayExpression(depth), ParamToken::LeftCurly) => ParamLexicalScope::ArrayExpression(depth + 1),
+            (ParamLexicalScope::ArrayExpression(1), ParamToken::RightCurly) => ParamLexicalScope::Expression,
+            (ParamLexicalScope::ArrayExpression(depth), ParamToken::RightCurly) => ParamLexicalScope::ArrayExpression(depth - 1),
+            (scope, _) => scope
+        };
+        Ok(token)
     }
 }
 
+/// Ends unquoted strings and numbers in an expression, the newline ends values missing their
+/// semicolon.
+const EXPRESSION_TERMINATORS: &[u8] = b";\r\n";
+/// Ends unquoted strings and numbers inside an array.
+const ARRAY_TERMINATORS: &[u8] = b",;{}\r\n";
+const EVALUATE_KEYWORD: &[u8] = b"__EVAL";
+
 fn next_statement(lexer: &mut Lexer<u8>) -> LexerResult<ParamToken> {
-    return match take_word(lexer)? {
-        word if word.is_empty() => next_symbol(lexer),
+    if skip_space(lexer)?.is_none() {
+        return Ok(ParamToken::EOF)
+    }
+    match take_word(lexer)? {
+        b"" => next_symbol(lexer),
         word => Ok(match word {
             b"class" =>  ParamToken::ClassKeyword,
             b"enum" => ParamToken::EnumKeyword,
@@ -58,7 +107,7 @@ fn next_statement(lexer: &mut Lexer<u8>) -> LexerResult<ParamToken> {
             b"__EXEC" => ParamToken::ExecuteDirective(read_execute(lexer)?),
             unknown=> ParamToken::Unknown(Vec::from(unknown))
         }.or_else_identifier())
-    };
+    }
 }
 
 fn next_symbol(lexer: &mut Lexer<u8>) -> LexerResult<ParamToken> {
@@ -72,142 +121,274 @@ fn next_symbol(lexer: &mut Lexer<u8>) -> LexerResult<ParamToken> {
         b',' => Ok(ParamToken::Comma),
         b'=' => Ok(ParamToken::Assign),
         b'@' => Ok(ParamToken::Ampersand),
-        b'-' => unknown_unless_next(lexer, b'=', ParamToken::SubAssign),
-        b'+' => unknown_unless_next(lexer, b'=', ParamToken::AddAssign),
+        b'-' => unknown_unless_next(lexer, b'-', b'=', ParamToken::SubAssign),
+        b'+' => unknown_unless_next(lexer, b'+', b'=', ParamToken::AddAssign),
         b'#' => { process_directive(lexer)?; next_statement(lexer)},
         found => Ok(ParamToken::Unknown(vec![found]))
     }
 }
 
 pub fn take_word(lexer: &mut Lexer<u8>) -> LexerResult<&[u8]> {
-    let mut current = skip_space(lexer)?;
-    let start = lexer.pos().clone();
-    while current == b'_' || is_alphanumeric(&current) {
-        current = lexer.get()?;
+    skip_space(lexer)?;
+    let start = lexer.pos();
+    while peek(lexer).is_some_and(|current| current == b'_' || is_alphanumeric(&current)) {
+        lexer.step_forward()?;
     }
-    return Ok(&lexer.contents()[start..lexer.pos()]);
+    Ok(&lexer.contents()[start..lexer.pos()])
 }
 
+/// The token when `current` is followed by `next`, which is consumed, otherwise `current` alone.
 #[inline]
-fn unknown_unless_next(lexer: &mut Lexer<u8>, next: u8, correct: ParamToken) -> LexerResult<ParamToken> {
-    Ok(if lexer.take(&next)? {
-        correct
-    } else {
-        ParamToken::Unknown(vec![lexer.get()?])
-    })
+fn unknown_unless_next(lexer: &mut Lexer<u8>, current: u8, next: u8, correct: ParamToken) -> LexerResult<ParamToken> {
+    if peek(lexer) != Some(next) {
+        return Ok(ParamToken::Unknown(vec![current]))
+    }
+    lexer.step_forward()?;
+    Ok(correct)
 }
 
+/// Captures what is between the parentheses following `__EXEC` or `__EVAL`. Parentheses inside
+/// quoted strings are not counted.
 fn read_execute(lexer: &mut Lexer<u8>) -> LexerResult<Vec<u8>> {
-    todo!()
+    if skip_space(lexer)? != Some(b'(') {
+        return Err(ParamLexerError::ExpectedToken)
+    }
+    lexer.step_forward()?;
+    let start = lexer.pos();
+    let mut depth = 0usize;
+    let mut quote: Option<u8> = None;
+    loop {
+        let current = next_byte(lexer)?.ok_or(ParamLexerError::UnterminatedExecute)?;
+        match (quote, current) {
+            (Some(open), current) if current == open => quote = None,
+            (Some(_), _) => {},
+            (None, b'"' | b'\'') => quote = Some(current),
+            (None, b'(') => depth += 1,
+            (None, b')') if depth == 0 => return Ok(lexer.contents()[start..lexer.pos() - 1].to_vec()),
+            (None, b')') => depth -= 1,
+            _ => {}
+        }
+    }
 }
 
+/// Lexes a value, arrays are lexed one element at a time with `in_array` set until the closing
+/// brace of the outermost array.
 fn next_expression(lexer: &mut Lexer<u8>, in_array: bool) -> LexerResult<ParamToken> {
-    todo!()
+    let current = match skip_space(lexer)? {
+        None => return Ok(ParamToken::EOF),
+        Some(current) => current
+    };
+    let symbol = match current {
+        b'{' => Some(ParamToken::LeftCurly),
+        b'}' if in_array => Some(ParamToken::RightCurly),
+        b',' if in_array => Some(ParamToken::Comma),
+        b';' => Some(ParamToken::SemiColon),
+        _ => None
+    };
+    if let Some(symbol) = symbol {
+        lexer.step_forward()?;
+        return Ok(symbol)
+    }
+    if current == b'#' {
+        lexer.step_forward()?;
+        process_directive(lexer)?;
+        return next_expression(lexer, in_array)
+    }
+    if take_evaluate(lexer)? {
+        return Ok(ParamToken::ExecuteDirective(read_execute(lexer)?))
+    }
+
+    let terminators = if in_array { ARRAY_TERMINATORS } else { EXPRESSION_TERMINATORS };
+    Ok(match take_string(lexer, terminators)? {
+        ParamToken::LiteralString { double_quoted: false, data } => match parse_number(&data) {
+            Some(number) => number,
+            None => ParamToken::LiteralString { double_quoted: false, data }
+        },
+        token => token
+    })
+}
+
+/// Consumes `__EVAL` when it is followed by its parentheses, values merely starting with it are
+/// left alone.
+fn take_evaluate(lexer: &mut Lexer<u8>) -> LexerResult<bool> {
+    if !lexer.contents()[lexer.pos()..].starts_with(EVALUATE_KEYWORD) {
+        return Ok(false)
+    }
+    let start = lexer.pos();
+    for _ in EVALUATE_KEYWORD {
+        lexer.step_forward()?;
+    }
+    match skip_space(lexer)? {
+        Some(b'(') => Ok(true),
+        _ => {
+            rewind(lexer, start)?;
+            Ok(false)
+        }
+    }
+}
+
+/// Reads a decimal or hexadecimal integer, or a float with an optional exponent. Anything else,
+/// including numbers followed by other text, is left to be a string.
+fn parse_number(text: &[u8]) -> Option<ParamToken> {
+    let text = std::str::from_utf8(text).ok()?;
+    let (negative, unsigned) = match text.as_bytes().first()? {
+        b'-' => (true, &text[1..]),
+        b'+' => (false, &text[1..]),
+        _ => (false, text)
+    };
+
+    if let Some(hex) = unsigned.strip_prefix("0x").or_else(|| unsigned.strip_prefix("0X")) {
+        if hex.is_empty() || !hex.bytes().all(|c| c.is_ascii_hexdigit()) {
+            return None
+        }
+        let value = u32::from_str_radix(hex, 16).ok()? as i32;
+        return Some(ParamToken::Integer(if negative { value.wrapping_neg() } else { value }))
+    }
+    if !is_decimal(unsigned.as_bytes()) {
+        return None
+    }
+    if unsigned.bytes().all(|c| c.is_ascii_digit()) {
+        if let Ok(value) = text.parse::<i32>() {
+            return Some(ParamToken::Integer(value))
+        }
+    }
+    let value = text.parse::<f64>().ok()?;
+    Some(match value as f32 {
+        float if float.is_finite() => ParamToken::Float(float),
+        _ => ParamToken::Double(value)
+    })
+}
+
+/// Digits with an optional fraction and exponent, as in `12`, `1.5`, `.5` or `2e-3`.
+fn is_decimal(text: &[u8]) -> bool {
+    let (mantissa, exponent) = match text.iter().position(|c| matches!(c, b'e' | b'E')) {
+        Some(index) => (&text[..index], Some(&text[index + 1..])),
+        None => (text, None)
+    };
+    let mut parts = mantissa.splitn(2, |c| *c == b'.');
+    let whole = parts.next().unwrap_or_default();
+    let fraction = parts.next().unwrap_or_default();
+    let digits = |part: &[u8]| part.iter().all(u8::is_ascii_digit);
+    if whole.len() + fraction.len() == 0 || !digits(whole) || !digits(fraction) {
+        return false
+    }
+    match exponent {
+        None => true,
+        Some(exponent) => {
+            let exponent = exponent.strip_prefix(b"-").or_else(|| exponent.strip_prefix(b"+")).unwrap_or(exponent);
+            !exponent.is_empty() && digits(exponent)
+        }
+    }
 }
 
 fn process_line_directive(lexer: &mut Lexer<u8>) -> LexerResult<()> {
-    todo!()
+    while let Some(current) = next_byte(lexer)? {
+        if current == b'\n' { break }
+    }
+    Ok(())
 }
 
 fn process_directive(lexer: &mut Lexer<u8>) -> LexerResult<()> {
-    Ok(match take_word(lexer)? {
-        b"line" => process_line_directive(lexer)?,
-        _ => return Err(ParamLexerError::ExpectedToken)
-    })
+    match take_word(lexer)? {
+        b"line" => process_line_directive(lexer),
+        _ => Err(ParamLexerError::ExpectedToken)
+    }
 }
 
+/// Reads a quoted string, or an unquoted one running until one of the terminators or the end of
+/// the input with its trailing whitespace removed.
 pub fn take_string(lexer: &mut Lexer<u8>, terminators: &[u8]) -> LexerResult<ParamToken> {
-    let mut current = skip_space(lexer)?;
-    let mut data: Vec<u8> = vec![];
-    let double_quoted = match current {
-        b'"' => {
-            current = lexer.get()?;
-            loop {
-                match current {
-                    b'"' => if try_match_quoted_string_end(lexer, &mut current)? { break },
-                    c if c == b'\n' ||c == b'\r' => return Err(ParamLexerError::ExpectedToken),
-                    _ => {}
-                }
-                data.push(current)
-            }
-            true
-        },
-        _ => {
-            while !terminators.contains(&current) {
-                if current == b'\n' || current == b'\r' {
-                    if try_match_terminated_string_end(lexer, &mut current, terminators)? { break }
-                }
-
-                data.push(current)
-            }
-            while !data.is_empty() && is_space(data.last().unwrap()) { data.pop(); }
+    if skip_space(lexer)? == Some(b'"') {
+        lexer.step_forward()?;
+        return Ok(ParamToken::LiteralString { double_quoted: true, data: take_quoted(lexer)? })
+    }
 
-            lexer.step_back()?;
-            false
-        }
-    };
-    return Ok(ParamToken::LiteralString {
-        double_quoted,
+    let start = lexer.pos();
+    while peek(lexer).is_some_and(|current| !terminators.contains(&current)) {
+        lexer.step_forward()?;
+    }
+    let mut data = lexer.contents()[start..lexer.pos()].to_vec();
+    while data.last().is_some_and(is_space) { data.pop(); }
+    Ok(ParamToken::LiteralString {
+        double_quoted: false,
         data,
     })
 }
-fn skip_space(lexer: &mut Lexer<u8>) -> LexerResult<u8> {
-    let mut current = *lexer.peek()?;
-    while is_space(&current) {
-        current = lexer.get()?;
+
+/// Reads the rest of a quoted string after its opening quote. A doubled quote stands for one
+/// quote, and `"one" \n "two"` joins both strings with a line break.
+fn take_quoted(lexer: &mut Lexer<u8>) -> LexerResult<Vec<u8>> {
+    let mut data: Vec<u8> = vec![];
+    loop {
+        match next_byte(lexer)? {
+            None | Some(b'\n' | b'\r') => return Err(ParamLexerError::UnterminatedString),
+            Some(b'"') if peek(lexer) == Some(b'"') => {
+                lexer.step_forward()?;
+                data.push(b'"')
+            },
+            Some(b'"') => match take_joined_line(lexer)? {
+                true => data.push(b'\n'),
+                false => return Ok(data)
+            },
+            Some(current) => data.push(current)
+        }
     }
-    return Ok(current);
 }
 
-
-fn try_match_quoted_string_end(lexer: &mut Lexer<u8>, current: &mut u8) -> LexerResult<bool> {
-    *current = lexer.get()?;
-    match current {
-        b'"' => Ok(false),
-        _ => {
-            *current = skip_space(lexer)?;
-            if !lexer.take(&b'\\')? {
-                lexer.step_back()?;
-                *current = *lexer.peek()?;
-                return Ok(true)
-            }
-            if !lexer.take(&b'n')? {
-                return Err(ParamLexerError::UnknownEscape)
-            }
-            *current = skip_space(lexer)?;
-            if !lexer.take(&b'"')? {
-                return Err(ParamLexerError::UnknownEscape)
-            }
-            *current = b'\n';
-            Ok(false)
+/// Consumes the `\n "` between two joined quoted strings, if it follows.
+fn take_joined_line(lexer: &mut Lexer<u8>) -> LexerResult<bool> {
+    let start = lexer.pos();
+    for expected in [b'\\', b'n', b'"'] {
+        let found = match expected {
+            b'n' => peek(lexer),
+            _ => skip_space(lexer)?
+        };
+        if found != Some(expected) {
+            rewind(lexer, start)?;
+            return Ok(false)
         }
+        lexer.step_forward()?;
     }
+    Ok(true)
 }
 
-fn try_match_terminated_string_end(lexer: &mut Lexer<u8>, current: &mut u8, terminators: &[u8]) -> LexerResult<bool> {
-    loop {
-        *current = skip_space(lexer)?;
-        if *current != b'#' {
-            return Ok(false)
+/// Skips whitespace, returning the byte after it without consuming it.
+fn skip_space(lexer: &mut Lexer<u8>) -> LexerResult<Option<u8>> {
+    while let Some(current) = peek(lexer) {
+        if !is_space(&current) {
+            return Ok(Some(current))
         }
-        process_directive(lexer)?;
-        *current = skip_space(lexer)?;
+        lexer.step_forward()?;
+    }
+    Ok(None)
+}
 
-        if !terminators.contains(current) {
-            return Err(ParamLexerError::ExpectedToken)
+fn peek(lexer: &Lexer<u8>) -> Option<u8> { lexer.contents().get(lexer.pos()).copied() }
+
+fn next_byte(lexer: &mut Lexer<u8>) -> LexerResult<Option<u8>> {
+    match peek(lexer) {
+        None => Ok(None),
+        Some(current) => {
+            lexer.step_forward()?;
+            Ok(Some(current))
         }
+    }
+}
+
+fn rewind(lexer: &mut Lexer<u8>, position: usize) -> LexerResult<()> {
+    while lexer.pos() > position {
         lexer.step_back()?;
-        return Ok(true)
     }
+    Ok(())
 }
 
 fn is_space(c: &u8) -> bool { matches!(c, b' ' | b'\t' | b'\n' | b'\r' | 0x0b | 0x0c) }
 
 fn is_alphanumeric(c: &u8) -> bool { is_alpha(c) || is_numeric(c) }
 
-fn is_numeric(c: &u8) -> bool { matches!(c, b'0'..=b'9') }
+fn is_numeric(c: &u8) -> bool { c.is_ascii_digit() }
 
-fn is_alpha(c: &u8) -> bool { matches!(c, b'a'..=b'z' | b'A'..=b'Z') }
+fn is_alpha(c: &u8) -> bool { c.is_ascii_alphabetic() }
 
 impl ParamToken {
 
@@ -220,8 +401,146 @@ impl ParamToken {
 
     pub fn or_else_identifier(self) -> Self {
         match self {
-            ParamToken::Unknown(it) if it.len() > 0 && !is_numeric(it.first().unwrap())  => ParamToken::Identifier(it),
+            ParamToken::Unknown(it) if it.first().is_some_and(|first| !is_numeric(first)) => ParamToken::Identifier(it),
             _ => self,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::profile::GameTitle;
+    use ParamToken::*;
+
+    fn lex_with(text: &str, profile: GameProfile) -> LexerResult<Vec<ParamToken>> {
+        let mut lexer = ParamLexer::new(Lexer::new(text.as_bytes().to_vec()), profile);
+        let mut scope = ParamLexicalScope::default();
+        let mut tokens = vec![];
+        loop {
+            match lexer.next_token(&mut scope)? {
+                EOF => return Ok(tokens),
+                token => tokens.push(token)
+            }
+        }
+    }
+
+    fn lex(text: &str) -> Vec<ParamToken> { lex_with(text, GameProfile::default()).unwrap() }
+
+    fn id(name: &str) -> ParamToken { Identifier(name.as_bytes().to_vec()) }
+
+    fn quoted(data: &str) -> ParamToken { LiteralString { double_quoted: true, data: data.as_bytes().to_vec() } }
+
+    fn unquoted(data: &str) -> ParamToken { LiteralString { double_quoted: false, data: data.as_bytes().to_vec() } }
+
+    /// The value assigned by `x = ...;`.
+    fn value(text: &str) -> ParamToken {
+        match lex(&format!("x = {text};")).as_slice() {
+            [_, Assign, value, SemiColon] => value.clone(),
+            tokens => panic!("{text} was lexed as {tokens:?}")
+        }
+    }
+
+    #[test]
+    fn statements() {
+        assert_eq!(lex("class A: B { delete C; enum { }; };"), [
+            ClassKeyword, id("A"), Colon, id("B"), LeftCurly,
+            DeleteKeyword, id("C"), SemiColon,
+            EnumKeyword, LeftCurly, RightCurly, SemiColon,
+            RightCurly, SemiColon
+        ]);
+        assert_eq!(lex("x[] += {}; y -= {}; @ + - 1a"), [
+            id("x"), LeftSquare, RightSquare, AddAssign, LeftCurly, RightCurly, SemiColon,
+            id("y"), SubAssign, LeftCurly, RightCurly, SemiColon,
+            Ampersand, Unknown(b"+".to_vec()), Unknown(b"-".to_vec()), Unknown(b"1a".to_vec())
+        ]);
+        assert_eq!(lex(""), []);
+    }
+
+    #[test]
+    fn line_directives_are_skipped() {
+        assert_eq!(lex("#line 10 \"a.hpp\"\nclass A;"), [ClassKeyword, id("A"), SemiColon]);
+    }
+
+    #[test]
+    fn arrays_are_tracked_until_their_closing_brace() {
+        assert_eq!(lex("x = {1,2}; y = 3;"), [
+            id("x"), Assign, LeftCurly, Integer(1), Comma, Integer(2), RightCurly, SemiColon,
+            id("y"), Assign, Integer(3), SemiColon
+        ]);
+        assert_eq!(lex("x[] = {{1}, {\"a\", b c}, {}};"), [
+            id("x"), LeftSquare, RightSquare, Assign,
+            LeftCurly,
+            LeftCurly, Integer(1), RightCurly, Comma,
+            LeftCurly, quoted("a"), Comma, unquoted("b c"), RightCurly, Comma,
+            LeftCurly, RightCurly,
+            RightCurly, SemiColon
+        ]);
+    }
+
+    #[test]
+    fn scopes_follow_the_tokens() {
+        let mut lexer = ParamLexer::new(Lexer::new(b"x = {{1}, 2}; class".to_vec()), GameProfile::default());
+        let mut scope = ParamLexicalScope::default();
+        let mut scopes = vec![];
+        while lexer.next_token(&mut scope).unwrap() != EOF {
+            scopes.push(scope);
+        }
+        use ParamLexicalScope::*;
+        assert_eq!(scopes, [
+            Statement, Expression,
+            ArrayExpression(1), ArrayExpression(2), ArrayExpression(2), ArrayExpression(1),
+            ArrayExpression(1), ArrayExpression(1), Expression, Statement,
+            Statement
+        ]);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(value("12"), Integer(12));
+        assert_eq!(value("-5"), Integer(-5));
+        assert_eq!(value("0x1F"), Integer(0x1f));
+        assert_eq!(value("-0x10"), Integer(-16));
+        assert_eq!(value("0xFFFFFFFF"), Integer(-1));
+        assert_eq!(value("1.5"), Float(1.5));
+        assert_eq!(value(".5"), Float(0.5));
+        assert_eq!(value("2e-3"), Float(2e-3));
+        assert_eq!(value("1E+2"), Float(100.0));
+        assert_eq!(value("3000000000"), Float(3e9));
+        assert_eq!(value("1e300"), Double(1e300));
+        for text in ["12abc", "0x", "0xG", "1e", "1.2.3", "-", "e5"] {
+            assert_eq!(value(text), unquoted(text));
+        }
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(value("\"say \"\"hi\"\"\""), quoted("say \"hi\""));
+        assert_eq!(value("\"\""), quoted(""));
+        assert_eq!(value("\"one\" \\n \"two\""), quoted("one\ntwo"));
+        assert_eq!(lex("x = \"one\" \\n;"), [id("x"), Assign, quoted("one"), unquoted("\\n"), SemiColon]);
+        assert_eq!(value("some text  "), unquoted("some text"));
+    }
+
+    #[test]
+    fn evaluations_are_captured() {
+        assert_eq!(lex("__EXEC(a = (1 + 2); b = \")\")"), [ExecuteDirective(b"a = (1 + 2); b = \")\"".to_vec())]);
+        assert_eq!(value("__EVAL (1 + (2))"), ExecuteDirective(b"1 + (2)".to_vec()));
+        assert_eq!(value("__EVALUATED"), unquoted("__EVALUATED"));
+    }
+
+    #[test]
+    fn errors() {
+        let profile = GameProfile::default();
+        assert!(matches!(lex_with("#include \"a.hpp\"", profile.clone()), Err(ParamLexerError::ExpectedToken)));
+        assert!(matches!(lex_with("__EXEC a", profile.clone()), Err(ParamLexerError::ExpectedToken)));
+        assert!(matches!(lex_with("x = \"a\nb\";", profile.clone()), Err(ParamLexerError::UnterminatedString)));
+        assert!(matches!(lex_with("x = \"a", profile.clone()), Err(ParamLexerError::UnterminatedString)));
+        assert!(matches!(lex_with("__EXEC(a = (1)", profile.clone()), Err(ParamLexerError::UnterminatedExecute)));
+        assert!(matches!(lex_with("x = __EVAL(\")\";", profile), Err(ParamLexerError::UnterminatedExecute)));
+
+        let flashpoint = GameProfile::new(GameTitle::Flashpoint);
+        assert!(matches!(lex_with("__EXEC(a)", flashpoint.clone()), Err(ParamLexerError::EvaluationUnsupported(GameTitle::Flashpoint))));
+        assert!(matches!(lex_with("x = __EVAL(1);", flashpoint), Err(ParamLexerError::EvaluationUnsupported(GameTitle::Flashpoint))));
+    }
+}
@@ -1,7 +1,6 @@
 pub mod lexer;
 
 use std::collections::HashMap;
-use std::hash::Hash;
 pub use lexer::*;
 pub mod parser; pub use parser::*;
 
@@ -43,6 +42,14 @@ pub enum ParamLiteral {
     Long(i64)
 }
 
+impl ParamContext for ParamFile {
+    fn name(&self) -> &Vec<u8> { &self.name }
+
+    fn nodes(&self) -> &NodeSet { &self.nodes }
+
+    fn mut_nodes(&mut self) -> &mut NodeSet { &mut self.nodes }
+}
+
 impl ParamContext for ParamClass {
     fn name(&self) -> &Vec<u8> { &self.name }
 
@@ -51,7 +58,7 @@ impl ParamContext for ParamClass {
     fn mut_nodes(&mut self) -> &mut NodeSet { &mut self.nodes }
 }
 
-trait ParamContext {
+pub trait ParamContext {
     fn name(&self) -> &Vec<u8>;
 
     fn nodes(&self) -> &NodeSet;
@@ -75,6 +82,6 @@ trait ParamContext {
 
 
 impl ParamFile {
-
-
+    /// The values of the enums declared in the file.
+    pub fn internals(&self) -> &HashMap<Vec<u8>, i32> { &self.internals }
 }
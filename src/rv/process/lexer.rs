@@ -1,5 +1,5 @@
 use std::io;
-use bex::{Analyser, Lexer, ScopedToken, Token};
+use bex::{Analyser, Lexer, ScopedToken};
 use thiserror::Error;
 
 type LexerResult<T> = Result<T, ProcError>;
@@ -78,7 +78,7 @@ fn read_macro_name(
     if !valid_id_char(next, true) {
         return Err(ProcError::ExpectedId)
     }
-    Ok(get_name(lexer, &mut scope.line_number, Some(next), 128)?)
+    get_name(lexer, &mut scope.line_number, Some(next), 128)
 }
 
 impl ScopedToken<u8> for ProcToken {
@@ -185,8 +185,8 @@ fn skip_space(lexer: &mut Lexer<u8>) -> LexerResult<bool> {
 
 fn read_if(
     lexer: &mut Lexer<u8>,
-    scope: &mut ProcScope,
-    negated: bool
+    _scope: &mut ProcScope,
+    _negated: bool
 ) -> LexerResult<ProcToken> {
     if !skip_space(lexer)? {
         return Err(ProcError::MissingSpace)
@@ -1,2 +1,3 @@
 pub mod lexer;
+#[allow(clippy::module_inception)]
 pub mod process;
\ No newline at end of file
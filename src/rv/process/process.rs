@@ -1,47 +1,65 @@
 use std::io;
 use bex::{Lexer, PreProcess};
 use thiserror::Error;
+use crate::core::profile::{Directive, GameProfile, GameTitle};
 use crate::process::lexer::{ProcMacro, ProcToken, ProcError, ProcInclude};
 
 type PreprocessorResult<T> = Result<T, PreprocessorError>;
 
+#[derive(Default)]
 pub struct RvPreprocessor {
-
+    profile: GameProfile
 }
 #[derive(Error, Debug)]
 pub enum PreprocessorError {
     #[error(transparent)]
     IO(#[from] io::Error),
     #[error(transparent)]
-    Lexer(#[from] ProcError)
+    Lexer(#[from] ProcError),
+    #[error("Preprocessor Error: {0} is not supported by {1:?}.")]
+    UnsupportedDirective(Directive, GameTitle)
 }
 
 impl RvPreprocessor {
+    /// A preprocessor accepting the directives of the game the profile describes.
+    pub fn new(profile: GameProfile) -> Self {
+        Self { profile }
+    }
+
+    pub fn profile(&self) -> &GameProfile { &self.profile }
+
+    fn require(&self, directive: Directive) -> PreprocessorResult<()> {
+        match self.profile.supports(directive) {
+            true => Ok(()),
+            false => Err(PreprocessorError::UnsupportedDirective(directive, self.profile.title()))
+        }
+    }
+
     fn try_expand_macro(
         &mut self,
-        macro_name:  &Vec<u8>,
-        token_stack: &mut Vec<ProcToken>
+        _macro_name:  &[u8],
+        _token_stack: &mut Vec<ProcToken>
     ) -> PreprocessorResult<Option<Vec<u8>>> {
         todo!()
     }
 
     fn undefine(
         &mut self,
-        macro_name: &Vec<u8>
+        _macro_name: &[u8]
     ) -> PreprocessorResult<Option<ProcMacro>> {
         todo!()
     }
 
     fn defined(
         &mut self,
-        macro_name: &Vec<u8>
+        _macro_name: &[u8]
     ) -> PreprocessorResult<bool> {
         todo!()
     }
 
     fn define(
         &mut self,
-        mac: ProcMacro
+        _mac: ProcMacro
     ) -> PreprocessorResult<()> {
         todo!()
     }
@@ -50,9 +68,9 @@ impl RvPreprocessor {
         self.preprocess_file(include.path)
     }
 
-    fn preprocess_file(&mut self, path: Vec<u8>) -> PreprocessorResult<Vec<u8>> {
-        let file_contents: Vec<u8> = todo!();
-        return PreProcess::preprocess(self, Lexer::new(file_contents))
+    fn preprocess_file(&mut self, _path: Vec<u8>) -> PreprocessorResult<Vec<u8>> {
+        //The contents are read through the virtual filesystem, which does not exist yet.
+        todo!()
     }
 }
 impl PreProcess<u8> for RvPreprocessor {
@@ -67,32 +85,44 @@ impl PreProcess<u8> for RvPreprocessor {
                 None => break,
                 Some(it) => it
             } {
-                ProcToken::Include(include) => document.extend(self.process_include(include)?),
-                ProcToken::Define(it) => self.define(it)?,
-                ProcToken::IfBlock(block) => if block.negated ^ self.defined(&block.target)? {
-                    document.extend(block.if_section)
-                } else if let Some(text) = block.else_section {
-                    document.extend(text)
+                ProcToken::Include(include) => {
+                    self.require(Directive::Include)?;
+                    document.extend(self.process_include(include)?)
+                },
+                ProcToken::Define(it) => {
+                    self.require(Directive::Define)?;
+                    self.define(it)?
+                },
+                ProcToken::IfBlock(block) => {
+                    self.require(if block.negated { Directive::IfNDef } else { Directive::IfDef })?;
+                    if block.else_section.is_some() {
+                        self.require(Directive::Else)?;
+                    }
+                    self.require(Directive::EndIf)?;
+                    if block.negated ^ self.defined(&block.target)? {
+                        document.extend(block.if_section)
+                    } else if let Some(text) = block.else_section {
+                        document.extend(text)
+                    }
+                },
+                ProcToken::Undefine(it) => {
+                    self.require(Directive::Undef)?;
+                    _ = self.undefine(&it)?
                 },
-                ProcToken::Undefine(it) => _ = self.undefine(&it)?,
                 ProcToken::Identifier(id) => match self.try_expand_macro(&id, &mut contents)? {
                     None => { document.extend(id); }
                     Some(expanded) => document.extend(expanded)
                 }
                 ProcToken::Unknown(text) => document.extend(text),
                 ProcToken::DoubleHash => match contents.pop() {
-                    None => document.extend_from_slice(&[b'#', b'#']),
+                    None => document.extend_from_slice(b"##"),
                     Some(tok) => {
-                        match &tok {
-                            ProcToken::Identifier(text) => {
-                                match self.try_expand_macro(text, &mut contents)? {
-                                    None => {}
-                                    Some(expanded) => document.extend(expanded)
-                                }
+                        if let ProcToken::Identifier(text) = &tok {
+                            if let Some(expanded) = self.try_expand_macro(text, &mut contents)? {
+                                document.extend(expanded)
                             }
-                            _ => {}
                         }
-                        document.extend_from_slice(&[b'#', b'#']);
+                        document.extend_from_slice(b"##");
                         contents.push(tok)
                     }
                 }
@@ -106,6 +136,6 @@ impl PreProcess<u8> for RvPreprocessor {
             }
         }
 
-        return Ok(document)
+        Ok(document)
     }
 }
\ No newline at end of file
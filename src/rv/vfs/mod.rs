@@ -1,2 +1,2 @@
-struct RvFilesystem {
+pub struct RvFilesystem {
   }
\ No newline at end of file